@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::fs::File;
+
+use byteorder;
+use byteorder::LittleEndian;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+
+use includes::MediaKind;
+use includes::descriptor::Descriptor;
+use includes::error::SriError;
+use includes::fingerprint::Fingerprint;
+use includes::metadata::Metadata;
+use includes::context_types::AssociationRecord;
+use includes::context_types::LoadedAssociation;
+use includes::util::fnv1a;
+use includes::util::warn;
+
+/// First bytes of every binary index file
+pub static MAGIC: &'static [u8] = b"SRIINDEX";
+
+/// Version written by this build, files with a greater version are refused
+pub const FORMAT_VERSION: u32 = 1;
+
+const ASSOCIATIONS_SECTION: u32 = 0;
+const BASE_SECTION: u32 = 1;
+const METADATA_SECTION: u32 = 2;
+
+
+/// Builds the bytes of a record, integers being written in little endian whatever the platform
+pub struct RecordWriter {
+	bytes: Vec<u8>,
+}
+
+impl RecordWriter {
+	pub fn new() -> RecordWriter { RecordWriter { bytes: Vec::new() } }
+
+	// Writing to a vector never fails
+	pub fn write_u32(&mut self, value: u32) { self.bytes.write_u32::<LittleEndian>(value).unwrap() }
+
+	pub fn write_i32(&mut self, value: i32) { self.bytes.write_i32::<LittleEndian>(value).unwrap() }
+
+	pub fn write_u64(&mut self, value: u64) { self.bytes.write_u64::<LittleEndian>(value).unwrap() }
+
+	/// Length prefixed utf-8 string
+	pub fn write_str(&mut self, value: &str) {
+		self.write_u32(value.len() as u32);
+		self.bytes.extend_from_slice(value.as_bytes());
+	}
+
+	pub fn into_bytes(self) -> Vec<u8> { self.bytes }
+}
+
+fn record_error(error: byteorder::Error) -> SriError { SriError::parse(format!("Unreadable record ({})", error)) }
+
+/// Reads back the fields of a record written by a `RecordWriter`
+pub struct RecordReader<'a> {
+	bytes: &'a [u8],
+}
+
+impl<'a> RecordReader<'a> {
+	pub fn new(bytes: &'a [u8]) -> RecordReader<'a> { RecordReader { bytes: bytes } }
+
+	fn need(&self, amount: usize) -> Result<(), SriError> {
+		match self.bytes.len() < amount {
+			true => Err(SriError::parse(format!("Truncated record, {} bytes needed but {} left", amount, self.bytes.len()))),
+			false => Ok(()),
+		}
+	}
+
+	fn take(&mut self, amount: usize) -> Result<&'a [u8], SriError> {
+		try!(self.need(amount));
+		let (taken, rest) = self.bytes.split_at(amount);
+		self.bytes = rest;
+		Ok(taken)
+	}
+
+	pub fn read_u32(&mut self) -> Result<u32, SriError> {
+		try!(self.need(4));
+		self.bytes.read_u32::<LittleEndian>().map_err(record_error)
+	}
+
+	pub fn read_i32(&mut self) -> Result<i32, SriError> {
+		try!(self.need(4));
+		self.bytes.read_i32::<LittleEndian>().map_err(record_error)
+	}
+
+	pub fn read_u64(&mut self) -> Result<u64, SriError> {
+		try!(self.need(8));
+		self.bytes.read_u64::<LittleEndian>().map_err(record_error)
+	}
+
+	/// Number of elements written before them, refused when the rest of the record can't hold that many elements of `element_size` bytes at least
+	///
+	/// A corrupted count then never makes the reader reserve more memory than the record itself takes.
+	pub fn read_count(&mut self, element_size: usize) -> Result<usize, SriError> {
+		let count = try!(self.read_u32()) as usize;
+		if count.saturating_mul(element_size) > self.bytes.len() {
+			return Err(SriError::parse(format!("Record announces {} elements but only {} bytes are left", count, self.bytes.len())));
+		}
+		Ok(count)
+	}
+
+	pub fn read_string(&mut self) -> Result<String, SriError> {
+		let length = try!(self.read_u32()) as usize;
+		match String::from_utf8(try!(self.take(length)).to_vec()) {
+			Ok(value) => Ok(value),
+			Err(e) => Err(SriError::parse(format!("Invalid string in record ({})", e))),
+		}
+	}
+
+	/// True once every byte of the record was read
+	pub fn is_empty(&self) -> bool { self.bytes.is_empty() }
+}
+
+
+/// Group of length prefixed records, stored with its own checksum
+pub struct Section {
+	tag: u32,
+	record_count: u32,
+	payload: Vec<u8>,
+}
+
+impl Section {
+	fn new(tag: u32) -> Section { Section { tag: tag, record_count: 0, payload: Vec::new() } }
+
+	fn push_record(&mut self, record: RecordWriter) {
+		let bytes = record.into_bytes();
+		let mut length = RecordWriter::new();
+		length.write_u32(bytes.len() as u32);
+		self.payload.extend_from_slice(&length.into_bytes());
+		self.payload.extend_from_slice(&bytes);
+		self.record_count += 1;
+	}
+
+	/// Splits the payload back into its records
+	fn records(&self) -> Result<Vec<RecordReader>, SriError> {
+		let mut records = Vec::new();
+		let mut payload = RecordReader::new(&self.payload);
+		while !payload.is_empty() {
+			let length = try!(payload.read_u32()) as usize;
+			records.push(RecordReader::new(try!(payload.take(length))));
+		}
+		if records.len() != self.record_count as usize {
+			return Err(SriError::parse(format!("Section announces {} records but holds {}", self.record_count, records.len())));
+		}
+		Ok(records)
+	}
+
+	/// Media kind and content of a section written by this version, `None` for the sections it does not know
+	fn content(&self) -> Option<(MediaKind, u32)> {
+		let (kind_code, content) = match self.tag {
+			tag if tag < 6 => (tag / 2, tag % 2),
+			tag if tag < 9 => (tag - 6, METADATA_SECTION),
+			_ => return None,
+		};
+		let kind = match kind_code {
+			0 => MediaKind::Image,
+			1 => MediaKind::Sound,
+			_ => MediaKind::Text,
+		};
+		Some((kind, content))
+	}
+}
+
+/// Tag of a section, metadata sections coming after the associations and base ones of the first version
+fn section_tag(kind: MediaKind, content: u32) -> u32 {
+	let kind_code = match kind {
+		MediaKind::Image => 0,
+		MediaKind::Sound => 1,
+		MediaKind::Text => 2,
+	};
+	match content {
+		METADATA_SECTION => 6 + kind_code,
+		_ => kind_code * 2 + content,
+	}
+}
+
+/// Section holding the `id => filename` associations of a media kind
+///
+/// The fingerprint of the file follows the filename when it is known, records written before fingerprints stopping at the filename.
+pub fn associations_section(kind: MediaKind, associations: &[AssociationRecord]) -> Section {
+	let mut section = Section::new(section_tag(kind, ASSOCIATIONS_SECTION));
+	for &(id, filename, fingerprint, _) in associations {
+		let mut record = RecordWriter::new();
+		record.write_str(id);
+		record.write_str(filename);
+		if let Some(fingerprint) = fingerprint {
+			record.write_u64(fingerprint.get_size());
+			record.write_u64(fingerprint.get_mtime());
+			record.write_u64(fingerprint.get_hash());
+		}
+		section.push_record(record);
+	}
+	section
+}
+
+/// Section holding the metadata of the documents of a media kind, as an id followed by its `key, value` pairs
+pub fn metadata_section(kind: MediaKind, associations: &[AssociationRecord]) -> Section {
+	let mut section = Section::new(section_tag(kind, METADATA_SECTION));
+	for &(id, _, _, metadata) in associations {
+		if let Some(metadata) = metadata {
+			let mut record = RecordWriter::new();
+			record.write_str(id);
+			record.write_u32(metadata.get_fields().len() as u32);
+			for (key, value) in metadata.get_fields() {
+				record.write_str(key);
+				record.write_str(value);
+			}
+			section.push_record(record);
+		}
+	}
+	section
+}
+
+/// Section holding the descriptors of a base
+pub fn base_section<D: Descriptor>(base: &[D]) -> Section {
+	let mut section = Section::new(section_tag(D::media_kind(), BASE_SECTION));
+	for descriptor in base {
+		let mut record = RecordWriter::new();
+		descriptor.to_record(&mut record);
+		section.push_record(record);
+	}
+	section
+}
+
+
+/// Content of a binary index file
+pub struct BinaryIndex {
+	sections: Vec<Section>,
+}
+
+impl BinaryIndex {
+	fn find_section(&self, kind: MediaKind, content: u32) -> Option<&Section> { self.sections.iter().find(|section| section.content() == Some((kind, content))) }
+
+	/// Associations of a media kind with the metadata of their documents, empty when the index has none
+	pub fn get_associations(&self, kind: MediaKind) -> Result<Vec<LoadedAssociation>, SriError> {
+		let mut metadata = try!(self.get_metadata(kind));
+		let mut associations = Vec::new();
+		if let Some(section) = self.find_section(kind, ASSOCIATIONS_SECTION) {
+			for mut record in try!(section.records()) {
+				let id = try!(record.read_string());
+				let filename = try!(record.read_string());
+				let fingerprint = match record.is_empty() {
+					true => None,
+					false => Some(Fingerprint::new(try!(record.read_u64()), try!(record.read_u64()), try!(record.read_u64()))),
+				};
+				let document_metadata = metadata.remove(&id).unwrap_or_default();
+				associations.push((id, filename, fingerprint, document_metadata));
+			}
+		}
+		Ok(associations)
+	}
+
+	fn get_metadata(&self, kind: MediaKind) -> Result<HashMap<String, Metadata>, SriError> {
+		let mut metadata = HashMap::new();
+		if let Some(section) = self.find_section(kind, METADATA_SECTION) {
+			for mut record in try!(section.records()) {
+				let id = try!(record.read_string());
+				let mut document_metadata = Metadata::new();
+				// Each field holds the lengths of its key and of its value at least
+				for _ in 0..try!(record.read_count(8)) {
+					let key = try!(record.read_string());
+					document_metadata.set(&key, try!(record.read_string()));
+				}
+				metadata.insert(id, document_metadata);
+			}
+		}
+		Ok(metadata)
+	}
+
+	/// Descriptors of a base, empty when the index has none
+	pub fn get_base<D: Descriptor>(&self) -> Result<Vec<D>, SriError> {
+		let mut base = Vec::new();
+		if let Some(section) = self.find_section(D::media_kind(), BASE_SECTION) {
+			for mut record in try!(section.records()) {
+				let descriptor = try!(D::from_record(&mut record));
+				if !record.is_empty() {
+					return Err(SriError::parse(format!("Unexpected bytes after the record of {}", descriptor.get_id())));
+				}
+				base.push(descriptor);
+			}
+		}
+		Ok(base)
+	}
+}
+
+
+/// Writes the magic number, the format version then every section with its checksum
+pub fn write_index(path: &str, sections: &[Section]) -> Result<(), SriError> {
+	let mut index_writer = BufWriter::new(try!(File::create(path).map_err(|e| SriError::io(path, e))));
+	let mut header = RecordWriter::new();
+	header.write_u32(FORMAT_VERSION);
+	header.write_u32(sections.len() as u32);
+	for section in sections {
+		header.write_u32(section.tag);
+		header.write_u32(section.record_count);
+		header.write_u64(section.payload.len() as u64);
+		header.write_u64(fnv1a(&section.payload));
+	}
+	try!(index_writer.write_all(MAGIC).map_err(|e| SriError::io(path, e)));
+	try!(index_writer.write_all(&header.into_bytes()).map_err(|e| SriError::io(path, e)));
+	for section in sections {
+		try!(index_writer.write_all(&section.payload).map_err(|e| SriError::io(path, e)));
+	}
+	try!(index_writer.flush().map_err(|e| SriError::io(path, e)));
+	Ok(())
+}
+
+/// Reads a binary index file, checking its magic number, its version and the checksum of every section
+pub fn read_index(path: &str) -> Result<BinaryIndex, SriError> {
+	let mut bytes = Vec::new();
+	let file = try!(File::open(path).map_err(|e| SriError::io(path, e)));
+	try!(BufReader::new(file).read_to_end(&mut bytes).map_err(|e| SriError::io(path, e)));
+	read_index_bytes(&bytes).map_err(|e| e.in_file(path))
+}
+
+fn read_index_bytes(bytes: &[u8]) -> Result<BinaryIndex, SriError> {
+	if !bytes.starts_with(MAGIC) {
+		return Err(SriError::parse("Not a binary index (wrong magic number)"));
+	}
+	let mut reader = RecordReader::new(&bytes[MAGIC.len()..]);
+	let version = try!(reader.read_u32());
+	if version == 0 || version > FORMAT_VERSION {
+		return Err(SriError::parse(format!("Unsupported binary index version {} (this build reads up to version {})", version, FORMAT_VERSION)));
+	}
+
+	let section_count = try!(reader.read_u32());
+	let mut headers = Vec::new();
+	for _ in 0..section_count {
+		let tag = try!(reader.read_u32());
+		let record_count = try!(reader.read_u32());
+		let length = try!(reader.read_u64());
+		let checksum = try!(reader.read_u64());
+		headers.push((tag, record_count, length, checksum));
+	}
+
+	let mut sections = Vec::new();
+	for (tag, record_count, length, checksum) in headers {
+		let payload = try!(reader.take(length as usize));
+		if fnv1a(payload) != checksum {
+			return Err(SriError::parse(format!("Checksum mismatch in section {}", tag)));
+		}
+		let section = Section { tag: tag, record_count: record_count, payload: payload.to_vec() };
+		if section.content().is_none() {
+			warn(&format!("unknown section {} of binary index is ignored", tag));
+		}
+		sections.push(section);
+	}
+	if !reader.is_empty() {
+		return Err(SriError::parse("Unexpected bytes after the last section"));
+	}
+	Ok(BinaryIndex { sections: sections })
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use super::*;
+	use includes::image_types::IMAGE_QUANT_LVL;
+	use includes::image_types::ImageDescriptor;
+	use includes::sound_types::SoundDescriptor;
+	use includes::text_types::TextDescriptor;
+	use includes::util::test_directory;
+
+	#[test]
+	fn records_are_read_back() {
+		let mut writer = RecordWriter::new();
+		writer.write_u32(7);
+		writer.write_i32(-3);
+		writer.write_u64(1 << 40);
+		writer.write_str("caf\u{e9}");
+		let bytes = writer.into_bytes();
+		assert_eq!(&bytes[..4], &[7, 0, 0, 0]);
+
+		let mut reader = RecordReader::new(&bytes);
+		assert_eq!(reader.read_u32().unwrap(), 7);
+		assert_eq!(reader.read_i32().unwrap(), -3);
+		assert_eq!(reader.read_u64().unwrap(), 1 << 40);
+		assert_eq!(reader.read_string().unwrap(), "caf\u{e9}");
+		assert!(reader.is_empty());
+		assert!(reader.read_u32().is_err());
+	}
+
+	#[test]
+	fn truncated_records_are_refused() {
+		let mut writer = RecordWriter::new();
+		writer.write_str("truncated");
+		let bytes = writer.into_bytes();
+		assert!(RecordReader::new(&bytes[..bytes.len() - 1]).read_string().is_err());
+		assert!(RecordReader::new(&bytes[..2]).read_u32().is_err());
+	}
+
+	#[test]
+	fn counts_are_bounded_by_the_remaining_bytes() {
+		let mut writer = RecordWriter::new();
+		writer.write_u32(2);
+		writer.write_u64(0);
+		writer.write_u64(0);
+		let bytes = writer.into_bytes();
+		assert_eq!(RecordReader::new(&bytes).read_count(8).unwrap(), 2);
+		assert!(RecordReader::new(&bytes).read_count(9).is_err());
+
+		let mut writer = RecordWriter::new();
+		writer.write_u32(!0);
+		assert!(RecordReader::new(&writer.into_bytes()).read_count(!0).is_err());
+	}
+
+	#[test]
+	fn index_files_are_read_back() {
+		let directory = test_directory("binary-index");
+		let path = format!("{}/index.bin", directory);
+		let mut histogram = [0; IMAGE_QUANT_LVL];
+		histogram[3] = 12;
+		let images = vec![ImageDescriptor::from_histogram(String::from("img1"), 12, histogram)];
+		let sounds = vec![SoundDescriptor::from_histogram(String::from("snd1"), 2, 1, vec![vec![1, 2], vec![3, 4]])];
+		let mut occurences = HashMap::new();
+		occurences.insert(String::from("word"), 3);
+		occurences.insert(String::from("other"), 1);
+		let texts = vec![TextDescriptor::from_occurences(String::from("txt1"), 2, 4, occurences.clone())];
+		let fingerprint = Fingerprint::new(10, 20, 30);
+		let mut metadata = Metadata::new();
+		metadata.set("author", "someone");
+		let associations: Vec<AssociationRecord> = vec![("txt1", "docs/a.txt", Some(&fingerprint), Some(&metadata))];
+		let sections = vec![base_section(&images), base_section(&sounds), base_section(&texts), associations_section(MediaKind::Text, &associations), metadata_section(MediaKind::Text, &associations)];
+		write_index(&path, &sections).unwrap();
+
+		let index = read_index(&path).unwrap();
+		let image = &index.get_base::<ImageDescriptor>().unwrap()[0];
+		assert_eq!((image.get_id(), image.get_pixels_amount(), image.get_histogram()[3]), ("img1", 12, 12));
+		let sound = &index.get_base::<SoundDescriptor>().unwrap()[0];
+		assert_eq!((sound.get_id(), sound.get_histograms_size(), sound.get_histograms_levels()), ("snd1", 2, 1));
+		assert_eq!(sound.get_histograms(), &vec![vec![1, 2], vec![3, 4]]);
+		let text = &index.get_base::<TextDescriptor>().unwrap()[0];
+		assert_eq!((text.get_id(), text.get_word_file(), text.get_word_numb(), text.get_occurences()), ("txt1", 2, 4, &occurences));
+		assert_eq!(index.get_associations(MediaKind::Text).unwrap(), vec![(String::from("txt1"), String::from("docs/a.txt"), Some(fingerprint), metadata)]);
+		assert!(index.get_associations(MediaKind::Image).unwrap().is_empty());
+	}
+
+	#[test]
+	fn corrupted_index_files_are_refused() {
+		let directory = test_directory("binary-index-corrupted");
+		let path = format!("{}/index.bin", directory);
+		let sounds = vec![SoundDescriptor::from_histogram(String::from("snd1"), 1, 1, vec![vec![1, 2]])];
+		write_index(&path, &[base_section(&sounds)]).unwrap();
+		let mut bytes = Vec::new();
+		File::open(&path).unwrap().read_to_end(&mut bytes).unwrap();
+
+		let mut corrupted = bytes.clone();
+		let last = corrupted.len() - 1;
+		corrupted[last] ^= 1;
+		assert!(read_index_bytes(&corrupted).is_err());
+		assert!(read_index_bytes(&bytes[1..]).is_err());
+		let mut longer = bytes.clone();
+		longer.push(0);
+		assert!(read_index_bytes(&longer).is_err());
+		assert!(read_index_bytes(&bytes).is_ok());
+	}
+}
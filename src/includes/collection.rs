@@ -0,0 +1,90 @@
+use includes::util::absolute_path;
+use includes::util::normalize_path;
+use includes::util::relative_path;
+
+/// Directory the paths of the documents of an index are relative to, so the collection can be moved with its index or on its own
+///
+/// A relative root is relative to the index directory, `..` making an index stored inside its collection portable as a whole.
+#[derive(Clone, Debug)]
+pub struct CollectionRoot {
+	path: String,
+	index_directory: String,
+	directory: String,
+}
+
+impl CollectionRoot {
+	/// Root written as `path` for the index stored in `index_path`
+	pub fn new(path: &str, index_path: &str) -> CollectionRoot {
+		let index_directory = absolute_path(index_path);
+		let directory = match path.starts_with('/') {
+			true => normalize_path(path),
+			false => normalize_path(&format!("{}/{}", index_directory, path)),
+		};
+		CollectionRoot { path: String::from(path), index_directory: index_directory, directory: directory }
+	}
+
+	/// Root as it was given, relative to the index directory or absolute
+	pub fn get_path(&self) -> &str { &self.path }
+
+	/// Absolute directory of the root
+	pub fn get_directory(&self) -> &str { &self.directory }
+
+	/// Root to save in the `index_path` directory, absolute when the index is saved away from the directory its relative root was given for
+	pub fn path_from(&self, index_path: &str) -> &str {
+		match self.path.starts_with('/') || absolute_path(index_path) == self.index_directory {
+			true => &self.path,
+			false => &self.directory,
+		}
+	}
+
+	/// Path stored for a file given from the working directory, relative to the root when the file is inside it and absolute otherwise
+	pub fn store_path(&self, filename: &str) -> String {
+		let path = absolute_path(filename);
+		relative_path(&path, &self.directory).unwrap_or(path)
+	}
+
+	/// Path of the file of a stored path, from the working directory
+	pub fn resolve_path(&self, stored_path: &str) -> String {
+		match stored_path.starts_with('/') {
+			true => String::from(stored_path),
+			false => format!("{}/{}", self.directory, stored_path),
+		}
+	}
+}
+
+/// Path with its leading `old_prefix` components replaced by `new_prefix`, `None` when it does not start with them
+pub fn replace_prefix(path: &str, old_prefix: &str, new_prefix: &str) -> Option<String> {
+	let (path, old_prefix, new_prefix) = (normalize_path(path), normalize_path(old_prefix), normalize_path(new_prefix));
+	if path == old_prefix {
+		return Some(new_prefix);
+	}
+	relative_path(&path, &old_prefix).map(|rest| match new_prefix.is_empty() {
+		true => rest,
+		false => format!("{}/{}", new_prefix.trim_right_matches('/'), rest),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn relative_roots_follow_the_index_directory() {
+		let root = CollectionRoot::new("..", "/data/index");
+		assert_eq!(root.get_directory(), "/data");
+		assert_eq!(root.store_path("/data/docs/a.txt"), "docs/a.txt");
+		assert_eq!(root.store_path("/other/b.txt"), "/other/b.txt");
+		assert_eq!(root.resolve_path("docs/a.txt"), "/data/docs/a.txt");
+		assert_eq!(root.path_from("/data/index"), "..");
+		assert_eq!(root.path_from("/backup/index"), "/data");
+		assert_eq!(CollectionRoot::new("/srv", "/data/index").path_from("/backup/index"), "/srv");
+	}
+
+	#[test]
+	fn prefixes_are_replaced_by_whole_components() {
+		assert_eq!(replace_prefix("/old/photos/a.png", "/old", "/new/"), Some(String::from("/new/photos/a.png")));
+		assert_eq!(replace_prefix("/old/photos/a.png", "/old/photos", ""), Some(String::from("a.png")));
+		assert_eq!(replace_prefix("/old", "/old/", "/new"), Some(String::from("/new")));
+		assert_eq!(replace_prefix("/older/a.png", "/old", "/new"), None);
+	}
+}
@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+
+use std::io::BufReader;
+use std::fs::File;
+use std::str::FromStr;
+
+use java_properties::PropertiesIter;
+
+use includes::error::SriError;
+
+static IMAGES_BASE_FILENAME: &'static str = "img_base.txt";
+static SOUNDS_BASE_FILENAME: &'static str = "snd_base.txt";
+static TEXTS_BASE_FILENAME: &'static str = "txt_base.txt";
+
+static IMAGES_ASSOCIATION_FILENAME: &'static str = "img_association.txt";
+static SOUNDS_ASSOCIATION_FILENAME: &'static str = "snd_association.txt";
+static TEXTS_ASSOCIATION_FILENAME: &'static str = "txt_association.txt";
+
+static BINARY_INDEX_FILENAME: &'static str = "index.bin";
+static TOMBSTONES_FILENAME: &'static str = "tombstones.txt";
+static ALIASES_FILENAME: &'static str = "aliases.txt";
+static ROOT_FILENAME: &'static str = "root.txt";
+
+const MIN_WORD_SIZE: usize = 0;
+const MAX_WORD_PER_TEXT: usize = 100000;
+
+const MAX_WINDOW_LEVELS: usize = 65536;
+
+const MAX_SEGMENTS: usize = 16;
+const MAX_SNAPSHOTS: usize = 3;
+const WATCH_DELAY: u64 = 2000;
+const WATCH_MAX_DELAY: u64 = 30000;
+
+/// Every parameter a config file may contain
+pub static KNOWN_PARAMS: &'static [&'static str] = &["index",
+                                                     "images_base_filename",
+                                                     "sounds_base_filename",
+                                                     "texts_base_filename",
+                                                     "images_associations_filename",
+                                                     "sounds_associations_filename",
+                                                     "texts_associations_filename",
+                                                     "binary_index_filename",
+                                                     "tombstones_filename",
+                                                     "aliases_filename",
+                                                     "root_filename",
+                                                     "collection_root",
+                                                     "index_format",
+                                                     "id_scheme",
+                                                     "load_mode",
+                                                     "max_segments",
+                                                     "max_snapshots",
+                                                     "snapshot_copies",
+                                                     "indexing_threads",
+                                                     "watch_delay",
+                                                     "watch_max_delay",
+                                                     "window_size",
+                                                     "window_levels",
+                                                     "min_word_size",
+                                                     "max_word_per_text"];
+
+/// Format the index is saved in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexFormat {
+	/// One base file and one associations file per media kind, as whitespace separated text
+	Text,
+	/// A single file of checksummed sections of length prefixed records
+	Binary,
+}
+
+impl IndexFormat {
+	pub fn from_name(name: &str) -> Option<IndexFormat> {
+		match name {
+			"text" => Some(IndexFormat::Text),
+			"binary" => Some(IndexFormat::Binary),
+			_ => None,
+		}
+	}
+
+	pub fn get_name(&self) -> &'static str {
+		match *self {
+			IndexFormat::Text => "text",
+			IndexFormat::Binary => "binary",
+		}
+	}
+}
+
+/// How the ids of new documents are chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdScheme {
+	/// The prefix of the media kind followed by a counter, `snd12`, which depends on the indexing order
+	Sequential,
+	/// The prefix followed by the hash of the content of the file, `snd_6daf8240e857b3aa`
+	Content,
+	/// The prefix followed by the hash of the normalised path of the file
+	Path,
+}
+
+impl IdScheme {
+	pub fn from_name(name: &str) -> Option<IdScheme> {
+		match name {
+			"sequential" => Some(IdScheme::Sequential),
+			"content" => Some(IdScheme::Content),
+			"path" => Some(IdScheme::Path),
+			_ => None,
+		}
+	}
+
+	pub fn get_name(&self) -> &'static str {
+		match *self {
+			IdScheme::Sequential => "sequential",
+			IdScheme::Content => "content",
+			IdScheme::Path => "path",
+		}
+	}
+}
+
+/// How the loading of an index handles the records it can't read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadMode {
+	/// The first unreadable record fails the loading
+	Strict,
+	/// Unreadable records are left out, the context keeping their errors
+	Lenient,
+}
+
+impl LoadMode {
+	pub fn from_name(name: &str) -> Option<LoadMode> {
+		match name {
+			"strict" => Some(LoadMode::Strict),
+			"lenient" => Some(LoadMode::Lenient),
+			_ => None,
+		}
+	}
+
+	pub fn get_name(&self) -> &'static str {
+		match *self {
+			LoadMode::Strict => "strict",
+			LoadMode::Lenient => "lenient",
+		}
+	}
+}
+
+/// Validated configuration of an index
+#[derive(Clone, Debug)]
+pub struct Config {
+	index: String,
+	images_base_filename: String,
+	sounds_base_filename: String,
+	texts_base_filename: String,
+	images_associations_filename: String,
+	sounds_associations_filename: String,
+	texts_associations_filename: String,
+	binary_index_filename: String,
+	tombstones_filename: String,
+	aliases_filename: String,
+	root_filename: String,
+	collection_root: Option<String>,
+	index_format: IndexFormat,
+	id_scheme: IdScheme,
+	load_mode: LoadMode,
+	max_segments: usize,
+	max_snapshots: usize,
+	snapshot_copies: bool,
+	indexing_threads: usize,
+	watch_delay: u64,
+	watch_max_delay: u64,
+	window_size: Option<usize>,
+	window_levels: Option<usize>,
+	min_word_size: usize,
+	max_word_per_text: usize,
+}
+
+/// Outcome of the validation of raw config parameters
+pub struct ConfigCheck {
+	config: Config,
+	errors: Vec<SriError>,
+	warnings: Vec<String>,
+}
+
+impl ConfigCheck {
+	/// The config, present only when no error was found
+	pub fn get_config(&self) -> Option<&Config> {
+		match self.errors.is_empty() {
+			true => Some(&self.config),
+			false => None,
+		}
+	}
+
+	/// The config as far as it could be parsed, the parameters in error keeping their default value
+	pub fn get_effective_config(&self) -> &Config { &self.config }
+
+	pub fn get_errors(&self) -> &Vec<SriError> { &self.errors }
+
+	pub fn get_warnings(&self) -> &Vec<String> { &self.warnings }
+
+	pub fn into_result(self) -> Result<Config, SriError> {
+		match self.errors.is_empty() {
+			true => Ok(self.config),
+			false => {
+				let messages: Vec<String> = self.errors.iter().map(|e| format!("{}", e)).collect();
+				Err(SriError::config(messages.join(", ")))
+			},
+		}
+	}
+}
+
+impl Config {
+	/// Default configuration for an index stored in `index`
+	pub fn new(index: String) -> Config {
+		Config {
+			index: index,
+			images_base_filename: String::from(IMAGES_BASE_FILENAME),
+			sounds_base_filename: String::from(SOUNDS_BASE_FILENAME),
+			texts_base_filename: String::from(TEXTS_BASE_FILENAME),
+			images_associations_filename: String::from(IMAGES_ASSOCIATION_FILENAME),
+			sounds_associations_filename: String::from(SOUNDS_ASSOCIATION_FILENAME),
+			texts_associations_filename: String::from(TEXTS_ASSOCIATION_FILENAME),
+			binary_index_filename: String::from(BINARY_INDEX_FILENAME),
+			tombstones_filename: String::from(TOMBSTONES_FILENAME),
+			aliases_filename: String::from(ALIASES_FILENAME),
+			root_filename: String::from(ROOT_FILENAME),
+			collection_root: None,
+			index_format: IndexFormat::Text,
+			id_scheme: IdScheme::Sequential,
+			load_mode: LoadMode::Lenient,
+			max_segments: MAX_SEGMENTS,
+			max_snapshots: MAX_SNAPSHOTS,
+			snapshot_copies: false,
+			indexing_threads: 0,
+			watch_delay: WATCH_DELAY,
+			watch_max_delay: WATCH_MAX_DELAY,
+			window_size: None,
+			window_levels: None,
+			min_word_size: MIN_WORD_SIZE,
+			max_word_per_text: MAX_WORD_PER_TEXT,
+		}
+	}
+
+	/// Reads the raw parameters of a java properties file
+	pub fn load_params(config_filename: &str) -> Result<HashMap<String, String>, SriError> {
+		let mut params = HashMap::new();
+		let config_file = match File::open(config_filename) {
+			Ok(config_file) => config_file,
+			Err(e) => return Err(SriError::io(config_filename, e)),
+		};
+		match PropertiesIter::new(BufReader::new(config_file)).read_into(|k, v| {
+			params.insert(k, v);
+		}) {
+			Ok(_) => Ok(params),
+			Err(e) => Err(SriError::parse(format!("{:?}", e)).in_file(config_filename)),
+		}
+	}
+
+	/// Reads and checks a java properties config file, its warnings being left for the caller to report
+	pub fn from_file(config_filename: &str) -> Result<ConfigCheck, SriError> {
+		let params = try!(Config::load_params(config_filename));
+		Ok(Config::check(&params))
+	}
+
+	/// Parses and validates raw parameters, filling the missing ones with their defaults
+	///
+	/// Every problem is collected instead of stopping at the first one, so they can all be reported at once.
+	pub fn check(params: &HashMap<String, String>) -> ConfigCheck { Config::check_params(params, true) }
+
+	/// Same as `check` for a config whose index directories are given apart, like the shards of a federation, the index being optional
+	pub fn check_template(params: &HashMap<String, String>) -> ConfigCheck { Config::check_params(params, false) }
+
+	fn check_params(params: &HashMap<String, String>, index_required: bool) -> ConfigCheck {
+		let mut errors = Vec::new();
+		let mut warnings = Vec::new();
+
+		let mut keys: Vec<&String> = params.keys().collect();
+		keys.sort();
+		for key in keys {
+			if !KNOWN_PARAMS.contains(&key.as_ref()) {
+				warnings.push(format!("Unknown parameter '{}' is ignored", key));
+			}
+		}
+
+		let mut config = Config::new(String::new());
+		match params.get("index") {
+			Some(index) if !index.trim().is_empty() => config.index = index.clone(),
+			_ if index_required => errors.push(SriError::config("Parameter 'index' is required")),
+			_ => (),
+		}
+
+		{
+			let filenames: Vec<(&str, &mut String)> = vec![("images_base_filename", &mut config.images_base_filename),
+			                                               ("sounds_base_filename", &mut config.sounds_base_filename),
+			                                               ("texts_base_filename", &mut config.texts_base_filename),
+			                                               ("images_associations_filename", &mut config.images_associations_filename),
+			                                               ("sounds_associations_filename", &mut config.sounds_associations_filename),
+			                                               ("texts_associations_filename", &mut config.texts_associations_filename),
+			                                               ("binary_index_filename", &mut config.binary_index_filename),
+			                                               ("tombstones_filename", &mut config.tombstones_filename),
+			                                               ("aliases_filename", &mut config.aliases_filename),
+			                                               ("root_filename", &mut config.root_filename)];
+			for (param, filename) in filenames {
+				match params.get(param) {
+					Some(value) if value.trim().is_empty() => errors.push(SriError::config(format!("Parameter '{}' can't be empty", param))),
+					Some(value) => *filename = value.clone(),
+					None => (),
+				}
+			}
+		}
+
+		match params.get("collection_root") {
+			Some(root) if root.trim().is_empty() => errors.push(SriError::config("Parameter 'collection_root' can't be empty")),
+			Some(root) => config.collection_root = Some(String::from(root.trim())),
+			None => (),
+		}
+
+		if let Some(index_format) = params.get("index_format") {
+			match IndexFormat::from_name(index_format.trim()) {
+				Some(index_format) => config.index_format = index_format,
+				None => errors.push(SriError::config(format!("Invalid value for parameter index_format ({}), expected 'text' or 'binary'", index_format))),
+			}
+		}
+
+		if let Some(id_scheme) = params.get("id_scheme") {
+			match IdScheme::from_name(id_scheme.trim()) {
+				Some(id_scheme) => config.id_scheme = id_scheme,
+				None => errors.push(SriError::config(format!("Invalid value for parameter id_scheme ({}), expected 'sequential', 'content' or 'path'", id_scheme))),
+			}
+		}
+
+		if let Some(load_mode) = params.get("load_mode") {
+			match LoadMode::from_name(load_mode.trim()) {
+				Some(load_mode) => config.load_mode = load_mode,
+				None => errors.push(SriError::config(format!("Invalid value for parameter load_mode ({}), expected 'strict' or 'lenient'", load_mode))),
+			}
+		}
+
+		if let Some(max_segments) = parse_param(params, "max_segments", 0, usize::max_value(), &mut errors) {
+			config.max_segments = max_segments;
+		}
+		if let Some(max_snapshots) = parse_param(params, "max_snapshots", 0, usize::max_value(), &mut errors) {
+			config.max_snapshots = max_snapshots;
+		}
+		if let Some(snapshot_copies) = parse_param(params, "snapshot_copies", false, true, &mut errors) {
+			config.snapshot_copies = snapshot_copies;
+		}
+		if let Some(indexing_threads) = parse_param(params, "indexing_threads", 0, usize::max_value(), &mut errors) {
+			config.indexing_threads = indexing_threads;
+		}
+		if let Some(watch_delay) = parse_param(params, "watch_delay", 1, u64::max_value(), &mut errors) {
+			config.watch_delay = watch_delay;
+		}
+		if let Some(watch_max_delay) = parse_param(params, "watch_max_delay", 1, u64::max_value(), &mut errors) {
+			config.watch_max_delay = watch_max_delay;
+		}
+
+		config.window_size = parse_param(params, "window_size", 1, usize::max_value(), &mut errors);
+		config.window_levels = parse_param(params, "window_levels", 1, MAX_WINDOW_LEVELS, &mut errors);
+		if config.window_size.is_none() || config.window_levels.is_none() {
+			warnings.push(String::from("Parameters 'window_size' and 'window_levels' are needed to index sounds"));
+		}
+		if let Some(min_word_size) = parse_param(params, "min_word_size", 0, usize::max_value(), &mut errors) {
+			config.min_word_size = min_word_size;
+		}
+		if let Some(max_word_per_text) = parse_param(params, "max_word_per_text", 1, usize::max_value(), &mut errors) {
+			config.max_word_per_text = max_word_per_text;
+		}
+
+		let distinct_filenames = {
+			let mut filenames: Vec<&String> = vec![&config.images_base_filename, &config.sounds_base_filename, &config.texts_base_filename, &config.images_associations_filename, &config.sounds_associations_filename, &config.texts_associations_filename, &config.binary_index_filename, &config.tombstones_filename, &config.aliases_filename, &config.root_filename];
+			filenames.sort();
+			filenames.dedup();
+			filenames.len()
+		};
+		if distinct_filenames != 10 {
+			errors.push(SriError::config("The base, associations, binary index, tombstones, aliases and root filenames must all be different"));
+		}
+
+		ConfigCheck { config: config, errors: errors, warnings: warnings }
+	}
+
+	/// Every parameter with its effective value, in a stable order
+	pub fn to_params(&self) -> Vec<(&'static str, String)> {
+		let optional = |value: Option<usize>| {
+			match value {
+				Some(value) => format!("{}", value),
+				None => String::from("(unset)"),
+			}
+		};
+		vec![("index", self.index.clone()),
+		     ("images_base_filename", self.images_base_filename.clone()),
+		     ("sounds_base_filename", self.sounds_base_filename.clone()),
+		     ("texts_base_filename", self.texts_base_filename.clone()),
+		     ("images_associations_filename", self.images_associations_filename.clone()),
+		     ("sounds_associations_filename", self.sounds_associations_filename.clone()),
+		     ("texts_associations_filename", self.texts_associations_filename.clone()),
+		     ("binary_index_filename", self.binary_index_filename.clone()),
+		     ("tombstones_filename", self.tombstones_filename.clone()),
+		     ("aliases_filename", self.aliases_filename.clone()),
+		     ("root_filename", self.root_filename.clone()),
+		     ("collection_root", self.collection_root.clone().unwrap_or_else(|| String::from("(unset)"))),
+		     ("index_format", String::from(self.index_format.get_name())),
+		     ("id_scheme", String::from(self.id_scheme.get_name())),
+		     ("load_mode", String::from(self.load_mode.get_name())),
+		     ("max_segments", format!("{}", self.max_segments)),
+		     ("max_snapshots", format!("{}", self.max_snapshots)),
+		     ("snapshot_copies", format!("{}", self.snapshot_copies)),
+		     ("indexing_threads", format!("{}", self.indexing_threads)),
+		     ("watch_delay", format!("{}", self.watch_delay)),
+		     ("watch_max_delay", format!("{}", self.watch_max_delay)),
+		     ("window_size", optional(self.window_size)),
+		     ("window_levels", optional(self.window_levels)),
+		     ("min_word_size", format!("{}", self.min_word_size)),
+		     ("max_word_per_text", format!("{}", self.max_word_per_text))]
+	}
+
+	pub fn get_index(&self) -> &str { &self.index }
+
+	/// Same config for the index stored in another directory
+	pub fn with_index(&self, index: &str) -> Config {
+		let mut config = self.clone();
+		config.index = String::from(index);
+		config
+	}
+
+	/// Path of one of the index files
+	pub fn index_file_path(&self, filename: &str) -> String { format!("{}/{}", self.index, filename) }
+
+	pub fn get_images_base_filename(&self) -> &str { &self.images_base_filename }
+
+	pub fn get_sounds_base_filename(&self) -> &str { &self.sounds_base_filename }
+
+	pub fn get_texts_base_filename(&self) -> &str { &self.texts_base_filename }
+
+	pub fn get_images_associations_filename(&self) -> &str { &self.images_associations_filename }
+
+	pub fn get_sounds_associations_filename(&self) -> &str { &self.sounds_associations_filename }
+
+	pub fn get_texts_associations_filename(&self) -> &str { &self.texts_associations_filename }
+
+	pub fn get_binary_index_filename(&self) -> &str { &self.binary_index_filename }
+
+	/// File of the ids removed by a segment from the documents saved before it
+	pub fn get_tombstones_filename(&self) -> &str { &self.tombstones_filename }
+
+	/// File of the former ids of the documents, each one followed by the id it stands for
+	pub fn get_aliases_filename(&self) -> &str { &self.aliases_filename }
+
+	/// File of the collection root the paths of the documents are relative to
+	pub fn get_root_filename(&self) -> &str { &self.root_filename }
+
+	/// Collection root of a new index, relative to the index directory unless absolute; an existing index keeps its own
+	pub fn get_collection_root(&self) -> Option<&str> { self.collection_root.as_ref().map(String::as_str) }
+
+	/// Format new saves of the index are written in, loading accepts both
+	pub fn get_index_format(&self) -> IndexFormat { self.index_format }
+
+	/// How the ids of the documents added to the index are chosen
+	pub fn get_id_scheme(&self) -> IdScheme { self.id_scheme }
+
+	/// Whether an unreadable record fails the loading of the index or is left out
+	pub fn get_load_mode(&self) -> LoadMode { self.load_mode }
+
+	/// Number of segments a save may append to before the index is compacted, 0 compacting on every save
+	pub fn get_max_segments(&self) -> usize { self.max_segments }
+
+	/// Number of generations kept as snapshots by the saves of the index, 0 keeping none
+	pub fn get_max_snapshots(&self) -> usize { self.max_snapshots }
+
+	/// Whether the snapshots copy the files of the index on a filesystem without hard links, instead of not being taken
+	pub fn get_snapshot_copies(&self) -> bool { self.snapshot_copies }
+
+	/// Number of threads extracting the descriptors of the documents being indexed, 0 running one per core
+	pub fn get_indexing_threads(&self) -> usize { self.indexing_threads }
+
+	/// Milliseconds without change a watched directory waits for before indexing and saving a batch of changes
+	pub fn get_watch_delay(&self) -> u64 { self.watch_delay }
+
+	/// Milliseconds after which a batch of changes is indexed and saved even if the watched directory keeps changing
+	pub fn get_watch_max_delay(&self) -> u64 { self.watch_max_delay }
+
+	pub fn get_window_size(&self) -> Option<usize> { self.window_size }
+
+	pub fn get_window_levels(&self) -> Option<usize> { self.window_levels }
+
+	pub fn get_min_word_size(&self) -> usize { self.min_word_size }
+
+	pub fn get_max_word_per_text(&self) -> usize { self.max_word_per_text }
+}
+
+/// Parses an optional numeric parameter, recording an error when it is invalid or outside of `[min, max]`
+fn parse_param<T: FromStr + PartialOrd + ToString>(params: &HashMap<String, String>, param: &str, min: T, max: T, errors: &mut Vec<SriError>) -> Option<T> {
+	let value = match params.get(param) {
+		Some(value) => value,
+		None => return None,
+	};
+	match value.trim().parse::<T>() {
+		Ok(parsed) => {
+			if parsed < min || parsed > max {
+				errors.push(SriError::config(format!("Parameter '{}' must be between {} and {} ({})", param, min.to_string(), max.to_string(), value)));
+				None
+			} else {
+				Some(parsed)
+			}
+		},
+		Err(_) => {
+			errors.push(SriError::config(format!("Invalid value for parameter {} ({})", param, value)));
+			None
+		},
+	}
+}
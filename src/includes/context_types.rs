@@ -2,10 +2,13 @@
 
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 
 use std::io::BufReader;
 use std::io::BufRead;
 use std::io::Write;
+use std::io::BufWriter;
+use std::fs;
 use std::fs::File;
 use std::path::Path;
 use std::cmp;
@@ -13,49 +16,366 @@ use std::cmp;
 use includes::image_types::ImageDescriptor;
 use includes::sound_types::SoundDescriptor;
 use includes::text_types::TextDescriptor;
-
-static IMAGES_BASE_FILENAME: &'static str = "img_base.txt";
-static SOUNDS_BASE_FILENAME: &'static str = "snd_base.txt";
-static TEXTS_BASE_FILENAME: &'static str = "txt_base.txt";
-
-static IMAGES_ASSOCIATION_FILENAME: &'static str = "img_association.txt";
-static SOUNDS_ASSOCIATION_FILENAME: &'static str = "snd_association.txt";
-static TEXTS_ASSOCIATION_FILENAME: &'static str = "txt_association.txt";
-
-static MIN_WORD_SIZE: &'static str = "0";
-static MAX_WORD_PER_TEXT: &'static str = "100000";
+use includes::descriptor::Descriptor;
+use includes::MediaDescriptor;
+use includes::MediaKind;
+use includes::binary_index;
+use includes::config::Config;
+use includes::config::IdScheme;
+use includes::config::IndexFormat;
+use includes::config::LoadMode;
+use includes::fingerprint::Fingerprint;
+use includes::metadata;
+use includes::metadata::Metadata;
+use includes::collection;
+use includes::collection::CollectionRoot;
+use includes::lock::IndexLock;
+use includes::lock::LockMode;
+use includes::snapshot;
+use includes::manifest;
+use includes::manifest::Manifest;
+use includes::manifest::SaveTransaction;
+use includes::error::SriError;
+use includes::util::LineCountingReader;
+use includes::util::absolute_path;
+use includes::util::fnv1a;
+use includes::util::normalize_path;
+use includes::util::warn;
+
+/// First line of the associations files whose ids and filenames are escaped
+pub static ASSOCIATIONS_HEADER: &'static str = "sri-associations 2";
+
+/// Separates the prefix of a stable id from the hash it is made of, `snd_6daf8240e857b3aa`
+pub const STABLE_ID_SEPARATOR: char = '_';
 
 pub struct ContextObject {
     max_doc_id: u64,
-    config: HashMap<String, String>,
+    config: Config,
     images_associations: HashMap<String, String>,
     sounds_associations: HashMap<String, String>,
     texts_associations: HashMap<String, String>,
     images_base: Vec<ImageDescriptor>,
     sounds_base: Vec<SoundDescriptor>,
     texts_base: Vec<TextDescriptor>,
+    fingerprints: HashMap<String, Fingerprint>,
+    metadata: HashMap<String, Metadata>,
+    collection_root: Option<CollectionRoot>,
+    lock: Option<IndexLock>,
+    aliases: HashMap<String, String>,
+    saved: Option<SavedState>,
+    tombstones: Vec<String>,
+    updated_associations: Vec<String>,
+    skipped_records: Vec<SriError>,
+    warnings: Vec<String>,
+}
+
+/// Documents of the in-memory index that the generation `generation` of the manifest already holds
+#[derive(Clone, Copy)]
+struct SavedState {
+    generation: u64,
+    images: usize,
+    sounds: usize,
+    texts: usize,
+}
+
+/// Documents of an index merged into another one, with the id they had in their index
+pub struct MergeReport {
+    added: Vec<(String, String)>,
+    duplicates: Vec<(String, String)>,
+    orphans: Vec<String>,
+    skipped_records: Vec<SriError>,
+}
+
+impl MergeReport {
+    /// Merged documents, with their new id
+    pub fn get_added(&self) -> &[(String, String)] {
+        &self.added
+    }
+
+    /// Documents skipped because they were already indexed, with the id of the indexed document
+    pub fn get_duplicates(&self) -> &[(String, String)] {
+        &self.duplicates
+    }
+
+    /// Documents skipped because their index has no association for them, so no file to merge them under
+    pub fn get_orphans(&self) -> &[String] {
+        &self.orphans
+    }
+
+    /// Errors of the records of the merged index left out by its lenient loading
+    pub fn get_skipped_records(&self) -> &[SriError] {
+        &self.skipped_records
+    }
+}
+
+/// Association of a document being saved, with the fingerprint of its file and its metadata when known
+pub type AssociationRecord<'a> = (&'a str, &'a str, Option<&'a Fingerprint>, Option<&'a Metadata>);
+
+/// Association read from a saved index: id, filename, fingerprint of the file when known and metadata
+pub type LoadedAssociation = (String, String, Option<Fingerprint>, Metadata);
+
+/// Documents written by a save, either the whole index or the ones added since the last save
+struct IndexPart<'a> {
+    images_associations: Vec<AssociationRecord<'a>>,
+    sounds_associations: Vec<AssociationRecord<'a>>,
+    texts_associations: Vec<AssociationRecord<'a>>,
+    images_base: &'a [ImageDescriptor],
+    sounds_base: &'a [SoundDescriptor],
+    texts_base: &'a [TextDescriptor],
+}
+
+impl<'a> IndexPart<'a> {
+    fn is_empty(&self) -> bool {
+        self.images_associations.is_empty() && self.sounds_associations.is_empty() && self.texts_associations.is_empty() && self.images_base.is_empty() && self.sounds_base.is_empty() &&
+        self.texts_base.is_empty()
+    }
+
+    /// Writes the files of the part in the `directory` of the index, an empty one meaning the index directory itself
+    fn write(&self, config: &Config, transaction: &mut SaveTransaction, directory: &str, format: IndexFormat) -> Result<(), SriError> {
+        let filename = |name: &str| {
+            match directory {
+                "" => String::from(name),
+                _ => format!("{}/{}", directory, name),
+            }
+        };
+        match format {
+            IndexFormat::Text => {
+                try!(save_associations(&transaction.file_path(&filename(config.get_images_associations_filename())), &self.images_associations));
+                try!(save_base(&transaction.file_path(&filename(config.get_images_base_filename())), self.images_base));
+
+                try!(save_associations(&transaction.file_path(&filename(config.get_sounds_associations_filename())), &self.sounds_associations));
+                try!(save_base(&transaction.file_path(&filename(config.get_sounds_base_filename())), self.sounds_base));
+
+                try!(save_associations(&transaction.file_path(&filename(config.get_texts_associations_filename())), &self.texts_associations));
+                try!(save_base(&transaction.file_path(&filename(config.get_texts_base_filename())), self.texts_base));
+                Ok(())
+            }
+            IndexFormat::Binary => {
+                let sections = vec![binary_index::associations_section(MediaKind::Image, &self.images_associations),
+                                    binary_index::metadata_section(MediaKind::Image, &self.images_associations),
+                                    binary_index::base_section(self.images_base),
+                                    binary_index::associations_section(MediaKind::Sound, &self.sounds_associations),
+                                    binary_index::metadata_section(MediaKind::Sound, &self.sounds_associations),
+                                    binary_index::base_section(self.sounds_base),
+                                    binary_index::associations_section(MediaKind::Text, &self.texts_associations),
+                                    binary_index::metadata_section(MediaKind::Text, &self.texts_associations),
+                                    binary_index::base_section(self.texts_base)];
+                binary_index::write_index(&transaction.file_path(&filename(config.get_binary_index_filename())), &sections)
+            }
+        }
+    }
 }
 
 impl ContextObject {
-    pub fn new() -> ContextObject {
+    pub fn new(config: Config) -> ContextObject {
+        let collection_root = config.get_collection_root().map(|root| CollectionRoot::new(root, config.get_index()));
         ContextObject {
             max_doc_id: 0,
-            config: HashMap::new(),
+            config: config,
             images_associations: HashMap::new(),
             sounds_associations: HashMap::new(),
             texts_associations: HashMap::new(),
             images_base: vec![],
             sounds_base: vec![],
             texts_base: vec![],
+            fingerprints: HashMap::new(),
+            metadata: HashMap::new(),
+            collection_root: collection_root,
+            lock: None,
+            aliases: HashMap::new(),
+            saved: None,
+            tombstones: Vec::new(),
+            updated_associations: Vec::new(),
+            skipped_records: Vec::new(),
+            warnings: Vec::new(),
         }
     }
 
+    /// Creates an empty context from a java properties config file, its warnings being kept in the ones of the context
+    pub fn from_config_file(config_filename: &str) -> Result<ContextObject, SriError> {
+        let check = try!(Config::from_file(config_filename));
+        let warnings = check.get_warnings().clone();
+        let mut context = ContextObject::new(try!(check.into_result()));
+        context.warnings = warnings;
+        Ok(context)
+    }
+
     pub fn gen_id(&mut self, prefix: String) -> String {
         let id = String::from(format!("{}{}", prefix, self.max_doc_id));
         self.max_doc_id += 1;
         id
     }
 
+    /// Id derived from the content or from the normalised path of a file, following the id scheme of the config
+    ///
+    /// The same file gets the same id whenever the index is rebuilt. `None` with the sequential scheme.
+    /// Files with the same content share a hash, the second one indexed getting the id followed by `_1`, and so on.
+    pub fn stable_id(&self, kind: MediaKind, filename: &str, fingerprint: &Fingerprint) -> Option<String> {
+        let hash = match self.config.get_id_scheme() {
+            IdScheme::Sequential => return None,
+            IdScheme::Content => fingerprint.get_hash(),
+            IdScheme::Path => fnv1a(normalize_path(filename).as_bytes()),
+        };
+        let id = format!("{}{}{:016x}", kind.get_prefix(), STABLE_ID_SEPARATOR, hash);
+        let mut candidate = id.clone();
+        let mut duplicates = 0;
+        while self.get_association(&candidate).map_or(false, |associated_filename| associated_filename != filename) {
+            duplicates += 1;
+            candidate = format!("{}{}{}", id, STABLE_ID_SEPARATOR, duplicates);
+        }
+        Some(candidate)
+    }
+
+    /// Id for a document added to the index, stable when the config asks for it and the fingerprint of its file is known
+    pub fn new_document_id(&mut self, kind: MediaKind, filename: &str, fingerprint: Option<&Fingerprint>) -> String {
+        match fingerprint.and_then(|fingerprint| self.stable_id(kind, filename, fingerprint)) {
+            Some(id) => id,
+            None => self.gen_id(String::from(kind.get_prefix())),
+        }
+    }
+
+    /// Filename a document id is associated to
+    pub fn get_association(&self, id: &str) -> Option<&String> {
+        self.images_associations.get(id).or(self.sounds_associations.get(id)).or(self.texts_associations.get(id))
+    }
+
+    /// Id a former id of a document stands for, other ids being returned as they are
+    pub fn resolve_id<'a>(&'a self, id: &'a str) -> &'a str {
+        match self.aliases.get(id) {
+            Some(current_id) => current_id,
+            None => id,
+        }
+    }
+
+    /// Former ids of the documents, each one with the id it stands for
+    pub fn get_aliases(&self) -> &HashMap<String, String> {
+        &self.aliases
+    }
+
+    /// Gives a document a new id, its former id being kept as an alias
+    ///
+    /// A renamed document can only be saved by rewriting the whole index, which the next save does.
+    /// Returns false when no document has the id `id`, or a document already has the id `new_id`.
+    pub fn rename_document(&mut self, id: &str, new_id: &str) -> bool {
+        if self.get_association(new_id).is_some() {
+            return false;
+        }
+        let new_id = String::from(new_id);
+        let renamed = if let Some(filename) = self.images_associations.remove(id) {
+            self.images_associations.insert(new_id.clone(), filename);
+            for descriptor in self.images_base.iter_mut().filter(|descriptor| descriptor.get_id() == id) {
+                descriptor.set_id(new_id.clone());
+            }
+            true
+        } else if let Some(filename) = self.sounds_associations.remove(id) {
+            self.sounds_associations.insert(new_id.clone(), filename);
+            for descriptor in self.sounds_base.iter_mut().filter(|descriptor| descriptor.get_id() == id) {
+                descriptor.set_id(new_id.clone());
+            }
+            true
+        } else if let Some(filename) = self.texts_associations.remove(id) {
+            self.texts_associations.insert(new_id.clone(), filename);
+            for descriptor in self.texts_base.iter_mut().filter(|descriptor| descriptor.get_id() == id) {
+                descriptor.set_id(new_id.clone());
+            }
+            true
+        } else {
+            false
+        };
+        if !renamed {
+            return false;
+        }
+        if let Some(fingerprint) = self.fingerprints.remove(id) {
+            self.fingerprints.insert(new_id.clone(), fingerprint);
+        }
+        if let Some(metadata) = self.metadata.remove(id) {
+            self.metadata.insert(new_id.clone(), metadata);
+        }
+        self.add_alias(id, &new_id);
+        true
+    }
+
+    /// Keeps `id` as a former id of the document `new_id`, the aliases of `id` standing for `new_id` too
+    ///
+    /// Like a rename, an alias is only saved by rewriting the whole index, which the next save does.
+    pub fn add_alias(&mut self, id: &str, new_id: &str) {
+        for current_id in self.aliases.values_mut().filter(|current_id| *current_id == id) {
+            *current_id = String::from(new_id);
+        }
+        self.aliases.insert(String::from(id), String::from(new_id));
+        self.saved = None;
+    }
+
+    /// Media kind of the document with the id `id`, `None` when no document has it
+    pub fn get_document_kind(&self, id: &str) -> Option<MediaKind> {
+        match (self.images_associations.contains_key(id), self.sounds_associations.contains_key(id), self.texts_associations.contains_key(id)) {
+            (true, _, _) => Some(MediaKind::Image),
+            (_, true, _) => Some(MediaKind::Sound),
+            (_, _, true) => Some(MediaKind::Text),
+            _ => None,
+        }
+    }
+
+    /// Renames every document that does not have its stable id yet, returning the former and the new id of each one
+    ///
+    /// The fingerprint of the documents indexed before they were recorded is computed from their file, the documents
+    /// whose file can't be read keeping their id.
+    pub fn assign_stable_ids(&mut self) -> Vec<Result<(String, String), SriError>> {
+        let mut documents: Vec<(MediaKind, String, String)> = Vec::new();
+        for &(kind, associations) in &[(MediaKind::Image, &self.images_associations), (MediaKind::Sound, &self.sounds_associations), (MediaKind::Text, &self.texts_associations)] {
+            for (id, filename) in associations {
+                documents.push((kind, id.clone(), filename.clone()));
+            }
+        }
+        documents.sort_by(|a, b| a.1.cmp(&b.1));
+
+        let mut renames = Vec::new();
+        for (kind, id, filename) in documents {
+            let fingerprint = match self.fingerprints.get(&id).cloned() {
+                Some(fingerprint) => fingerprint,
+                None => {
+                    match Fingerprint::of_file(&filename) {
+                        Ok(fingerprint) => {
+                            self.fingerprints.insert(id.clone(), fingerprint);
+                            fingerprint
+                        }
+                        Err(e) => {
+                            renames.push(Err(e));
+                            continue;
+                        }
+                    }
+                }
+            };
+            if let Some(new_id) = self.stable_id(kind, &filename, &fingerprint) {
+                if new_id != id && self.rename_document(&id, &new_id) {
+                    renames.push(Ok((id, new_id)));
+                }
+            }
+        }
+        renames
+    }
+
+
+    /// Lets a document keep an id given outside of this index, `gen_id` never handing it out afterwards
+    ///
+    /// Returns false when the id is already used, or is neither the prefix of `kind` followed by a number nor a stable id of `kind`.
+    pub fn reserve_id(&mut self, kind: MediaKind, id: &str) -> bool {
+        if !id.starts_with(kind.get_prefix()) || self.images_associations.contains_key(id) || self.sounds_associations.contains_key(id) || self.texts_associations.contains_key(id) {
+            return false;
+        }
+        let suffix = &id[kind.get_prefix().len()..];
+        if suffix.starts_with(STABLE_ID_SEPARATOR) {
+            return suffix.len() > 1;
+        }
+        match suffix.parse::<u64>() {
+            Ok(value) => {
+                self.max_doc_id = cmp::max(self.max_doc_id, value + 1);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
 
     pub fn add_image_association(&mut self, id: String, filename: String) {
         self.images_associations.insert(id, filename);
@@ -79,6 +399,305 @@ impl ContextObject {
     }
 
 
+    /// Adds a freshly extracted descriptor and its association to the base of its media kind, returning its id
+    pub fn add_document(&mut self, filename: String, descriptor: MediaDescriptor) -> String {
+        let id = String::from(descriptor.get_id());
+        match descriptor {
+            MediaDescriptor::ImageDescriptor(descriptor) => {
+                self.add_image_association(id.clone(), filename);
+                self.add_image_descriptor(descriptor);
+            }
+            MediaDescriptor::SoundDescriptor(descriptor) => {
+                self.add_sound_association(id.clone(), filename);
+                self.add_sound_descriptor(descriptor);
+            }
+            MediaDescriptor::TextDescriptor(descriptor) => {
+                self.add_text_association(id.clone(), filename);
+                self.add_text_descriptor(descriptor);
+            }
+        }
+        id
+    }
+
+    /// Removes the association and the descriptor of a document
+    ///
+    /// The document disappears from the in-memory index at once, the next save records a tombstone for it until the index is compacted.
+    /// Returns false when no document of the index has this id.
+    pub fn remove_document(&mut self, id: &str) -> bool {
+        let removed_associations = vec![self.images_associations.remove(id), self.sounds_associations.remove(id), self.texts_associations.remove(id)];
+        self.fingerprints.remove(id);
+        self.metadata.remove(id);
+        let saved = self.saved;
+        let (images_removed, images_saved) = remove_from_base(&mut self.images_base, id, saved.map_or(0, |saved| saved.images));
+        let (sounds_removed, sounds_saved) = remove_from_base(&mut self.sounds_base, id, saved.map_or(0, |saved| saved.sounds));
+        let (texts_removed, texts_saved) = remove_from_base(&mut self.texts_base, id, saved.map_or(0, |saved| saved.texts));
+        if let Some(ref mut saved) = self.saved {
+            saved.images -= images_saved;
+            saved.sounds -= sounds_saved;
+            saved.texts -= texts_saved;
+        }
+
+        let removed = removed_associations.iter().any(Option::is_some) || images_removed + sounds_removed + texts_removed > 0;
+        if removed && self.saved.is_some() {
+            self.tombstones.push(String::from(id));
+        }
+        removed
+    }
+
+    /// Removes every document indexed from `filename`, given from the working directory, returning their ids
+    pub fn remove_path(&mut self, filename: &str) -> Vec<String> {
+        let filename = &self.collection_path(filename);
+        let ids: Vec<String> = self.images_associations
+                                   .iter()
+                                   .chain(self.sounds_associations.iter())
+                                   .chain(self.texts_associations.iter())
+                                   .filter(|&(_, associated_filename)| associated_filename == filename)
+                                   .map(|(id, _)| id.clone())
+                                   .collect();
+        for id in &ids {
+            self.remove_document(id);
+        }
+        ids
+    }
+
+    /// Ids and stored paths of the documents indexed from `filename`, given from the working directory, or from the files inside it, sorted by id
+    ///
+    /// The files of the documents are compared from the working directory, so the collection root itself holds every document stored relative to it.
+    pub fn find_documents_under(&self, filename: &str) -> Vec<(String, String)> {
+        let prefix = absolute_path(filename);
+        let mut documents: Vec<(String, String)> = self.images_associations
+                                                       .iter()
+                                                       .chain(self.sounds_associations.iter())
+                                                       .chain(self.texts_associations.iter())
+                                                       .filter(|&(_, associated_filename)| collection::replace_prefix(&absolute_path(&self.document_path(associated_filename)), &prefix, "").is_some())
+                                                       .map(|(id, associated_filename)| (id.clone(), associated_filename.clone()))
+                                                       .collect();
+        documents.sort();
+        documents
+    }
+
+    /// Associates a document to the file it was moved to, given from the working directory, keeping its id and its descriptor
+    ///
+    /// Returns false when no document has the id `id`.
+    pub fn move_document(&mut self, id: &str, filename: &str) -> bool {
+        let path = self.collection_path(filename);
+        let moved = match self.images_associations.get_mut(id).or(self.sounds_associations.get_mut(id)).or(self.texts_associations.get_mut(id)) {
+            Some(associated_filename) => {
+                *associated_filename = path;
+                true
+            }
+            None => false,
+        };
+        if moved && self.saved.is_some() {
+            self.updated_associations.push(String::from(id));
+        }
+        moved
+    }
+
+    /// Adds the documents of the index saved in `index_path`, under new ids so they never collide with the ids of this index
+    ///
+    /// A document whose path is already indexed, or whose file has the same size and hash as an indexed one, is skipped as a duplicate.
+    /// Contents can only be compared for the documents indexed with a fingerprint.
+    pub fn merge_index(&mut self, index_path: &str) -> Result<MergeReport, SriError> {
+        let mut other = ContextObject::new(self.config.clone());
+        try!(other.load_index_from(index_path));
+
+        let mut paths: HashMap<String, String> = HashMap::new();
+        for (id, filename) in self.images_associations.iter().chain(self.sounds_associations.iter()).chain(self.texts_associations.iter()) {
+            paths.insert(filename.clone(), id.clone());
+        }
+        let mut contents: HashMap<(u64, u64), String> = HashMap::new();
+        for (id, fingerprint) in &self.fingerprints {
+            contents.insert((fingerprint.get_size(), fingerprint.get_hash()), id.clone());
+        }
+
+        let mut descriptors: Vec<MediaDescriptor> = Vec::new();
+        descriptors.extend(other.images_base.drain(..).map(MediaDescriptor::ImageDescriptor));
+        descriptors.extend(other.sounds_base.drain(..).map(MediaDescriptor::SoundDescriptor));
+        descriptors.extend(other.texts_base.drain(..).map(MediaDescriptor::TextDescriptor));
+
+        let mut report = MergeReport { added: Vec::new(), duplicates: Vec::new(), orphans: Vec::new(), skipped_records: other.skipped_records.drain(..).collect() };
+        for mut descriptor in descriptors {
+            let merged_id = String::from(descriptor.get_id());
+            let filename = match other.get_association(&merged_id) {
+                Some(filename) => self.collection_path(&other.document_path(filename)),
+                None => {
+                    report.orphans.push(merged_id);
+                    continue;
+                }
+            };
+            let fingerprint = other.fingerprints.get(&merged_id).cloned();
+            let metadata = other.metadata.remove(&merged_id);
+            let duplicate = paths.get(&filename).or(fingerprint.and_then(|fingerprint| contents.get(&(fingerprint.get_size(), fingerprint.get_hash())))).cloned();
+            if let Some(id) = duplicate {
+                report.duplicates.push((merged_id, id));
+                continue;
+            }
+
+            let id = self.new_document_id(descriptor.get_kind(), &filename, fingerprint.as_ref());
+            descriptor.set_id(id.clone());
+            self.add_document(filename.clone(), descriptor);
+            paths.insert(filename, id.clone());
+            if let Some(fingerprint) = fingerprint {
+                self.set_fingerprint(&id, fingerprint);
+                contents.insert((fingerprint.get_size(), fingerprint.get_hash()), id.clone());
+            }
+            if let Some(metadata) = metadata {
+                self.set_metadata(&id, metadata);
+            }
+            report.added.push((merged_id, id));
+        }
+        Ok(report)
+    }
+
+    /// Collection root the paths of the documents are relative to, `None` when they are stored as they were given
+    pub fn get_collection_root(&self) -> Option<&CollectionRoot> {
+        self.collection_root.as_ref()
+    }
+
+    /// Path stored for a file given from the working directory
+    pub fn collection_path(&self, filename: &str) -> String {
+        match self.collection_root {
+            Some(ref root) => root.store_path(filename),
+            None => String::from(filename),
+        }
+    }
+
+    /// Path of the file of a document from the working directory, given the path stored for it
+    pub fn document_path(&self, stored_path: &str) -> String {
+        match self.collection_root {
+            Some(ref root) => root.resolve_path(stored_path),
+            None => String::from(stored_path),
+        }
+    }
+
+    /// Changes the collection root and rewrites the leading components of the stored paths, returning the `(id, former path, path)` of every rewritten one
+    ///
+    /// Each path is rewritten by the first of the `(old, new)` prefixes it starts with, then made relative to the new root when it is inside it.
+    /// The relative paths of an index without root were given from the working directory, they are resolved from it first.
+    /// The whole index is rewritten by the next save.
+    pub fn relocate(&mut self, root: Option<&str>, prefixes: &[(String, String)]) -> Vec<(String, String, String)> {
+        let had_root = self.collection_root.is_some();
+        if let Some(root) = root {
+            self.collection_root = Some(CollectionRoot::new(root, self.config.get_index()));
+        }
+        let mut rewritten = Vec::new();
+        {
+            let collection_root = &self.collection_root;
+            let associations = self.images_associations.iter_mut().chain(self.sounds_associations.iter_mut()).chain(self.texts_associations.iter_mut());
+            for (id, filename) in associations {
+                let mut path = prefixes.iter()
+                                       .filter_map(|&(ref old_prefix, ref new_prefix)| collection::replace_prefix(filename, old_prefix, new_prefix))
+                                       .next()
+                                       .unwrap_or_else(|| filename.clone());
+                if let Some(ref collection_root) = *collection_root {
+                    if !had_root || path.starts_with('/') {
+                        path = collection_root.store_path(&path);
+                    }
+                }
+                if path != *filename {
+                    rewritten.push((id.clone(), filename.clone(), path.clone()));
+                    *filename = path;
+                }
+            }
+        }
+        rewritten.sort();
+        if root.is_some() || !rewritten.is_empty() {
+            self.saved = None;
+        }
+        rewritten
+    }
+
+    /// Ids and paths of the documents whose file can't be found anymore, sorted by id
+    pub fn find_missing_documents(&self) -> Vec<(String, String)> {
+        let mut missing: Vec<(String, String)> = self.images_associations
+                                                     .iter()
+                                                     .chain(self.sounds_associations.iter())
+                                                     .chain(self.texts_associations.iter())
+                                                     .map(|(id, filename)| (id.clone(), self.document_path(filename)))
+                                                     .filter(|&(_, ref path)| !Path::new(path).is_file())
+                                                     .collect();
+        missing.sort();
+        missing
+    }
+
+    /// Id of the document indexed from `filename`
+    pub fn find_document(&self, filename: &str) -> Option<String> {
+        self.images_associations
+            .iter()
+            .chain(self.sounds_associations.iter())
+            .chain(self.texts_associations.iter())
+            .find(|&(_, associated_filename)| associated_filename == filename)
+            .map(|(id, _)| id.clone())
+    }
+
+    /// Fingerprint the file of a document had when it was indexed, unknown for the documents indexed before they were recorded
+    pub fn get_fingerprint(&self, id: &str) -> Option<&Fingerprint> {
+        self.fingerprints.get(id)
+    }
+
+    /// Records the fingerprint of the file of a document, saved with its association
+    pub fn set_fingerprint(&mut self, id: &str, fingerprint: Fingerprint) {
+        self.fingerprints.insert(String::from(id), fingerprint);
+        if self.saved.is_some() {
+            self.updated_associations.push(String::from(id));
+        }
+    }
+
+    /// Metadata found when the document was indexed
+    pub fn get_metadata(&self, id: &str) -> Option<&Metadata> {
+        self.metadata.get(id)
+    }
+
+    /// Records the metadata of a document, saved with its association
+    pub fn set_metadata(&mut self, id: &str, metadata: Metadata) {
+        self.metadata.insert(String::from(id), metadata);
+        if self.saved.is_some() {
+            self.updated_associations.push(String::from(id));
+        }
+    }
+
+    /// Metadata of a document completed with the size and the modification time of its file, as shown with results
+    pub fn describe_document(&self, id: &str) -> Metadata {
+        let mut description = self.metadata.get(id).cloned().unwrap_or_default();
+        if let Some(fingerprint) = self.fingerprints.get(id) {
+            description.set(metadata::SIZE, fingerprint.get_size());
+            description.set(metadata::MTIME, fingerprint.get_mtime());
+        }
+        description
+    }
+
+    /// Adds an association read from a saved index
+    fn add_loaded_association(&mut self, kind: MediaKind, association: LoadedAssociation) {
+        let (id, filename, fingerprint, metadata) = association;
+        self.register_loaded_id(kind, &id);
+        match fingerprint {
+            Some(fingerprint) => self.fingerprints.insert(id.clone(), fingerprint),
+            None => self.fingerprints.remove(&id),
+        };
+        match metadata.is_empty() {
+            true => self.metadata.remove(&id),
+            false => self.metadata.insert(id.clone(), metadata),
+        };
+        match kind {
+            MediaKind::Image => self.add_image_association(id, filename),
+            MediaKind::Sound => self.add_sound_association(id, filename),
+            MediaKind::Text => self.add_text_association(id, filename),
+        }
+    }
+
+    /// Removes from the loaded documents the ones a segment removed
+    fn apply_tombstones(&mut self, ids: &HashSet<String>) {
+        self.images_associations.retain(|id, _| !ids.contains(id));
+        self.sounds_associations.retain(|id, _| !ids.contains(id));
+        self.texts_associations.retain(|id, _| !ids.contains(id));
+        self.fingerprints.retain(|id, _| !ids.contains(id));
+        self.metadata.retain(|id, _| !ids.contains(id));
+        self.images_base.retain(|descriptor| !ids.contains(descriptor.get_id()));
+        self.sounds_base.retain(|descriptor| !ids.contains(descriptor.get_id()));
+        self.texts_base.retain(|descriptor| !ids.contains(descriptor.get_id()));
+    }
+
 
     pub fn get_images_associations(&self) -> &HashMap<String, String> {
         &self.images_associations
@@ -109,408 +728,490 @@ impl ContextObject {
 
 
 
-    pub fn get_config(&self) -> &HashMap<String, String> {
+    pub fn get_config(&self) -> &Config {
         &self.config
     }
-    pub fn get_param(&self, param: &str) -> Option<&String> {
-        self.config.get(param)
+
+
+    /// Keeps `max_doc_id` above the number of every loaded sequential id, so new ids never collide with them
+    fn register_loaded_id(&mut self, kind: MediaKind, id: &str) {
+        let number = match id.starts_with(kind.get_prefix()) {
+            true => &id[kind.get_prefix().len()..],
+            false => "",
+        };
+        if number.starts_with(STABLE_ID_SEPARATOR) {
+            return;
+        }
+        match number.parse::<u64>() {
+            Ok(value) => self.max_doc_id = cmp::max(self.max_doc_id, value + 1),
+            Err(_) => self.warnings.push(format!("Wrong id for {} association '{}' in saved index", kind.get_name(), id)),
+        }
     }
-    pub fn set_param(&mut self, param: String, value: String) {
-        self.config.insert(param, value);
+
+    /// Locks the index directory of the config for writing until the context is dropped or `unlock_index` is called
+    ///
+    /// Only needed to keep other processes out of a sequence of loads and saves, `save_index` and `compact` taking the lock
+    /// by themselves for the time of the save. Fails with `SriError::Busy` when another process reads or writes the index.
+    pub fn lock_index(&mut self) -> Result<(), SriError> {
+        if self.lock.is_none() {
+            self.lock = Some(try!(IndexLock::acquire(self.config.get_index(), LockMode::Exclusive)));
+        }
+        Ok(())
     }
 
+    pub fn unlock_index(&mut self) {
+        self.lock = None;
+    }
 
-    pub fn complete_config(&mut self) {
-        match self.get_param("images_base_filename") {
-            Some(_) => (),
-            None => {
-                self.set_param(String::from("images_base_filename"),
-                               String::from(IMAGES_BASE_FILENAME));
-                ()
-            }
+    /// Runs `write` with the index of the config locked for writing, releasing the lock afterwards unless it was already held
+    fn with_index_locked<T, F: FnOnce(&mut ContextObject) -> Result<T, SriError>>(&mut self, write: F) -> Result<T, SriError> {
+        if self.lock.is_some() {
+            return write(self);
         }
-        match self.get_param("sounds_base_filename") {
-            Some(_) => (),
-            None => {
-                self.set_param(String::from("sounds_base_filename"),
-                               String::from(SOUNDS_BASE_FILENAME));
-                ()
+        try!(self.lock_index());
+        let result = write(self);
+        self.unlock_index();
+        result
+    }
+
+    /// Fails when another process saved the index since this context loaded or saved it, as saving would lose its documents
+    fn check_generation(&self, index_path: &str) -> Result<(), SriError> {
+        let saved = match self.saved {
+            Some(saved) => saved,
+            None => return Ok(()),
+        };
+        match Manifest::from_file(&format!("{}/{}", index_path, manifest::MANIFEST_FILENAME)) {
+            Ok(ref manifest) if manifest.get_generation() != saved.generation => {
+                Err(SriError::busy(format!("Index {} was saved by another process since it was loaded, load it again and retry", index_path)))
             }
+            _ => Ok(()),
         }
-        match self.get_param("texts_base_filename") {
-            Some(_) => (),
-            None => {
-                self.set_param(String::from("texts_base_filename"),
-                               String::from(TEXTS_BASE_FILENAME));
-                ()
-            }
+    }
+
+    /// Lock of the `index_path` directory for the time of a load or a save, `None` when this context already holds it
+    fn lock_directory(&self, index_path: &str, mode: LockMode) -> Result<Option<IndexLock>, SriError> {
+        match self.lock {
+            Some(ref lock) if lock.is_on(index_path) => Ok(None),
+            _ => IndexLock::acquire(index_path, mode).map(Some),
         }
+    }
 
-        match self.get_param("images_associations_filename") {
-            Some(_) => (),
-            None => {
-                self.set_param(String::from("images_associations_filename"),
-                               String::from(IMAGES_ASSOCIATION_FILENAME));
-                ()
-            }
+    /// Loads the index of the config, in whichever format it was saved
+    pub fn load_index(&mut self) -> Result<(), SriError> {
+        let index_path = String::from(self.config.get_index());
+        let manifest = try!(self.load_directory(&index_path));
+        let saved = manifest.map(|manifest| self.saved_state(manifest.get_generation()));
+        self.saved = saved;
+        Ok(())
+    }
+
+    /// Loads the index saved in the `index_path` directory, a missing directory meaning an empty index
+    ///
+    /// An interrupted save is finished or cancelled first, and files whose length does not match the manifest of the last save are refused, their checksums being left to `verify`.
+    pub fn load_index_from(&mut self, index_path: &str) -> Result<(), SriError> {
+        try!(self.load_directory(index_path));
+        Ok(())
+    }
+
+    /// Loads an index directory under a shared lock, returning its manifest when it has one
+    ///
+    /// An interrupted save is finished or cancelled first under an exclusive lock, since it writes to the directory.
+    fn load_directory(&mut self, index_path: &str) -> Result<Option<Manifest>, SriError> {
+        if !Path::new(index_path).exists() {
+            return Ok(None);
         }
-        match self.get_param("sounds_associations_filename") {
-            Some(_) => (),
+        if manifest::is_interrupted(index_path) {
+            let _lock = try!(self.lock_directory(index_path, LockMode::Exclusive));
+            try!(manifest::recover(index_path));
+        }
+        let _lock = try!(self.lock_directory(index_path, LockMode::Shared));
+        self.read_directory(index_path, index_path)
+    }
+
+    /// Loads the main files of the index saved in `path` then each of its segments, without locking it
+    ///
+    /// The tombstones of a segment remove the documents loaded before it, then its own documents are added.
+    /// A relative collection root is relative to `index_path`, which differs from `path` for a snapshot.
+    fn read_directory(&mut self, path: &str, index_path: &str) -> Result<Option<Manifest>, SriError> {
+        let manifest = try!(manifest::load_manifest(path));
+        match manifest {
+            Some(ref manifest) => {
+                try!(self.load_files(path, manifest.get_format()));
+                for segment in manifest.get_segments() {
+                    let segment_path = format!("{}/{}", path, segment.get_name());
+                    let tombstones_path = format!("{}/{}", segment_path, self.config.get_tombstones_filename());
+                    if Path::new(&tombstones_path).exists() {
+                        let tombstones = try!(load_tombstones(&tombstones_path));
+                        self.apply_tombstones(&tombstones);
+                    }
+                    try!(self.load_files(&segment_path, segment.get_format()));
+                }
+            }
             None => {
-                self.set_param(String::from("sounds_associations_filename"),
-                               String::from(SOUNDS_ASSOCIATION_FILENAME));
-                ()
+                if let Some(format) = self.find_index_format(path) {
+                    try!(self.load_files(path, format));
+                }
             }
         }
-        match self.get_param("texts_associations_filename") {
-            Some(_) => (),
-            None => {
-                self.set_param(String::from("texts_associations_filename"),
-                               String::from(TEXTS_ASSOCIATION_FILENAME));
-                ()
+        let root_path = format!("{}/{}", path, self.config.get_root_filename());
+        if Path::new(&root_path).exists() {
+            let root = try!(load_root(&root_path));
+            if self.config.get_collection_root().map_or(false, |config_root| config_root != root) {
+                self.warnings.push(format!("The index keeps its collection root {}, relocate it to change it", root));
+            }
+            self.collection_root = Some(CollectionRoot::new(&root, index_path));
+        } else if manifest.is_some() || !(self.images_associations.is_empty() && self.sounds_associations.is_empty() && self.texts_associations.is_empty()) {
+            // An index saved without root stores the paths as they were given, a root of the config only applies to new indexes
+            if self.collection_root.take().is_some() {
+                self.warnings.push(String::from("The index has no collection root, relocate it to give it one"));
             }
         }
-
-        match self.get_param("min_word_size") {
-            Some(_) => (),
-            None => {
-                self.set_param(String::from("min_word_size"),
-                               String::from(MIN_WORD_SIZE));
-                ()
+        let aliases_path = format!("{}/{}", path, self.config.get_aliases_filename());
+        if Path::new(&aliases_path).exists() {
+            for (alias, id, _, _) in try!(load_associations(&aliases_path, self.config.get_load_mode(), &mut self.skipped_records)) {
+                self.aliases.insert(alias, id);
             }
         }
+        Ok(manifest)
+    }
 
-        match self.get_param("max_word_per_text") {
-            Some(_) => (),
-            None => {
-                self.set_param(String::from("max_word_per_text"),
-                               String::from(MAX_WORD_PER_TEXT));
-                ()
-            }
+    fn load_files(&mut self, path: &str, format: IndexFormat) -> Result<(), SriError> {
+        match format {
+            IndexFormat::Text => self.load_text_index(path),
+            IndexFormat::Binary => self.load_binary_index(path),
         }
+    }
 
+    /// Format of the index saved in `index_path`, `None` when there is no index there
+    pub fn find_index_format(&self, index_path: &str) -> Option<IndexFormat> {
+        if !Path::new(index_path).exists() {
+            return None;
+        }
+        if let Ok(manifest) = Manifest::from_file(&format!("{}/{}", index_path, manifest::MANIFEST_FILENAME)) {
+            return Some(manifest.get_format());
+        }
+        let config = &self.config;
+        let exists = |filename: &str| Path::new(&format!("{}/{}", index_path, filename)).exists();
+        if exists(config.get_binary_index_filename()) {
+            Some(IndexFormat::Binary)
+        } else if [config.get_images_associations_filename(), config.get_sounds_associations_filename(), config.get_texts_associations_filename()].iter().any(|filename| exists(filename)) {
+            Some(IndexFormat::Text)
+        } else {
+            None
+        }
     }
 
-    pub fn load_index(&mut self) {
-        let index_path;
-        match self.get_param("index") {
-            Some(value) => index_path = value.clone(),
-            None => panic!("Can't find parameter 'index' in config"),
+    fn load_text_index(&mut self, index_path: &str) -> Result<(), SriError> {
+        let config = self.config.clone();
+        let file_path = |filename: &str| format!("{}/{}", index_path, filename);
+
+        for association in try!(load_associations(&file_path(config.get_images_associations_filename()), config.get_load_mode(), &mut self.skipped_records)) {
+            self.add_loaded_association(MediaKind::Image, association);
+        }
+        for descriptor in try!(load_base::<ImageDescriptor>(&file_path(config.get_images_base_filename()), false, config.get_load_mode(), &mut self.skipped_records)) {
+            self.add_image_descriptor(descriptor);
         }
 
-        if !Path::new(&index_path).exists() {
-            return;
+        for association in try!(load_associations(&file_path(config.get_sounds_associations_filename()), config.get_load_mode(), &mut self.skipped_records)) {
+            self.add_loaded_association(MediaKind::Sound, association);
+        }
+        for descriptor in try!(load_base::<SoundDescriptor>(&file_path(config.get_sounds_base_filename()), true, config.get_load_mode(), &mut self.skipped_records)) {
+            self.add_sound_descriptor(descriptor);
         }
 
-        let mut associations_reader = match self.get_param("images_associations_filename") {
-            Some(value) => {
-                match File::open(format!("{}/{}", index_path, value)) {
-                    Ok(file) => BufReader::new(file),
-                    Err(e) => panic!("{}", e),
+        for association in try!(load_associations(&file_path(config.get_texts_associations_filename()), config.get_load_mode(), &mut self.skipped_records)) {
+            self.add_loaded_association(MediaKind::Text, association);
+        }
+        for descriptor in try!(load_base::<TextDescriptor>(&file_path(config.get_texts_base_filename()), true, config.get_load_mode(), &mut self.skipped_records)) {
+            self.add_text_descriptor(descriptor);
+        }
+        Ok(())
+    }
 
-                }
-            }
-            None => panic!("Can't find parameter 'images_associations_filename' in config"),
-        };
+    fn load_binary_index(&mut self, index_path: &str) -> Result<(), SriError> {
+        let path = format!("{}/{}", index_path, self.config.get_binary_index_filename());
+        let index = try!(binary_index::read_index(&path));
+        let in_file = |e: SriError| e.in_file(&path);
 
-        let mut file_reader = match self.get_param("images_base_filename") {
-            Some(value) => {
-                match File::open(format!("{}/{}", index_path, value)) {
-                    Ok(file) => BufReader::new(file),
-                    Err(e) => panic!("{}", e),
+        for association in try!(index.get_associations(MediaKind::Image).map_err(&in_file)) {
+            self.add_loaded_association(MediaKind::Image, association);
+        }
+        for descriptor in try!(index.get_base::<ImageDescriptor>().map_err(&in_file)) {
+            self.add_image_descriptor(descriptor);
+        }
 
-                }
-            }
-            None => panic!("Can't find parameter 'images_base_filename' in config"),
-        };
+        for association in try!(index.get_associations(MediaKind::Sound).map_err(&in_file)) {
+            self.add_loaded_association(MediaKind::Sound, association);
+        }
+        for descriptor in try!(index.get_base::<SoundDescriptor>().map_err(&in_file)) {
+            self.add_sound_descriptor(descriptor);
+        }
 
-        for line in associations_reader.lines() {
-            match line {
-                Ok(association) => {
-                    let mut fields = association.split("=");
-                    let id = fields.next().unwrap();
-                    let filename = fields.next().unwrap();
-                    self.add_image_association(String::from(id), String::from(filename));
-
-                    let string_number: String = id.chars().skip(3).collect();
-                    match string_number.parse::<u64>() {
-                        Ok(value) => self.max_doc_id = cmp::max(self.max_doc_id, value + 1),
-                        Err(_) => {
-                            println!("Warning: wrong id for image association '{}' in saved index",
-                                     association)
-                        }
-                    }
-                }
-                Err(e) => panic!(e),
-            }
+        for association in try!(index.get_associations(MediaKind::Text).map_err(&in_file)) {
+            self.add_loaded_association(MediaKind::Text, association);
         }
+        for descriptor in try!(index.get_base::<TextDescriptor>().map_err(&in_file)) {
+            self.add_text_descriptor(descriptor);
+        }
+        Ok(())
+    }
 
+    /// Saves the documents added since the last load or save
+    ///
+    /// They are appended to a new segment of the index with the tombstones of the removed documents, so the cost of a save only depends on how many documents changed.
+    /// The whole index is rewritten instead when it was not saved with a manifest yet, or when it already has `max_segments` segments.
+    /// The index is locked for writing during the save, which fails when another process saved it since it was loaded.
+    pub fn save_index(&mut self) -> Result<(), SriError> {
+        self.with_index_locked(ContextObject::save_locked_index)
+    }
 
-        loop {
-            match ImageDescriptor::from_file(&mut file_reader) {
-                Ok(value) => {
-                    match value {
-                        Some(image_descriptor) => self.add_image_descriptor(image_descriptor),
-                        None => {
-                            break;
-                        }
-                    }
-                }
-                Err(e) => {
-                    println!("{}", e);
+    fn save_locked_index(&mut self) -> Result<(), SriError> {
+        let index_path = String::from(self.config.get_index());
+        try!(self.check_generation(&index_path));
+        let append = match self.saved {
+            Some(saved) => {
+                match Manifest::from_file(&format!("{}/{}", index_path, manifest::MANIFEST_FILENAME)) {
+                    Ok(ref manifest) if manifest.get_segments().len() >= self.config.get_max_segments() => None,
+                    Ok(manifest) => Some((manifest, saved)),
+                    Err(_) => None,
                 }
             }
+            None => None,
+        };
+        match append {
+            Some((manifest, saved)) => self.append_segment(&index_path, manifest, saved),
+            None => self.compact_locked_index(),
         }
+    }
 
+    /// Rewrites the whole index in its directory, merging its segments back into its main files and dropping the removed documents
+    ///
+    /// Like `save_index`, it fails when another process saved the index since it was loaded.
+    pub fn compact(&mut self) -> Result<(), SriError> {
+        self.with_index_locked(ContextObject::compact_locked_index)
+    }
 
+    fn compact_locked_index(&mut self) -> Result<(), SriError> {
+        let index_path = String::from(self.config.get_index());
+        let format = self.config.get_index_format();
+        let manifest = try!(self.write_index(&index_path, format));
+        self.saved = Some(self.saved_state(manifest.get_generation()));
+        self.tombstones.clear();
+        self.updated_associations.clear();
+        self.skipped_records.clear();
+        self.keep_snapshot(&index_path, &manifest);
+        Ok(())
+    }
 
-
-        associations_reader = match self.get_param("sounds_associations_filename") {
-            Some(value) => {
-                match File::open(format!("{}/{}", index_path, value)) {
-                    Ok(file) => BufReader::new(file),
-                    Err(e) => panic!("{}", e),
-
-                }
+    /// Keeps the generation just saved as a snapshot, a failure only being reported since the save itself is complete
+    fn keep_snapshot(&self, index_path: &str, manifest: &Manifest) {
+        let max_snapshots = self.config.get_max_snapshots();
+        if max_snapshots > 0 {
+            if let Err(e) = snapshot::take_snapshot(index_path, manifest, max_snapshots, self.config.get_snapshot_copies()) {
+                warn(&format!("generation {} could not be kept as a snapshot ({})", manifest.get_generation(), e));
             }
-            None => panic!("Can't find parameter 'sounds_associations_filename' in config"),
-        };
-        file_reader = match self.get_param("sounds_base_filename") {
-            Some(value) => {
-                match File::open(format!("{}/{}", index_path, value)) {
-                    Ok(file) => BufReader::new(file),
-                    Err(e) => panic!("{}", e),
+        }
+    }
 
-                }
-            }
-            None => panic!("Can't find parameter 'sounds_base_filename' in config"),
-        };
+    /// Loads a snapshot of the index of the config, by name or by generation
+    pub fn load_snapshot(&mut self, name: &str) -> Result<(), SriError> {
+        let index_path = String::from(self.config.get_index());
+        let name = try!(snapshot::find_snapshot(&index_path, name));
+        let _lock = try!(self.lock_directory(&index_path, LockMode::Shared));
+        try!(self.read_directory(&snapshot::snapshot_path(&index_path, &name), &index_path));
+        Ok(())
+    }
 
-        for line in associations_reader.lines() {
-            match line {
-                Ok(association) => {
-                    let mut fields = association.split("=");
-                    let id = fields.next().unwrap();
-                    let filename = fields.next().unwrap();
-                    self.add_sound_association(String::from(id), String::from(filename));
-
-                    let string_number: String = id.chars().skip(3).collect();
-                    match string_number.parse::<u64>() {
-                        Ok(value) => self.max_doc_id = cmp::max(self.max_doc_id, value + 1),
-                        Err(_) => {
-                            println!("Warning: wrong id for sound association '{}' in saved index",
-                                     association)
-                        }
-                    }
-                }
-                Err(e) => panic!(e),
-            }
+    /// Replaces the index by one of its snapshots, saved as a new generation so the generations after it stay in their snapshots
+    ///
+    /// The index stays locked for writing from the load of the snapshot to the save.
+    pub fn rollback(&mut self, name: &str) -> Result<(), SriError> {
+        let mut restored = ContextObject::new(self.config.clone());
+        restored.lock = self.lock.take();
+        let result = restored.with_index_locked(|restored| {
+            try!(restored.load_snapshot(name));
+            restored.compact_locked_index()
+        });
+        if let Err(e) = result {
+            self.lock = restored.lock.take();
+            return Err(e);
         }
+        *self = restored;
+        Ok(())
+    }
 
-        loop {
-            let result = match SoundDescriptor::from_file(&mut file_reader) {
-                Ok(value) => {
-                    match value {
-                        Some(sound_descriptor) => Ok(sound_descriptor),
-                        None => {
-                            break;
-                        }
-                    }
-                }
-                Err(e) => Err(e),
+    fn append_segment(&mut self, index_path: &str, manifest: Manifest, saved: SavedState) -> Result<(), SriError> {
+        let generation = {
+            let updated = &self.updated_associations;
+            let part = IndexPart {
+                images_associations: self.associations_of(&self.images_associations, &self.images_base[saved.images..], updated),
+                sounds_associations: self.associations_of(&self.sounds_associations, &self.sounds_base[saved.sounds..], updated),
+                texts_associations: self.associations_of(&self.texts_associations, &self.texts_base[saved.texts..], updated),
+                images_base: &self.images_base[saved.images..],
+                sounds_base: &self.sounds_base[saved.sounds..],
+                texts_base: &self.texts_base[saved.texts..],
             };
-            match result {
-                Ok(sound_descriptor) => self.add_sound_descriptor(sound_descriptor),
-                Err(e) => {
-                    println!("{}", e);
-                    for line in (&mut file_reader).lines() {
-                        match line {
-                            Ok(line) => {
-                                match line.trim() {
-                                    "" => break,
-                                    _ => (),
-                                }
-                            }
-                            _ => (),
-                        }
-                    }
-                }
+            if part.is_empty() && self.tombstones.is_empty() {
+                return Ok(());
             }
-        }
-
-
-        associations_reader = match self.get_param("texts_associations_filename") {
-            Some(value) => {
-                match File::open(format!("{}/{}", index_path, value)) {
-                    Ok(file) => BufReader::new(file),
-                    Err(e) => panic!("{}", e),
-                }
+            let format = self.config.get_index_format();
+            let mut transaction = SaveTransaction::append(index_path, manifest);
+            let segment = try!(transaction.add_segment(format));
+            try!(part.write(&self.config, &mut transaction, &segment, format));
+            if !self.tombstones.is_empty() {
+                let tombstones_filename = format!("{}/{}", segment, self.config.get_tombstones_filename());
+                try!(save_tombstones(&transaction.file_path(&tombstones_filename), &self.tombstones));
             }
-            None => panic!("Can't find parameter 'texts_associations_filename' in config"),
+            let manifest = try!(transaction.commit());
+            self.keep_snapshot(index_path, &manifest);
+            manifest.get_generation()
         };
+        self.saved = Some(self.saved_state(generation));
+        self.tombstones.clear();
+        self.updated_associations.clear();
+        Ok(())
+    }
 
-        file_reader = match self.get_param("texts_base_filename") {
-            Some(value) => {
-                match File::open(format!("{}/{}", index_path, value)) {
-                    Ok(file) => BufReader::new(file),
-                    Err(e) => panic!("{}", e),
+    fn association_record<'a>(&'a self, id: &'a str, filename: &'a str) -> AssociationRecord<'a> {
+        (id, filename, self.fingerprints.get(id), self.metadata.get(id))
+    }
 
-                }
-            }
-            None => panic!("Can't find parameter 'texts_base_filename' in config"),
+    /// Every association, in the order of the base then by id for the ones without descriptor, so that saving the same index writes the same files
+    fn ordered_associations<'a, D: Descriptor>(&'a self, associations: &'a HashMap<String, String>, base: &'a [D]) -> Vec<AssociationRecord<'a>> {
+        let mut records = self.associations_of(associations, base, &[]);
+        let mut orphans: Vec<AssociationRecord<'a>> = {
+            let written: HashSet<&str> = records.iter().map(|&(id, _, _, _)| id).collect();
+            associations.iter().filter(|&(id, _)| !written.contains(id.as_str())).map(|(id, filename)| self.association_record(id, filename)).collect()
         };
+        orphans.sort_by(|a, b| a.0.cmp(b.0));
+        records.extend(orphans);
+        records
+    }
 
-        for line in associations_reader.lines() {
-            match line {
-                Ok(association) => {
-                    let mut fields = association.split("=");
-                    let id = fields.next().unwrap();
-                    let filename = fields.next().unwrap();
-                    self.add_text_association(String::from(id), String::from(filename));
-
-
-                    let string_number: String = id.chars().skip(3).collect();
-                    match string_number.parse::<u64>() {
-                        Ok(value) => self.max_doc_id = cmp::max(self.max_doc_id, value + 1),
-                        Err(_) => {
-                            println!("Warning: wrong id for text association '{}' in saved index",
-                                     association)
-                        }
-                    }
-                }
-                Err(e) => panic!(e),
-            }
-        }
-
-        loop {
-            let result = match TextDescriptor::from_file(&mut file_reader) {
-                Ok(value) => {
-                    match value {
-                        Some(text_descriptor) => Ok(text_descriptor),
-                        None => {
-                            break;
-                        }
-                    }
-                }
-                Err(e) => Err(e),
-            };
-            match result {
-                Ok(text_descriptor) => self.add_text_descriptor(text_descriptor),
-                Err(e) => {
-                    println!("{}", e);
-                    for line in (&mut file_reader).lines() {
-                        match line {
-                            Ok(line) => {
-                                match line.trim() {
-                                    "" => break,
-                                    _ => (),
-                                }
-                            }
-                            _ => (),
-                        }
-                    }
+    /// Associations of the documents of a part of a base, followed by the other associations among the `updated` ones
+    fn associations_of<'a, D: Descriptor>(&'a self, associations: &'a HashMap<String, String>, base: &'a [D], updated: &'a [String]) -> Vec<AssociationRecord<'a>> {
+        let mut records: Vec<AssociationRecord<'a>> = base.iter()
+                                                          .filter_map(|descriptor| associations.get(descriptor.get_id()).map(|filename| self.association_record(descriptor.get_id(), filename)))
+                                                          .collect();
+        let written: HashSet<&str> = records.iter().map(|&(id, _, _, _)| id).collect();
+        let mut updated_records = Vec::new();
+        for id in updated {
+            if let Some(filename) = associations.get(id) {
+                if !written.contains(id.as_str()) {
+                    updated_records.push(self.association_record(id, filename));
                 }
             }
         }
+        records.extend(updated_records);
+        records
     }
 
-    pub fn save_index(&self) {
-
-        let index_path;
-        match self.get_param("index") {
-            Some(value) => index_path = value.clone(),
-            None => panic!("Can't find parameter 'index' in config"),
+    fn saved_state(&self, generation: u64) -> SavedState {
+        SavedState {
+            generation: generation,
+            images: self.images_base.len(),
+            sounds: self.sounds_base.len(),
+            texts: self.texts_base.len(),
         }
+    }
 
+    /// Writes the whole index in the `index_path` directory, in the format of the config
+    pub fn save_index_to(&self, index_path: &str) -> Result<(), SriError> {
+        self.save_index_as(index_path, self.config.get_index_format())
+    }
 
-        let mut associations_writer = match self.get_param("images_associations_filename") {
-            Some(value) => {
-                match File::create(format!("{}/{}", index_path, value.clone())) {
-                    Ok(file) => file,
-                    Err(e) => panic!("{}", e),
-                }
-            }
-            None => panic!("Can't find parameter 'images_associations_filename' in config"),
-        };
+    /// Writes the whole index in the `index_path` directory in the given format, then removes the files of the other format
+    ///
+    /// Every file is written aside and only put in place once all of them are, so an interrupted save leaves the previous index intact.
+    pub fn save_index_as(&self, index_path: &str, format: IndexFormat) -> Result<(), SriError> {
+        try!(self.write_index(index_path, format));
+        Ok(())
+    }
 
-        let mut base_writer = match self.get_param("images_base_filename") {
-            Some(value) => {
-                match File::create(format!("{}/{}", index_path, value.clone())) {
-                    Ok(file) => file,
-                    Err(e) => panic!("{}", e),
-                }
-            }
-            None => panic!("Can't find parameter 'images_base_filename' in config"),
+    fn write_index(&self, index_path: &str, format: IndexFormat) -> Result<Manifest, SriError> {
+        let _lock = try!(self.lock_directory(index_path, LockMode::Exclusive));
+        if absolute_path(index_path) == absolute_path(self.config.get_index()) {
+            try!(self.check_generation(index_path));
+        }
+        let part = IndexPart {
+            images_associations: self.ordered_associations(&self.images_associations, &self.images_base),
+            sounds_associations: self.ordered_associations(&self.sounds_associations, &self.sounds_base),
+            texts_associations: self.ordered_associations(&self.texts_associations, &self.texts_base),
+            images_base: &self.images_base,
+            sounds_base: &self.sounds_base,
+            texts_base: &self.texts_base,
         };
-
-        for (id, filename) in &self.images_associations {
-            write!(associations_writer, "{}={}\n", id, filename).unwrap();
+        let mut aliases: Vec<AssociationRecord> = self.aliases
+                                                      .iter()
+                                                      .filter(|&(_, id)| self.get_association(id).is_some())
+                                                      .map(|(alias, id)| (alias.as_str(), id.as_str(), None, None))
+                                                      .collect();
+        aliases.sort_by(|a, b| a.0.cmp(b.0));
+        let mut transaction = try!(SaveTransaction::begin(index_path, format));
+        try!(part.write(&self.config, &mut transaction, "", format));
+        if !aliases.is_empty() {
+            try!(save_associations(&transaction.file_path(self.config.get_aliases_filename()), &aliases));
         }
-        for image_descriptor in &self.images_base {
-            image_descriptor.to_file(&mut base_writer);
+        if let Some(ref root) = self.collection_root {
+            try!(save_root(&transaction.file_path(self.config.get_root_filename()), root.path_from(index_path)));
         }
+        let manifest = try!(transaction.commit());
 
-
-
-
-
-        associations_writer = match self.get_param("sounds_associations_filename") {
-            Some(value) => {
-                match File::create(format!("{}/{}", index_path, value.clone())) {
-                    Ok(file) => file,
-                    Err(e) => panic!("{}", e),
-                }
-            }
-            None => panic!("Can't find parameter 'sounds_associations_filename' in config"),
-        };
-
-        base_writer = match self.get_param("sounds_base_filename") {
-            Some(value) => {
-                match File::create(format!("{}/{}", index_path, value.clone())) {
-                    Ok(file) => file,
-                    Err(e) => panic!("{}", e),
-
-                }
-            }
-            None => panic!("Can't find parameter 'sounds_base_filename' in config"),
-        };
-
-        for (id, filename) in &self.sounds_associations {
-            write!(associations_writer, "{}={}\n", id, filename).unwrap();
+        let config = &self.config;
+        if aliases.is_empty() {
+            try!(remove_index_files(index_path, &[config.get_aliases_filename()]));
         }
-        for sound_descriptor in &self.sounds_base {
-            sound_descriptor.to_file(&mut base_writer);
+        if self.collection_root.is_none() {
+            try!(remove_index_files(index_path, &[config.get_root_filename()]));
         }
-
-
-
-        associations_writer = match self.get_param("texts_associations_filename") {
-            Some(value) => {
-                match File::create(format!("{}/{}", index_path, value.clone())) {
-                    Ok(file) => file,
-                    Err(e) => panic!("{}", e),
-                }
+        try!(match format {
+            IndexFormat::Text => remove_index_files(index_path, &[config.get_binary_index_filename()]),
+            IndexFormat::Binary => {
+                remove_index_files(index_path,
+                                   &[config.get_images_associations_filename(),
+                                     config.get_images_base_filename(),
+                                     config.get_sounds_associations_filename(),
+                                     config.get_sounds_base_filename(),
+                                     config.get_texts_associations_filename(),
+                                     config.get_texts_base_filename()])
             }
-            None => panic!("Can't find parameter 'texts_associations_filename' in config"),
-        };
+        });
+        Ok(manifest)
+    }
 
-        base_writer = match self.get_param("texts_base_filename") {
-            Some(value) => {
-                match File::create(format!("{}/{}", index_path, value.clone())) {
-                    Ok(file) => file,
-                    Err(e) => panic!("{}", e),
+    /// Errors of the records of the text files left out by a lenient loading, with their file, line and column, until a compaction drops them
+    pub fn get_skipped_records(&self) -> &[SriError] {
+        &self.skipped_records
+    }
 
-                }
-            }
-            None => panic!("Can't find parameter 'texts_base_filename' in config"),
-        };
+    /// Problems of the config and of the loaded index that did not stop the context from working, in the order they were found
+    pub fn get_warnings(&self) -> &[String] {
+        &self.warnings
+    }
 
-        for (id, filename) in &self.texts_associations {
-            write!(associations_writer, "{}={}\n", id, filename).unwrap();
-        }
-        for text_descriptor in &self.texts_base {
-            text_descriptor.to_file(&mut base_writer);
+    /// Keeps only the last loaded descriptor of the ids found several times in the base of a media kind, returning these ids
+    pub fn remove_duplicate_descriptors(&mut self) -> Vec<String> {
+        let mut duplicates = Vec::new();
+        duplicates.extend(remove_base_duplicates(&mut self.images_base));
+        duplicates.extend(remove_base_duplicates(&mut self.sounds_base));
+        duplicates.extend(remove_base_duplicates(&mut self.texts_base));
+        if !duplicates.is_empty() {
+            self.saved = None;
         }
+        duplicates.sort();
+        duplicates
+    }
+
+    /// Ids associated to a filename but without descriptor, and ids of descriptors without association
+    pub fn find_orphans(&self) -> (Vec<String>, Vec<String>) {
+        let mut orphan_associations = Vec::new();
+        let mut orphan_descriptors = Vec::new();
+        find_base_orphans(&self.images_associations, &self.images_base, &mut orphan_associations, &mut orphan_descriptors);
+        find_base_orphans(&self.sounds_associations, &self.sounds_base, &mut orphan_associations, &mut orphan_descriptors);
+        find_base_orphans(&self.texts_associations, &self.texts_base, &mut orphan_associations, &mut orphan_descriptors);
+        orphan_associations.sort();
+        orphan_descriptors.sort();
+        (orphan_associations, orphan_descriptors)
     }
 
 
@@ -582,3 +1283,516 @@ impl ContextObject {
         println!("");
     }
 }
+
+
+fn open_index_file(path: &str) -> Result<LineCountingReader<BufReader<File>>, SriError> {
+    match File::open(path) {
+        Ok(file) => Ok(LineCountingReader::new(BufReader::new(file))),
+        Err(e) => Err(SriError::io(path, e)),
+    }
+}
+
+/// Reads the `id=filename` lines of an associations file
+///
+/// The filename may be followed by tab separated `key=value` fields, `size`, `mtime` and `hash` giving the fingerprint of the file
+/// and the other ones the metadata of the document, with their delimiters escaped. The ids and filenames are escaped as well
+/// in the files starting with `ASSOCIATIONS_HEADER`, the older ones keeping them as they were given.
+fn load_associations(path: &str, mode: LoadMode, skipped: &mut Vec<SriError>) -> Result<Vec<LoadedAssociation>, SriError> {
+    let mut associations = Vec::new();
+    let mut escaped = false;
+    for (line_number, line) in try!(open_index_file(path)).lines().enumerate() {
+        let line = try!(line.map_err(|e| SriError::io(path, e)));
+        if line_number == 0 && line == ASSOCIATIONS_HEADER {
+            escaped = true;
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_association(&line, escaped) {
+            Ok(association) => associations.push(association),
+            Err(e) => try!(skip_record(e.in_file(path).at_line(line_number + 1), mode, skipped)),
+        }
+    }
+    Ok(associations)
+}
+
+fn parse_association(line: &str, escaped: bool) -> Result<LoadedAssociation, SriError> {
+    let unescape = |value: &str| {
+        match escaped {
+            true => metadata::unescape(value),
+            false => String::from(value),
+        }
+    };
+    let (id, value) = match metadata::split_field(line) {
+        Some((id, value)) if !id.is_empty() => (id, value),
+        _ => return Err(SriError::parse(format!("Invalid association '{}', expected ID=PATH", line)).at_column(1)),
+    };
+    let mut column = id.chars().count() + 2;
+    let mut values = value.split('\t');
+    let filename = values.next().unwrap_or("");
+    if filename.is_empty() {
+        return Err(SriError::parse(format!("Association of {} without path", id)).at_column(column));
+    }
+    column += filename.chars().count() + 1;
+
+    let (mut size, mut mtime, mut hash) = (None, None, None);
+    let mut document_metadata = Metadata::new();
+    for field in values {
+        let (key, value) = match metadata::split_field(field) {
+            Some(key_value) => key_value,
+            None => return Err(SriError::parse(format!("Invalid field '{}' of {}, expected KEY=VALUE", field, id)).at_column(column)),
+        };
+        let value_column = column + key.chars().count() + 1;
+        let invalid_value = |e: &ToString| SriError::parse(format!("Invalid {} of {} ({})", key, id, e.to_string())).at_column(value_column);
+        match key {
+            metadata::SIZE => size = Some(try!(value.parse::<u64>().map_err(|e| invalid_value(&e)))),
+            metadata::MTIME => mtime = Some(try!(value.parse::<u64>().map_err(|e| invalid_value(&e)))),
+            "hash" => hash = Some(try!(u64::from_str_radix(value, 16).map_err(|e| invalid_value(&e)))),
+            _ => document_metadata.set(key, metadata::unescape(value)),
+        }
+        column += field.chars().count() + 1;
+    }
+    let fingerprint = match (size, mtime, hash) {
+        (Some(size), Some(mtime), Some(hash)) => Some(Fingerprint::new(size, mtime, hash)),
+        _ => None,
+    };
+    Ok((unescape(id), unescape(filename), fingerprint, document_metadata))
+}
+
+/// Fails the loading on an unreadable record in strict mode, and keeps its error to leave it out in lenient mode
+fn skip_record(e: SriError, mode: LoadMode, skipped: &mut Vec<SriError>) -> Result<(), SriError> {
+    match mode {
+        LoadMode::Strict => Err(e),
+        LoadMode::Lenient => {
+            skipped.push(e);
+            Ok(())
+        }
+    }
+}
+
+/// Reads every descriptor of a base file
+///
+/// Unreadable records are handled by `skip_record`, when `records_end_with_blank_line` the reading resumes after the next blank line.
+fn load_base<D: Descriptor>(path: &str, records_end_with_blank_line: bool, mode: LoadMode, skipped: &mut Vec<SriError>) -> Result<Vec<D>, SriError> {
+    let mut base = Vec::new();
+    let mut file_reader = try!(open_index_file(path));
+    loop {
+        let record_line = file_reader.get_line() + 1;
+        match D::from_file(&mut file_reader) {
+            Ok(Some(descriptor)) => base.push(descriptor),
+            Ok(None) => break,
+            Err(SriError::Io(_, e)) => return Err(SriError::io(path, e)),
+            Err(e) => {
+                try!(skip_record(locate_record_error(e, path, record_line), mode, skipped));
+                if records_end_with_blank_line {
+                    for line in (&mut file_reader).lines() {
+                        match line {
+                            Ok(line) => {
+                                match line.trim() {
+                                    "" => break,
+                                    _ => (),
+                                }
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(base)
+}
+
+/// Turns the line of a parse error, counted from the header of its record, into a line of the base file
+fn locate_record_error(e: SriError, path: &str, record_line: usize) -> SriError {
+    match e {
+        SriError::Parse { line, column, message, .. } => SriError::Parse { file: String::from(path), line: record_line + cmp::max(line, 1) - 1, column: column, message: message },
+        e => e.in_file(path),
+    }
+}
+
+fn save_associations(path: &str, associations: &[AssociationRecord]) -> Result<(), SriError> {
+    let mut associations_writer = BufWriter::new(try!(File::create(path).map_err(|e| SriError::io(path, e))));
+    try!(writeln!(associations_writer, "{}", ASSOCIATIONS_HEADER).map_err(|e| SriError::io(path, e)));
+    for &(id, filename, fingerprint, document_metadata) in associations {
+        let mut line = format!("{}={}", metadata::escape(id), metadata::escape(filename));
+        if let Some(fingerprint) = fingerprint {
+            line.push_str(&format!("\t{}={}\t{}={}\thash={:016x}", metadata::SIZE, fingerprint.get_size(), metadata::MTIME, fingerprint.get_mtime(), fingerprint.get_hash()));
+        }
+        if let Some(document_metadata) = document_metadata {
+            for (key, value) in document_metadata.get_fields() {
+                line.push_str(&format!("\t{}={}", key, metadata::escape(value)));
+            }
+        }
+        try!(writeln!(associations_writer, "{}", line).map_err(|e| SriError::io(path, e)));
+    }
+    try!(associations_writer.flush().map_err(|e| SriError::io(path, e)));
+    Ok(())
+}
+
+fn save_base<D: Descriptor>(path: &str, base: &[D]) -> Result<(), SriError> {
+    let mut base_writer = BufWriter::new(try!(File::create(path).map_err(|e| SriError::io(path, e))));
+    for descriptor in base {
+        try!(descriptor.to_file(&mut base_writer).map_err(|e| e.in_file(path)));
+    }
+    try!(base_writer.flush().map_err(|e| SriError::io(path, e)));
+    Ok(())
+}
+
+/// Reads the ids of a tombstones file, one per line
+fn load_tombstones(path: &str) -> Result<HashSet<String>, SriError> {
+    let mut tombstones = HashSet::new();
+    for line in try!(open_index_file(path)).lines() {
+        let id = try!(line.map_err(|e| SriError::io(path, e)));
+        if !id.trim().is_empty() {
+            tombstones.insert(String::from(id.trim()));
+        }
+    }
+    Ok(tombstones)
+}
+
+/// Reads the collection root, the only line of its file
+fn load_root(path: &str) -> Result<String, SriError> {
+    for line in try!(open_index_file(path)).lines() {
+        let root = try!(line.map_err(|e| SriError::io(path, e)));
+        if !root.trim().is_empty() {
+            return Ok(String::from(root.trim()));
+        }
+    }
+    Err(SriError::parse("Empty collection root").in_file(path))
+}
+
+fn save_root(path: &str, root: &str) -> Result<(), SriError> {
+    let mut root_file = try!(File::create(path).map_err(|e| SriError::io(path, e)));
+    writeln!(root_file, "{}", root).map_err(|e| SriError::io(path, e))
+}
+
+fn save_tombstones(path: &str, tombstones: &[String]) -> Result<(), SriError> {
+    let mut tombstones_writer = BufWriter::new(try!(File::create(path).map_err(|e| SriError::io(path, e))));
+    for id in tombstones {
+        try!(write!(tombstones_writer, "{}\n", id).map_err(|e| SriError::io(path, e)));
+    }
+    try!(tombstones_writer.flush().map_err(|e| SriError::io(path, e)));
+    Ok(())
+}
+
+/// Removes the descriptors of `id` from a base, returning how many were removed and how many of them were among its `saved_length` first ones
+fn remove_from_base<D: Descriptor>(base: &mut Vec<D>, id: &str, saved_length: usize) -> (usize, usize) {
+    let mut position = 0;
+    let mut removed = 0;
+    let mut removed_saved = 0;
+    base.retain(|descriptor| {
+        let keep = descriptor.get_id() != id;
+        if !keep {
+            removed += 1;
+            if position < saved_length {
+                removed_saved += 1;
+            }
+        }
+        position += 1;
+        keep
+    });
+    (removed, removed_saved)
+}
+
+fn remove_index_files(index_path: &str, filenames: &[&str]) -> Result<(), SriError> {
+    for filename in filenames {
+        let path = format!("{}/{}", index_path, filename);
+        if Path::new(&path).exists() {
+            try!(fs::remove_file(&path).map_err(|e| SriError::io(&path, e)));
+        }
+    }
+    Ok(())
+}
+
+fn remove_base_duplicates<D: Descriptor>(base: &mut Vec<D>) -> Vec<String> {
+    let mut last_positions = HashMap::new();
+    for (position, descriptor) in base.iter().enumerate() {
+        last_positions.insert(String::from(descriptor.get_id()), position);
+    }
+    let mut duplicates = HashSet::new();
+    let mut position = 0;
+    base.retain(|descriptor| {
+        let keep = last_positions[descriptor.get_id()] == position;
+        if !keep {
+            duplicates.insert(String::from(descriptor.get_id()));
+        }
+        position += 1;
+        keep
+    });
+    duplicates.into_iter().collect()
+}
+
+fn find_base_orphans<D: Descriptor>(associations: &HashMap<String, String>, base: &[D], orphan_associations: &mut Vec<String>, orphan_descriptors: &mut Vec<String>) {
+    let described: HashSet<&str> = base.iter().map(Descriptor::get_id).collect();
+    for id in associations.keys() {
+        if !described.contains(id.as_str()) {
+            orphan_associations.push(id.clone());
+        }
+    }
+    for id in described {
+        if !associations.contains_key(id) {
+            orphan_descriptors.push(String::from(id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use includes::util::test_directory;
+
+    fn context(index_path: &str, format: &str) -> ContextObject {
+        let mut params = HashMap::new();
+        params.insert(String::from("index"), String::from(index_path));
+        params.insert(String::from("index_format"), String::from(format));
+        params.insert(String::from("max_snapshots"), String::from("0"));
+        ContextObject::new(Config::check(&params).into_result().unwrap())
+    }
+
+    /// Config of the index in `index_path` with one more parameter
+    fn config_with(index_path: &str, param: &str, value: &str) -> Config {
+        let mut params = HashMap::new();
+        params.insert(String::from("index"), String::from(index_path));
+        params.insert(String::from(param), String::from(value));
+        params.insert(String::from("max_snapshots"), String::from("0"));
+        Config::check(&params).into_result().unwrap()
+    }
+
+    fn loaded(index_path: &str, format: &str) -> ContextObject {
+        let mut context = context(index_path, format);
+        context.load_index().unwrap();
+        context
+    }
+
+    /// Adds a text document with a single word, returning its id
+    fn add_text(context: &mut ContextObject, filename: &str, word: &str) -> String {
+        let id = context.new_document_id(MediaKind::Text, filename, None);
+        let mut occurences = HashMap::new();
+        occurences.insert(String::from(word), 1);
+        context.add_document(String::from(filename), MediaDescriptor::TextDescriptor(TextDescriptor::from_occurences(id.clone(), 1, 1, occurences)));
+        context.set_fingerprint(&id, Fingerprint::new(1, 2, 3));
+        id
+    }
+
+    fn assert_busy<T>(result: Result<T, SriError>) {
+        match result {
+            Err(SriError::Busy(_)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("the index was not found busy"),
+        }
+    }
+
+    fn check_saves(index_path: &str, format: &str) {
+        let mut writer = context(index_path, format);
+        let first = add_text(&mut writer, "docs/a.txt", "alpha");
+        let second = add_text(&mut writer, "docs/b.txt", "beta");
+        writer.save_index().unwrap();
+
+        let reader = loaded(index_path, format);
+        assert_eq!(reader.get_texts_associations(), writer.get_texts_associations());
+        assert_eq!(reader.get_fingerprint(&first), Some(&Fingerprint::new(1, 2, 3)));
+        assert_eq!(reader.get_texts_base()[1].get_occurences().get("beta"), Some(&1));
+
+        assert!(writer.remove_document(&first));
+        let third = add_text(&mut writer, "docs/c.txt", "gamma");
+        writer.save_index().unwrap();
+        let reader = loaded(index_path, format);
+        let ids: Vec<&str> = reader.get_texts_base().iter().map(Descriptor::get_id).collect();
+        assert_eq!(ids, vec![second.as_str(), third.as_str()]);
+        assert!(reader.get_association(&first).is_none());
+
+        writer.compact().unwrap();
+        assert!(Manifest::from_file(&format!("{}/{}", index_path, manifest::MANIFEST_FILENAME)).unwrap().get_segments().is_empty());
+        assert_eq!(loaded(index_path, format).get_texts_associations(), writer.get_texts_associations());
+    }
+
+    #[test]
+    fn text_indexes_are_loaded_back() { check_saves(&test_directory("context-text"), "text"); }
+
+    #[test]
+    fn binary_indexes_are_loaded_back() { check_saves(&test_directory("context-binary"), "binary"); }
+
+    #[test]
+    fn removed_paths_leave_tombstones_until_compaction() {
+        let index_path = test_directory("context-tombstones");
+        let mut writer = context(&index_path, "text");
+        let first = add_text(&mut writer, "docs/a.txt", "alpha");
+        let second = add_text(&mut writer, "docs/b.txt", "beta");
+        writer.save_index().unwrap();
+
+        assert_eq!(writer.remove_path("docs/a.txt"), vec![first.clone()]);
+        assert!(writer.remove_path("docs/a.txt").is_empty());
+        assert!(!writer.remove_document(&first));
+        writer.save_index().unwrap();
+        let manifest = Manifest::from_file(&format!("{}/{}", index_path, manifest::MANIFEST_FILENAME)).unwrap();
+        let tombstones_path = format!("{}/{}/{}", index_path, manifest.get_segments()[0].get_name(), writer.config.get_tombstones_filename());
+        assert_eq!(load_tombstones(&tombstones_path).unwrap(), vec![first.clone()].into_iter().collect());
+
+        let reader = loaded(&index_path, "text");
+        assert!(reader.get_association(&first).is_none());
+        let ids: Vec<&str> = reader.get_texts_base().iter().map(Descriptor::get_id).collect();
+        assert_eq!(ids, vec![second.as_str()]);
+
+        writer.compact().unwrap();
+        assert!(!Path::new(&tombstones_path).exists());
+        let reader = loaded(&index_path, "text");
+        assert!(reader.get_association(&first).is_none());
+        assert_eq!(reader.get_texts_associations().len(), 1);
+    }
+
+    #[test]
+    fn merged_documents_get_new_ids_and_duplicates_are_skipped() {
+        let directory = test_directory("context-merge");
+        let mut target = context(&format!("{}/target", directory), "text");
+        let indexed = add_text(&mut target, "docs/a.txt", "alpha");
+
+        let merged_path = format!("{}/merged", directory);
+        let mut merged = context(&merged_path, "text");
+        let same_content = add_text(&mut merged, "docs/copy.txt", "alpha");
+        let same_path = add_text(&mut merged, "docs/a.txt", "beta");
+        merged.set_fingerprint(&same_path, Fingerprint::new(4, 5, 6));
+        let new = add_text(&mut merged, "docs/c.txt", "gamma");
+        merged.set_fingerprint(&new, Fingerprint::new(7, 8, 9));
+        let orphan = add_text(&mut merged, "docs/d.txt", "delta");
+        merged.texts_associations.remove(&orphan);
+        merged.save_index().unwrap();
+
+        let report = target.merge_index(&merged_path).unwrap();
+        assert_eq!(report.get_duplicates(), &[(same_content, indexed.clone()), (same_path, indexed.clone())][..]);
+        assert_eq!(report.get_orphans(), &[orphan][..]);
+        assert_eq!(report.get_added().len(), 1);
+        let id = &report.get_added()[0].1;
+        assert_eq!(report.get_added()[0].0, new);
+        assert!(id != &indexed);
+        assert_eq!(target.get_association(id).map(String::as_str), Some("docs/c.txt"));
+        assert_eq!(target.get_fingerprint(id), Some(&Fingerprint::new(7, 8, 9)));
+        let ids: Vec<&str> = target.get_texts_base().iter().map(Descriptor::get_id).collect();
+        assert_eq!(ids, vec![indexed.as_str(), id.as_str()]);
+    }
+
+    #[test]
+    fn stable_ids_follow_the_content_or_the_path() {
+        let fingerprint = Fingerprint::new(1, 2, 3);
+        let mut by_content = ContextObject::new(config_with("unsaved", "id_scheme", "content"));
+        let id = by_content.new_document_id(MediaKind::Text, "docs/a.txt", Some(&fingerprint));
+        assert_eq!(id, format!("txt_{:016x}", fingerprint.get_hash()));
+        by_content.add_text_association(id.clone(), String::from("docs/a.txt"));
+        assert_eq!(by_content.new_document_id(MediaKind::Text, "docs/a.txt", Some(&fingerprint)), id);
+        assert_eq!(by_content.new_document_id(MediaKind::Text, "docs/copy.txt", Some(&fingerprint)), format!("{}_1", id));
+        assert_eq!(by_content.new_document_id(MediaKind::Text, "docs/b.txt", None), "txt0");
+
+        let mut by_path = ContextObject::new(config_with("unsaved", "id_scheme", "path"));
+        let id = by_path.new_document_id(MediaKind::Image, "docs/./a.png", Some(&fingerprint));
+        assert_eq!(by_path.new_document_id(MediaKind::Image, "docs/a.png", Some(&Fingerprint::new(4, 5, 6))), id);
+        assert!(id.starts_with("img_"));
+        assert!(ContextObject::new(config_with("unsaved", "id_scheme", "sequential")).stable_id(MediaKind::Text, "docs/a.txt", &fingerprint).is_none());
+    }
+
+    #[test]
+    fn former_ids_stay_aliases_of_the_stable_ids() {
+        let index_path = test_directory("context-stable-ids");
+        let mut context = context(&index_path, "text");
+        let first = add_text(&mut context, "docs/a.txt", "alpha");
+        let second = add_text(&mut context, "docs/b.txt", "beta");
+        context.save_index().unwrap();
+
+        context.config = config_with(&index_path, "id_scheme", "content");
+        let stable_id = format!("txt_{:016x}", Fingerprint::new(1, 2, 3).get_hash());
+        let renames: Vec<(String, String)> = context.assign_stable_ids().into_iter().map(Result::unwrap).collect();
+        assert_eq!(renames, vec![(first.clone(), stable_id.clone()), (second.clone(), format!("{}_1", stable_id))]);
+        assert!(context.assign_stable_ids().is_empty());
+        context.save_index().unwrap();
+
+        let mut reader = ContextObject::new(config_with(&index_path, "id_scheme", "content"));
+        reader.load_index().unwrap();
+        assert_eq!(reader.resolve_id(&first), stable_id);
+        assert_eq!(reader.get_association(reader.resolve_id(&second)).map(String::as_str), Some("docs/b.txt"));
+        assert_eq!(reader.get_texts_base()[0].get_id(), stable_id);
+        assert_eq!(reader.resolve_id("txt9"), "txt9");
+    }
+
+    #[test]
+    fn reserved_ids_are_never_handed_out() {
+        let mut context = context("unsaved", "text");
+        assert!(context.reserve_id(MediaKind::Text, "txt7"));
+        assert_eq!(context.gen_id(String::from("txt")), "txt8");
+        assert!(context.reserve_id(MediaKind::Text, "txt_0123"));
+        assert!(!context.reserve_id(MediaKind::Text, "img3"));
+        assert!(!context.reserve_id(MediaKind::Text, "txt_"));
+        assert!(!context.reserve_id(MediaKind::Text, "txtx"));
+        let id = add_text(&mut context, "docs/a.txt", "alpha");
+        assert!(!context.reserve_id(MediaKind::Text, &id));
+    }
+
+    #[test]
+    fn collections_move_with_their_index() {
+        let directory = test_directory("context-collection");
+        let index_path = format!("{}/collection/index", directory);
+        let mut context = ContextObject::new(config_with(&index_path, "collection_root", ".."));
+        let inside = context.collection_path(&format!("{}/collection/docs/./a.txt", directory));
+        let outside = context.collection_path(&format!("{}/outside/b.txt", directory));
+        assert_eq!(inside, "docs/a.txt");
+        assert_eq!(outside, format!("{}/outside/b.txt", directory));
+        add_text(&mut context, &inside, "alpha");
+        let outside_id = add_text(&mut context, &outside, "beta");
+        context.save_index().unwrap();
+
+        fs::rename(format!("{}/collection", directory), format!("{}/moved", directory)).unwrap();
+        let index_path = format!("{}/moved/index", directory);
+        let mut moved = ContextObject::new(config_with(&index_path, "collection_root", ".."));
+        moved.load_index().unwrap();
+        assert!(moved.get_warnings().is_empty());
+        assert_eq!(moved.document_path("docs/a.txt"), format!("{}/moved/docs/a.txt", directory));
+
+        let prefixes = vec![(format!("{}/outside", directory), format!("{}/moved/outside", directory))];
+        assert_eq!(moved.relocate(None, &prefixes), vec![(outside_id.clone(), outside, String::from("outside/b.txt"))]);
+        assert!(moved.relocate(Some("/srv/collection"), &[]).is_empty());
+        assert_eq!(moved.document_path("docs/a.txt"), "/srv/collection/docs/a.txt");
+        moved.save_index().unwrap();
+
+        let mut reader = ContextObject::new(config_with(&index_path, "collection_root", ".."));
+        reader.load_index().unwrap();
+        assert_eq!(reader.get_collection_root().map(CollectionRoot::get_path), Some("/srv/collection"));
+        assert_eq!(reader.get_warnings().len(), 1);
+        assert_eq!(reader.get_association(&outside_id).map(String::as_str), Some("outside/b.txt"));
+    }
+
+    #[test]
+    fn saves_of_another_process_are_not_overwritten() {
+        let index_path = test_directory("context-conflict");
+        let mut first = context(&index_path, "text");
+        add_text(&mut first, "docs/a.txt", "alpha");
+        first.save_index().unwrap();
+
+        let mut second = loaded(&index_path, "text");
+        add_text(&mut first, "docs/b.txt", "beta");
+        first.save_index().unwrap();
+        add_text(&mut second, "docs/c.txt", "gamma");
+        assert_busy(second.save_index());
+
+        let mut second = loaded(&index_path, "text");
+        add_text(&mut second, "docs/c.txt", "gamma");
+        second.save_index().unwrap();
+        assert_eq!(loaded(&index_path, "text").get_texts_associations().len(), 3);
+    }
+
+    #[test]
+    fn locked_indexes_keep_other_contexts_out() {
+        let index_path = test_directory("context-lock");
+        let mut writer = context(&index_path, "text");
+        add_text(&mut writer, "docs/a.txt", "alpha");
+        writer.save_index().unwrap();
+
+        writer.lock_index().unwrap();
+        assert_busy(context(&index_path, "text").load_index());
+        add_text(&mut writer, "docs/b.txt", "beta");
+        writer.save_index().unwrap();
+        assert_busy(context(&index_path, "text").load_index());
+        writer.unlock_index();
+        assert_eq!(loaded(&index_path, "text").get_texts_associations().len(), 2);
+    }
+}
@@ -0,0 +1,292 @@
+use std::fs;
+use std::path::Path;
+
+use glob::Pattern;
+use walkdir::WalkDir;
+
+use includes::error::SriError;
+use includes::util::warn;
+
+/// How a crawl handles the symbolic links it meets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+	/// Links are ignored
+	Skip,
+	/// Links to files are indexed, links to directories are not crawled
+	Files,
+	/// Links are followed like the files and directories they point to, loops being detected
+	Follow,
+}
+
+impl SymlinkPolicy {
+	pub fn from_name(name: &str) -> Option<SymlinkPolicy> {
+		match name {
+			"skip" => Some(SymlinkPolicy::Skip),
+			"files" => Some(SymlinkPolicy::Files),
+			"follow" => Some(SymlinkPolicy::Follow),
+			_ => None,
+		}
+	}
+
+	pub fn get_name(&self) -> &'static str {
+		match *self {
+			SymlinkPolicy::Skip => "skip",
+			SymlinkPolicy::Files => "files",
+			SymlinkPolicy::Follow => "follow",
+		}
+	}
+}
+
+/// Finds the files of directory trees to index
+///
+/// The patterns are matched against the path of an entry relative to the crawled directory, `*` also matching `/`,
+/// so `*.jpg` selects the jpg files at any depth. An excluded directory is not crawled at all.
+pub struct Crawler {
+	includes: Vec<Pattern>,
+	excludes: Vec<Pattern>,
+	symlinks: SymlinkPolicy,
+	max_depth: Option<usize>,
+	hidden: bool,
+}
+
+/// Names of the components of `filename` below `directory`, `None` when it is not inside it
+fn relative_names(directory: &str, filename: &str) -> Option<Vec<String>> {
+	match Path::new(filename).strip_prefix(directory) {
+		Ok(relative_path) => Some(relative_path.components().map(|component| component.as_os_str().to_string_lossy().into_owned()).collect()),
+		Err(_) => None,
+	}
+}
+
+fn parse_pattern(pattern: &str) -> Result<Pattern, SriError> {
+	match Pattern::new(pattern) {
+		Ok(pattern) => Ok(pattern),
+		Err(e) => Err(SriError::config(format!("Invalid glob pattern '{}' ({})", pattern, e.msg))),
+	}
+}
+
+impl Crawler {
+	/// Crawler of every file but the hidden ones, skipping symbolic links, at any depth
+	pub fn new() -> Crawler { Crawler { includes: Vec::new(), excludes: Vec::new(), symlinks: SymlinkPolicy::Skip, max_depth: None, hidden: false } }
+
+	/// Only indexes the files matching one of the included patterns, every file being indexed when there is none
+	pub fn include(&mut self, pattern: &str) -> Result<(), SriError> {
+		self.includes.push(try!(parse_pattern(pattern)));
+		Ok(())
+	}
+
+	/// Leaves out the files and the directories matching the pattern
+	pub fn exclude(&mut self, pattern: &str) -> Result<(), SriError> {
+		self.excludes.push(try!(parse_pattern(pattern)));
+		Ok(())
+	}
+
+	pub fn set_symlinks(&mut self, symlinks: SymlinkPolicy) { self.symlinks = symlinks; }
+
+	/// Deepest level crawled, 1 only keeping the files directly in the directory
+	pub fn set_max_depth(&mut self, max_depth: Option<usize>) { self.max_depth = max_depth; }
+
+	/// Whether the files and directories whose name starts with a dot are crawled
+	pub fn set_hidden(&mut self, hidden: bool) { self.hidden = hidden; }
+
+	/// Paths of the files to index in a directory tree, sorted by name within each directory
+	///
+	/// Entries that can't be read are reported and skipped, only a directory that can't be crawled at all is an error.
+	pub fn crawl(&self, directory: &str) -> Result<Vec<String>, SriError> {
+		if !Path::new(directory).is_dir() {
+			return Err(SriError::config(format!("'{}' is not a directory", directory)));
+		}
+		Ok(self.walk(directory, directory, 0))
+	}
+
+	/// Paths of the files to index in `path`, a directory inside the crawled `directory`, the ones a crawl of `directory` would find there
+	///
+	/// Only `path` is walked, for a directory created or moved after the crawl.
+	pub fn crawl_under(&self, directory: &str, path: &str) -> Vec<String> {
+		let names = match relative_names(directory, path) {
+			Some(names) => names,
+			None => return Vec::new(),
+		};
+		if names.is_empty() || self.max_depth.map_or(false, |max_depth| names.len() >= max_depth) {
+			return Vec::new();
+		}
+		for depth in 1..names.len() + 1 {
+			if self.leaves_out(&names[depth - 1], &names[..depth].join("/")) {
+				return Vec::new();
+			}
+		}
+		let is_linked = fs::symlink_metadata(path).map(|metadata| metadata.file_type().is_symlink()).unwrap_or(false);
+		match (Path::new(path).is_dir(), is_linked && self.symlinks != SymlinkPolicy::Follow) {
+			(true, false) => self.walk(directory, path, names.len()),
+			_ => Vec::new(),
+		}
+	}
+
+	/// Files to index in `start`, which is `start_depth` levels below the crawled `directory`
+	fn walk(&self, directory: &str, start: &str, start_depth: usize) -> Vec<String> {
+		let mut walker = WalkDir::new(start).follow_links(self.symlinks == SymlinkPolicy::Follow).sort_by(|a, b| a.file_name().cmp(b.file_name()));
+		if let Some(max_depth) = self.max_depth {
+			walker = walker.max_depth(max_depth - start_depth);
+		}
+
+		let mut filenames = Vec::new();
+		let mut entries = walker.into_iter();
+		while let Some(entry) = entries.next() {
+			let entry = match entry {
+				Ok(entry) => entry,
+				Err(e) => {
+					warn(&e.to_string());
+					continue;
+				}
+			};
+			if entry.depth() == 0 {
+				continue;
+			}
+			let relative_path = match entry.path().strip_prefix(directory) {
+				Ok(relative_path) => relative_path.to_string_lossy().replace('\\', "/"),
+				Err(_) => continue,
+			};
+			let is_dir = entry.file_type().is_dir();
+			if self.leaves_out(&entry.file_name().to_string_lossy(), &relative_path) {
+				if is_dir {
+					entries.skip_current_dir();
+				}
+				continue;
+			}
+			if entry.path_is_symlink() && self.symlinks != SymlinkPolicy::Follow {
+				let links_to_file = self.symlinks == SymlinkPolicy::Files && fs::metadata(entry.path()).map(|metadata| metadata.is_file()).unwrap_or(false);
+				if !links_to_file {
+					continue;
+				}
+			} else if !entry.file_type().is_file() {
+				continue;
+			}
+			if self.is_included(&relative_path) {
+				filenames.push(entry.path().to_string_lossy().into_owned());
+			}
+		}
+		filenames
+	}
+
+	/// Tells whether a file would be found by crawling `directory`, for files created or moved after the crawl
+	///
+	/// Only the link itself is checked against the symlink policy, not the directories the path goes through.
+	pub fn accepts(&self, directory: &str, filename: &str) -> bool {
+		let names = match relative_names(directory, filename) {
+			Some(names) => names,
+			None => return false,
+		};
+		if names.is_empty() || self.max_depth.map_or(false, |max_depth| names.len() > max_depth) {
+			return false;
+		}
+		for depth in 1..names.len() + 1 {
+			if self.leaves_out(&names[depth - 1], &names[..depth].join("/")) {
+				return false;
+			}
+		}
+		let is_file = match fs::symlink_metadata(filename) {
+			Ok(ref metadata) if metadata.file_type().is_symlink() => self.symlinks != SymlinkPolicy::Skip && fs::metadata(filename).map(|metadata| metadata.is_file()).unwrap_or(false),
+			Ok(metadata) => metadata.is_file(),
+			Err(_) => false,
+		};
+		is_file && self.is_included(&names.join("/"))
+	}
+
+	/// Tells whether an entry is left out with everything inside it, given its name and its path relative to the crawled directory
+	fn leaves_out(&self, name: &str, relative_path: &str) -> bool { (name.starts_with('.') && !self.hidden) || self.excludes.iter().any(|pattern| pattern.matches(relative_path)) }
+
+	fn is_included(&self, relative_path: &str) -> bool { self.includes.is_empty() || self.includes.iter().any(|pattern| pattern.matches(relative_path)) }
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::fs::File;
+
+	use super::*;
+	use includes::util::test_directory;
+
+	/// Directory tree with a hidden file, a nested directory and a directory to exclude
+	fn collection(name: &str) -> String {
+		let directory = test_directory(name);
+		for path in &["b.jpg", "a.txt", ".hidden.txt", "sub/c.jpg", "sub/deeper/d.txt", "skipped/e.jpg"] {
+			let path = format!("{}/{}", directory, path);
+			fs::create_dir_all(Path::new(&path).parent().unwrap()).unwrap();
+			File::create(&path).unwrap();
+		}
+		directory
+	}
+
+	fn relative(directory: &str, filenames: Vec<String>) -> Vec<String> { filenames.iter().map(|filename| String::from(&filename[directory.len() + 1..])).collect() }
+
+	#[test]
+	fn every_visible_file_is_crawled_in_order() {
+		let directory = collection("crawl-all");
+		let filenames = Crawler::new().crawl(&directory).unwrap();
+		assert_eq!(relative(&directory, filenames), vec!["a.txt", "b.jpg", "skipped/e.jpg", "sub/c.jpg", "sub/deeper/d.txt"]);
+
+		let mut crawler = Crawler::new();
+		crawler.set_hidden(true);
+		assert!(relative(&directory, crawler.crawl(&directory).unwrap()).contains(&String::from(".hidden.txt")));
+	}
+
+	#[test]
+	fn patterns_and_depth_select_the_files() {
+		let directory = collection("crawl-patterns");
+		let mut crawler = Crawler::new();
+		crawler.include("*.jpg").unwrap();
+		crawler.exclude("skipped").unwrap();
+		assert_eq!(relative(&directory, crawler.crawl(&directory).unwrap()), vec!["b.jpg", "sub/c.jpg"]);
+
+		crawler.set_max_depth(Some(1));
+		assert_eq!(relative(&directory, crawler.crawl(&directory).unwrap()), vec!["b.jpg"]);
+		assert!(Crawler::new().include("[").is_err());
+	}
+
+	#[test]
+	fn accepted_files_are_the_crawled_ones() {
+		let directory = collection("crawl-accepts");
+		let mut crawler = Crawler::new();
+		crawler.exclude("skipped").unwrap();
+		crawler.set_max_depth(Some(2));
+		for path in &["a.txt", "sub/c.jpg"] {
+			assert!(crawler.accepts(&directory, &format!("{}/{}", directory, path)), "{} is refused", path);
+		}
+		for path in &[".hidden.txt", "skipped/e.jpg", "sub/deeper/d.txt", "missing.txt", "sub"] {
+			assert!(!crawler.accepts(&directory, &format!("{}/{}", directory, path)), "{} is accepted", path);
+		}
+		assert!(!crawler.accepts(&directory, "/elsewhere/a.txt"));
+	}
+
+	#[test]
+	fn directories_crawled_alone_give_the_files_of_the_whole_crawl() {
+		let directory = collection("crawl-under");
+		let mut crawler = Crawler::new();
+		crawler.include("*.txt").unwrap();
+		assert_eq!(relative(&directory, crawler.crawl_under(&directory, &format!("{}/sub", directory))), vec!["sub/deeper/d.txt"]);
+		crawler.exclude("sub/deeper").unwrap();
+		assert!(crawler.crawl_under(&directory, &format!("{}/sub", directory)).is_empty());
+
+		let mut crawler = Crawler::new();
+		crawler.set_max_depth(Some(2));
+		assert_eq!(relative(&directory, crawler.crawl_under(&directory, &format!("{}/sub", directory))), vec!["sub/c.jpg"]);
+		assert!(crawler.crawl_under(&directory, &format!("{}/sub/deeper", directory)).is_empty());
+		assert!(crawler.crawl_under(&directory, "/elsewhere").is_empty());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn symbolic_links_follow_the_policy() {
+		use std::os::unix::fs::symlink;
+		let directory = collection("crawl-links");
+		symlink(format!("{}/a.txt", directory), format!("{}/link.txt", directory)).unwrap();
+		symlink(format!("{}/sub", directory), format!("{}/linked", directory)).unwrap();
+
+		let mut crawler = Crawler::new();
+		crawler.include("*.txt").unwrap();
+		assert_eq!(relative(&directory, crawler.crawl(&directory).unwrap()), vec!["a.txt", "sub/deeper/d.txt"]);
+		crawler.set_symlinks(SymlinkPolicy::Files);
+		assert_eq!(relative(&directory, crawler.crawl(&directory).unwrap()), vec!["a.txt", "link.txt", "sub/deeper/d.txt"]);
+		crawler.set_symlinks(SymlinkPolicy::Follow);
+		assert_eq!(relative(&directory, crawler.crawl(&directory).unwrap()), vec!["a.txt", "link.txt", "linked/deeper/d.txt", "sub/deeper/d.txt"]);
+	}
+}
@@ -0,0 +1,47 @@
+use std::io::BufRead;
+use std::io::Write;
+
+use rustc_serialize::json::Json;
+
+use includes::MediaDescriptor;
+use includes::MediaKind;
+use includes::binary_index::RecordReader;
+use includes::binary_index::RecordWriter;
+use includes::error::SriError;
+use includes::metadata::Metadata;
+use includes::config::Config;
+
+/// Behaviour shared by the image, sound and text descriptors
+///
+/// Descriptors are extracted from the documents of the formats of the `registry`, saved to and loaded back from the index base files, either as text or as binary records, and compared to each other when querying.
+pub trait Descriptor: Sized {
+	/// Media kind of the descriptor, also giving the prefix of its ids
+	fn media_kind() -> MediaKind;
+
+	fn get_id(&self) -> &str;
+
+	/// Extracts the descriptor of a document of the given format, one of the names of the `registry`, filling the metadata it finds on the way
+	fn extract(config: &Config, format: &str, filename: &str, metadata: &mut Metadata) -> Result<Self, SriError>;
+
+	/// Reads the next descriptor of a base file, `None` meaning the end of the base was reached
+	///
+	/// The line of a parse error is counted from the header of the record, which is line 1.
+	fn from_file(file_reader: &mut BufRead) -> Result<Option<Self>, SriError>;
+
+	fn to_file(&self, file_writer: &mut Write) -> Result<(), SriError>;
+
+	/// Reads a descriptor from a record of a binary index
+	fn from_record(record: &mut RecordReader) -> Result<Self, SriError>;
+
+	fn to_record(&self, record: &mut RecordWriter);
+
+	/// Reads a descriptor from the `descriptor` object of a JSON Lines export, which holds everything but its id
+	fn from_json(id: String, payload: &Json) -> Result<Self, SriError>;
+
+	fn to_json(&self) -> Json;
+
+	/// Similarity between two descriptors, the higher the closer
+	fn compare_to(&self, other: &Self) -> f64;
+
+	fn into_media_descriptor(self) -> MediaDescriptor;
+}
@@ -0,0 +1,117 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Every failure the indexing, loading and querying can report
+#[derive(Debug)]
+pub enum SriError {
+	/// Missing or invalid configuration parameter
+	Config(String),
+	/// Input or output failure, with the path involved when known
+	Io(String, io::Error),
+	/// Malformed content in an index base, a list or a query, with its location when known
+	Parse {
+		file: String,
+		line: usize,
+		column: usize,
+		message: String,
+	},
+	/// Document that could not be decoded by its extractor
+	Decode {
+		file: String,
+		message: String,
+	},
+	/// Index locked by another process
+	Busy(String),
+}
+
+pub type SriResult<T> = Result<T, SriError>;
+
+impl SriError {
+	pub fn config<S: Into<String>>(message: S) -> SriError { SriError::Config(message.into()) }
+
+	pub fn io(path: &str, error: io::Error) -> SriError { SriError::Io(String::from(path), error) }
+
+	/// Parse error whose location is filled in later by `in_file` and `at_line`
+	pub fn parse<S: Into<String>>(message: S) -> SriError { SriError::Parse { file: String::new(), line: 0, column: 0, message: message.into() } }
+
+	pub fn busy<S: Into<String>>(message: S) -> SriError { SriError::Busy(message.into()) }
+
+	/// Decode error whose file is filled in later by `in_file`
+	pub fn decode<S: Into<String>>(message: S) -> SriError { SriError::Decode { file: String::new(), message: message.into() } }
+
+	/// Sets the file of the error, unless it already has one
+	pub fn in_file(self, filename: &str) -> SriError {
+		match self {
+			SriError::Io(ref path, _) if !path.is_empty() => self,
+			SriError::Io(_, error) => SriError::Io(String::from(filename), error),
+			SriError::Parse { ref file, .. } if !file.is_empty() => self,
+			SriError::Parse { line, column, message, .. } => SriError::Parse { file: String::from(filename), line: line, column: column, message: message },
+			SriError::Decode { ref file, .. } if !file.is_empty() => self,
+			SriError::Decode { message, .. } => SriError::Decode { file: String::from(filename), message: message },
+			SriError::Config(_) | SriError::Busy(_) => self,
+		}
+	}
+
+	/// Sets the line of a parse error, unless it already has one
+	pub fn at_line(self, line_number: usize) -> SriError {
+		match self {
+			SriError::Parse { file, line: 0, column, message } => SriError::Parse { file: file, line: line_number, column: column, message: message },
+			_ => self,
+		}
+	}
+
+	/// Sets the column of a parse error, counted in characters from 1, unless it already has one
+	pub fn at_column(self, column_number: usize) -> SriError {
+		match self {
+			SriError::Parse { file, line, column: 0, message } => SriError::Parse { file: file, line: line, column: column_number, message: message },
+			_ => self,
+		}
+	}
+}
+
+impl fmt::Display for SriError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			SriError::Config(ref message) => write!(f, "Configuration error: {}", message),
+			SriError::Io(ref path, ref error) if path.is_empty() => write!(f, "I/O error: {}", error),
+			SriError::Io(ref path, ref error) => write!(f, "I/O error on '{}': {}", path, error),
+			SriError::Parse { ref file, line, column, ref message } => {
+				let location = match (line, column) {
+					(0, _) => String::new(),
+					(_, 0) => format!(" line {}", line),
+					_ => format!(" line {} column {}", line, column),
+				};
+				match file.is_empty() {
+					true => write!(f, "Parse error{}: {}", location, message),
+					false => write!(f, "Parse error in '{}'{}: {}", file, location, message),
+				}
+			},
+			SriError::Decode { ref file, ref message } => write!(f, "Could not decode '{}': {}", file, message),
+			SriError::Busy(ref message) => write!(f, "Index busy: {}", message),
+		}
+	}
+}
+
+impl Error for SriError {
+	fn description(&self) -> &str {
+		match *self {
+			SriError::Config(_) => "configuration error",
+			SriError::Io(_, ref error) => error.description(),
+			SriError::Parse { .. } => "parse error",
+			SriError::Decode { .. } => "decode error",
+			SriError::Busy(_) => "index busy",
+		}
+	}
+
+	fn cause(&self) -> Option<&Error> {
+		match *self {
+			SriError::Io(_, ref error) => Some(error),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for SriError {
+	fn from(error: io::Error) -> SriError { SriError::Io(String::new(), error) }
+}
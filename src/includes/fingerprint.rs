@@ -0,0 +1,54 @@
+use std::io::Read;
+use std::fs;
+use std::fs::File;
+use std::time::UNIX_EPOCH;
+
+use includes::error::SriError;
+use includes::util::Fnv1a;
+
+/// Size, modification time and content hash of an indexed file, telling if it changed since it was indexed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+	size: u64,
+	mtime: u64,
+	hash: u64,
+}
+
+impl Fingerprint {
+	pub fn new(size: u64, mtime: u64, hash: u64) -> Fingerprint { Fingerprint { size: size, mtime: mtime, hash: hash } }
+
+	/// Size and modification time of a file, which are much cheaper to get than its hash
+	pub fn stat(filename: &str) -> Result<(u64, u64), SriError> {
+		let metadata = try!(fs::metadata(filename).map_err(|e| SriError::io(filename, e)));
+		let mtime = match metadata.modified().map(|modified| modified.duration_since(UNIX_EPOCH)) {
+			Ok(Ok(duration)) => duration.as_secs() * 1000 + (duration.subsec_nanos() / 1000000) as u64,
+			_ => 0,
+		};
+		Ok((metadata.len(), mtime))
+	}
+
+	/// Fingerprint of the current content of a file
+	pub fn of_file(filename: &str) -> Result<Fingerprint, SriError> {
+		let (size, mtime) = try!(Fingerprint::stat(filename));
+		let mut file = try!(File::open(filename).map_err(|e| SriError::io(filename, e)));
+		let mut hasher = Fnv1a::new();
+		let mut buffer = [0; 65536];
+		loop {
+			match try!(file.read(&mut buffer).map_err(|e| SriError::io(filename, e))) {
+				0 => break,
+				read => hasher.write(&buffer[..read]),
+			}
+		}
+		Ok(Fingerprint::new(size, mtime, hasher.finish()))
+	}
+
+	pub fn get_size(&self) -> u64 { self.size }
+
+	/// Modification time in milliseconds since the epoch
+	pub fn get_mtime(&self) -> u64 { self.mtime }
+
+	pub fn get_hash(&self) -> u64 { self.hash }
+
+	/// Tells if a file still has the size and the modification time it had, in which case its content is assumed unchanged
+	pub fn matches_stat(&self, size: u64, mtime: u64) -> bool { self.size == size && self.mtime == mtime }
+}
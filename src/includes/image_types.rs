@@ -1,7 +1,21 @@
 
 use std::io::BufRead;
 use std::io::Write;
-use std::fs::File;
+use std::collections::BTreeMap;
+use rustc_serialize::json::Json;
+use includes::descriptor::Descriptor;
+use includes::MediaDescriptor;
+use includes::MediaKind;
+use includes::binary_index::RecordReader;
+use includes::binary_index::RecordWriter;
+use includes::json_lines::get_array_field;
+use includes::json_lines::get_i32_array;
+use includes::json_lines::get_u64_field;
+use includes::error::SriError;
+use includes::metadata::Metadata;
+use includes::config::Config;
+use includes::registry;
+use includes::util::split_fields;
 
 
 pub const IMAGE_QUANT_LVL: usize = 64;
@@ -19,7 +33,26 @@ impl ImageDescriptor {
 	pub fn from_histogram(id: String, length: usize, source_histogram: [i32; IMAGE_QUANT_LVL]) -> ImageDescriptor { ImageDescriptor { id: id, pixels_amount: length, histogram: source_histogram } }
 
 
-	pub fn from_file(file_reader: &mut BufRead) -> Result<Option<ImageDescriptor>, String> {
+	/// Gives the descriptor the id of the document it replaces
+	pub fn set_id(&mut self, id: String) { self.id = id; }
+	pub fn get_pixels_amount(&self) -> usize { self.pixels_amount }
+	pub fn get_histogram(&self) -> &[i32; IMAGE_QUANT_LVL] { &self.histogram }
+}
+
+impl Descriptor for ImageDescriptor {
+	fn media_kind() -> MediaKind { MediaKind::Image }
+
+	fn get_id(&self) -> &str { &self.id }
+
+	fn extract(_config: &Config, format: &str, filename: &str, metadata: &mut Metadata) -> Result<ImageDescriptor, SriError> {
+		match format {
+			registry::IMAGE => ImageDescriptor::from_image_file(filename, metadata),
+			registry::PLAIN_TEXT_IMAGE => ImageDescriptor::from_plain_text_file(try!(registry::open_document(filename)), metadata),
+			_ => Err(SriError::decode(format!("Unknown image format {}", format)).in_file(filename)),
+		}
+	}
+
+	fn from_file(file_reader: &mut BufRead) -> Result<Option<ImageDescriptor>, SriError> {
 		let id: String;
 		let _quantification_levels: usize;
 		let mut histogram = [0; IMAGE_QUANT_LVL];
@@ -31,66 +64,112 @@ impl ImageDescriptor {
 				match header.trim() {
 					"" => return Ok(None),
 					_ => {
-						let mut header_fields = header.split_whitespace();
+						let mut header_fields = split_fields(&header).into_iter();
+						let header_end = header.trim_right().chars().count() + 1;
 						match header_fields.next() {
-							Some(value) => id = String::from(value),
-							None => return Err(format!("Invalid header ({})", header)),
+							Some((_, value)) => id = String::from(value),
+							None => return Err(SriError::parse(format!("Invalid header ({})", header.trim())).at_column(header_end)),
 						}
 						match header_fields.next() {
-							Some(value) => {
+							Some((column, value)) => {
 								match value.parse::<usize>() {
 									Ok(i) => _quantification_levels = i,
-									Err(e) => return Err(String::from(format!("Invalid value for header field 2 ({})", e))),
+									Err(e) => return Err(SriError::parse(format!("Invalid value for header field 2 ({})", e)).at_column(column)),
 								}
 							},
-							None => return Err(format!("Invalid header ({})", header)),
+							None => return Err(SriError::parse(format!("Invalid header ({})", header.trim())).at_column(header_end)),
 						}
 						match header_fields.next() {
-							Some(value) => {
+							Some((column, value)) => {
 								match value.parse::<usize>() {
 									Ok(i) => pixels_amount = i,
-									Err(e) => return Err(String::from(format!("Invalid value for header field 2 ({})", e))),
+									Err(e) => return Err(SriError::parse(format!("Invalid value for header field 3 ({})", e)).at_column(column)),
 								}
 							},
-							None => return Err(format!("Invalid header ({})", header)),
+							None => return Err(SriError::parse(format!("Invalid header ({})", header.trim())).at_column(header_end)),
 						}
 					},
 				}
 			},
-			Err(e) => return Err(format!("{}", e)),
+			Err(e) => return Err(SriError::from(e)),
 		}
 
 		let mut histogram_line = String::new();
 		match file_reader.read_line(&mut histogram_line) {
 			Ok(_) => {
-				for (index, value) in histogram_line.split_whitespace().enumerate() {
+				let values = split_fields(&histogram_line);
+				if values.len() < IMAGE_QUANT_LVL && !histogram_line.ends_with('\n') {
+					return Err(SriError::parse(format!("Truncated histogram of {}, {} values instead of {}", id, values.len(), IMAGE_QUANT_LVL)).at_line(2));
+				}
+				if values.len() != IMAGE_QUANT_LVL {
+					return Err(SriError::parse(format!("Histogram of {} has {} values instead of {}", id, values.len(), IMAGE_QUANT_LVL)).at_line(2));
+				}
+				for (index, (column, value)) in values.into_iter().enumerate() {
 					match value.parse::<i32>() {
 						Ok(i) => {
 							histogram[index] = i;
 						},
-						Err(e) => return Err(String::from(format!("Invalid value in histogram of {} ({})", id, e))),
+						Err(e) => return Err(SriError::parse(format!("Invalid value in histogram of {} ({})", id, e)).at_line(2).at_column(column)),
 					}
 				}
 			},
-			Err(e) => return Err(format!("{}", e)),
+			Err(e) => return Err(SriError::from(e)),
 		}
 
 		Ok(Some(ImageDescriptor::from_histogram(id, pixels_amount, histogram)))
 	}
 
-	pub fn get_id(&self) -> &str { &self.id }
-	pub fn get_pixels_amount(&self) -> usize { self.pixels_amount }
-	pub fn get_histogram(&self) -> &[i32; IMAGE_QUANT_LVL] { &self.histogram }
-
-	pub fn to_file(&self, file_writer: &mut File) {
-		write!(file_writer, "{} {} {}\n", self.get_id(), 64, self.get_pixels_amount()).unwrap();
+	fn to_file(&self, file_writer: &mut Write) -> Result<(), SriError> {
+		try!(write!(file_writer, "{} {} {}\n", self.get_id(), 64, self.get_pixels_amount()));
 		for value in self.get_histogram().into_iter() {
-			write!(file_writer, "{} ", value).unwrap();
+			try!(write!(file_writer, "{} ", value));
 		}
-		write!(file_writer, "\n").unwrap();
+		try!(write!(file_writer, "\n"));
+		Ok(())
 	}
 
-	pub fn compare_to(&self, other: &ImageDescriptor) -> f64 {
+	fn from_record(record: &mut RecordReader) -> Result<ImageDescriptor, SriError> {
+		let id = try!(record.read_string());
+		let pixels_amount = try!(record.read_u64()) as usize;
+		let quantification_levels = try!(record.read_u32()) as usize;
+		if quantification_levels != IMAGE_QUANT_LVL {
+			return Err(SriError::parse(format!("Histogram of {} has {} levels instead of {}", id, quantification_levels, IMAGE_QUANT_LVL)));
+		}
+		let mut histogram = [0; IMAGE_QUANT_LVL];
+		for value in histogram.iter_mut() {
+			*value = try!(record.read_i32());
+		}
+		Ok(ImageDescriptor::from_histogram(id, pixels_amount, histogram))
+	}
+
+	fn to_record(&self, record: &mut RecordWriter) {
+		record.write_str(self.get_id());
+		record.write_u64(self.get_pixels_amount() as u64);
+		record.write_u32(IMAGE_QUANT_LVL as u32);
+		for value in self.get_histogram().iter() {
+			record.write_i32(*value);
+		}
+	}
+
+	fn from_json(id: String, payload: &Json) -> Result<ImageDescriptor, SriError> {
+		let pixels_amount = try!(get_u64_field(payload, "pixels")) as usize;
+		let values = try!(get_i32_array(try!(get_array_field(payload, "histogram")), "histogram"));
+		if values.len() != IMAGE_QUANT_LVL {
+			return Err(SriError::parse(format!("Histogram of {} has {} levels instead of {}", id, values.len(), IMAGE_QUANT_LVL)));
+		}
+		let mut histogram = [0; IMAGE_QUANT_LVL];
+		histogram.copy_from_slice(&values);
+		Ok(ImageDescriptor::from_histogram(id, pixels_amount, histogram))
+	}
+
+	fn to_json(&self) -> Json {
+		let mut payload = BTreeMap::new();
+		payload.insert(String::from("pixels"), Json::U64(self.get_pixels_amount() as u64));
+		payload.insert(String::from("histogram"), Json::Array(self.get_histogram().iter().map(|value| Json::I64(*value as i64)).collect()));
+		Json::Object(payload)
+	}
+
+	fn compare_to(&self, other: &ImageDescriptor) -> f64 {
 
 		let self_weight = self.get_pixels_amount() as f64;
 		let other_weight = self.get_pixels_amount() as f64;
@@ -104,6 +183,8 @@ impl ImageDescriptor {
 
 		return score;
 	}
+
+	fn into_media_descriptor(self) -> MediaDescriptor { MediaDescriptor::ImageDescriptor(self) }
 }
 
 pub fn quantification_image(r_int: u32, g_int: u32, b_int: u32) -> Result<u32, String> {
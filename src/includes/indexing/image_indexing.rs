@@ -10,17 +10,25 @@ use image;
 use image::Pixel;
 use image::GenericImage;
 
-use super::super::context_types::ContextObject;
+use includes::error::SriError;
+use includes::metadata;
+use includes::metadata::Metadata;
 
 impl ImageDescriptor {
-	pub fn from_image_file(context: &mut ContextObject, image_filename: &str) -> Result<ImageDescriptor, String> {
+	pub fn from_image_file(image_filename: &str, metadata: &mut Metadata) -> Result<ImageDescriptor, SriError> {
 
 
 		let mut pixels_amount = 0;
 		let mut histogram: [i32; IMAGE_QUANT_LVL] = [0; IMAGE_QUANT_LVL];
 
 
-		let img = image::open(&Path::new(image_filename)).unwrap();
+		let img = match image::open(&Path::new(image_filename)) {
+			Ok(img) => img,
+			Err(e) => return Err(SriError::Decode { file: String::from(image_filename), message: format!("{}", e) }),
+		};
+		let (width, height) = img.dimensions();
+		metadata.set(metadata::WIDTH, width);
+		metadata.set(metadata::HEIGHT, height);
 
 		for (_x, _y, pixel) in img.pixels() {
 			// let (r_int, g_int, b_int) = match pixel {
@@ -37,13 +45,13 @@ impl ImageDescriptor {
 					histogram[quantification as usize] += 1;
 					pixels_amount += 1;
 				},
-				Err(e) => panic!(e),
+				Err(e) => return Err(SriError::decode(e)),
 			}
 		}
 
-		Ok(ImageDescriptor::from_histogram(context.gen_id(String::from("img")), pixels_amount, histogram))
+		Ok(ImageDescriptor::from_histogram(String::new(), pixels_amount, histogram))
 	}
-	pub fn from_plain_text_file(context: &mut ContextObject, image_file: File) -> Result<ImageDescriptor, String> {
+	pub fn from_plain_text_file(image_file: File, metadata: &mut Metadata) -> Result<ImageDescriptor, SriError> {
 		let l_int;
 		let h_int;
 		let nbcomp_int;
@@ -61,32 +69,34 @@ impl ImageDescriptor {
 					Some(value) => {
 						match value.parse::<usize>() {
 							Ok(i) => l_int = i,
-							Err(e) => return Err(String::from(format!("Invalid value for header field 1 ({})", e))),
+							Err(e) => return Err(SriError::decode(format!("Invalid value for header field 1 ({})", e))),
 						}
 					},
-					None => return Err(format!("Invalid header ({})", header)),
+					None => return Err(SriError::decode(format!("Invalid header ({})", header))),
 				}
 				match header_fields.next() {
 					Some(value) => {
 						match value.parse::<usize>() {
 							Ok(i) => h_int = i,
-							Err(e) => return Err(String::from(format!("Invalid value for header field 2 ({})", e))),
+							Err(e) => return Err(SriError::decode(format!("Invalid value for header field 2 ({})", e))),
 						}
 					},
-					None => return Err(format!("Invalid header ({})", header)),
+					None => return Err(SriError::decode(format!("Invalid header ({})", header))),
 				}
 				match header_fields.next() {
 					Some(value) => {
 						match value.parse::<usize>() {
 							Ok(i) => nbcomp_int = i,
-							Err(e) => return Err(String::from(format!("Invalid value for header field 3 ({})", e))),
+							Err(e) => return Err(SriError::decode(format!("Invalid value for header field 3 ({})", e))),
 						}
 					},
-					None => return Err(format!("Invalid header ({})", header)),
+					None => return Err(SriError::decode(format!("Invalid header ({})", header))),
 				}
 			},
-			Err(e) => return Err(format!("{}", e)),
+			Err(e) => return Err(SriError::from(e)),
 		}
+		metadata.set(metadata::WIDTH, l_int);
+		metadata.set(metadata::HEIGHT, h_int);
 
 		if nbcomp_int == 3 {
 			let mut r_values = String::new();
@@ -105,7 +115,7 @@ impl ImageDescriptor {
 							},
 						}
 					},
-					Err(e) => return Err(format!("{}", e)),
+					Err(e) => return Err(SriError::from(e)),
 				}
 			}
 
@@ -115,24 +125,24 @@ impl ImageDescriptor {
 				}
 				let r_int = match r_str.parse::<u32>() {
 					Ok(i) => i,
-					Err(e) => return Err(String::from(format!("Corrupted image file ({})", e))),
+					Err(e) => return Err(SriError::decode(format!("Corrupted image file ({})", e))),
 				};
 
 				let g_int = match g_str.parse::<u32>() {
 					Ok(i) => i,
-					Err(e) => return Err(String::from(format!("Corrupted image file ({})", e))),
+					Err(e) => return Err(SriError::decode(format!("Corrupted image file ({})", e))),
 				};
 
 				let b_int = match b_str.parse::<u32>() {
 					Ok(i) => i,
-					Err(e) => return Err(String::from(format!("Corrupted image file ({})", e))),
+					Err(e) => return Err(SriError::decode(format!("Corrupted image file ({})", e))),
 				};
 				match quantification_image(r_int, g_int, b_int) {
 					Ok(quantification) => {
 						histogram[quantification as usize] += 1;
 						pixels_amount += 1;
 					},
-					Err(e) => panic!(e),
+					Err(e) => return Err(SriError::decode(e)),
 				}
 			}
 		} else if nbcomp_int == 1 {
@@ -150,7 +160,7 @@ impl ImageDescriptor {
 						for value in line.split_whitespace() {
 							match value.parse::<u32>() {
 								Ok(i) => current_int = i,
-								Err(e) => return Err(String::from(format!("Corrupted image file ({})", e))),
+								Err(e) => return Err(SriError::decode(format!("Corrupted image file ({})", e))),
 							}
 
 							match quantification_image(current_int, current_int, current_int) {
@@ -158,16 +168,16 @@ impl ImageDescriptor {
 									histogram[quantification as usize] += 1;
 									pixels_amount += 1;
 								},
-								Err(e) => panic!(e),
+								Err(e) => return Err(SriError::decode(e)),
 							}
 							cpt_int += 1;
 						}
 					},
-					Err(e) => return Err(format!("{}", e)),
+					Err(e) => return Err(SriError::from(e)),
 				}
 			}
 		}
 
-		Ok(ImageDescriptor::from_histogram(context.gen_id(String::from("img")), pixels_amount, histogram))
+		Ok(ImageDescriptor::from_histogram(String::new(), pixels_amount, histogram))
 	}
 }
@@ -1,165 +1,420 @@
 pub mod image_indexing;
 pub mod sound_indexing;
 pub mod text_indexing;
+pub mod report;
+pub mod watch;
 
 
+use std::collections::HashMap;
 use std::io::BufReader;
 use std::io::BufRead;
+use std::fs;
 use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
+use num_cpus;
 
-use includes::image_types::ImageDescriptor;
-use includes::sound_types::SoundDescriptor;
-use includes::text_types::TextDescriptor;
+use includes::registry;
+use includes::collection;
+use includes::crawl::Crawler;
+use includes::registry::MediaType;
+use includes::MediaDescriptor;
+use includes::config::Config;
+use includes::error::SriError;
+use includes::fingerprint::Fingerprint;
+use includes::metadata;
+use includes::metadata::Metadata;
+use includes::util::absolute_path;
+use includes::indexing::report::DocumentReport;
+use includes::indexing::report::IndexingReport;
 
 use includes::context_types::ContextObject;
+
+/// What indexing a document did
+#[derive(Debug, PartialEq)]
+pub enum IndexingOutcome {
+	/// The document was new, or its file became another kind of document, and got this id
+	Added(String),
+	/// The file changed since it was indexed, its descriptor was extracted again under the same id
+	Updated(String),
+	/// The file did not change since it was indexed
+	Unchanged(String),
+	/// The type of the document is not supported
+	Unsupported,
+}
+
+/// Document to index, with what the index knew about it when the indexing started
+struct IndexingJob {
+	filename: String,
+	indexed_id: Option<String>,
+	previous: Option<Fingerprint>,
+}
+
+/// What the extraction found out about a document, the index only being changed when it is committed
+enum Extraction {
+	/// The file did not change, with its fingerprint when the index has to record it
+	Unchanged(Option<Fingerprint>),
+	Unsupported,
+	Extracted(&'static MediaType, MediaDescriptor, Metadata, Fingerprint),
+}
+
+/// Checks whether a document changed and extracts its descriptor when it did, without touching the index
+///
+/// The size and modification time of an indexed file are compared first so that the hash of unchanged files is not computed.
+fn extract_document(config: &Config, job: &IndexingJob) -> Result<Extraction, SriError> {
+	let mut fingerprint = None;
+	if job.indexed_id.is_some() {
+		if let Some(previous) = job.previous {
+			let (size, mtime) = try!(Fingerprint::stat(&job.filename));
+			if previous.matches_stat(size, mtime) {
+				return Ok(Extraction::Unchanged(None));
+			}
+		}
+		let current = try!(Fingerprint::of_file(&job.filename));
+		// A document indexed before its fingerprint was recorded can't be compared, so it is extracted again
+		if job.previous.map_or(false, |previous| previous.get_hash() == current.get_hash()) {
+			return Ok(Extraction::Unchanged(match job.previous == Some(current) {
+				true => None,
+				false => Some(current),
+			}));
+		}
+		fingerprint = Some(current);
+	}
+
+	let media_type = match try!(registry::detect_media_type(&job.filename)) {
+		Some(media_type) => media_type,
+		None => return Ok(Extraction::Unsupported),
+	};
+	let fingerprint = match fingerprint {
+		Some(fingerprint) => fingerprint,
+		None => try!(Fingerprint::of_file(&job.filename)),
+	};
+	let (descriptor, metadata) = try!(media_type.extract(config, &job.filename));
+	Ok(Extraction::Extracted(media_type, descriptor, metadata, fingerprint))
+}
+
+fn timed_extraction(config: &Config, job: &IndexingJob) -> (Result<Extraction, SriError>, Duration) {
+	let start = Instant::now();
+	let extraction = extract_document(config, job);
+	(extraction, start.elapsed())
+}
+
 impl ContextObject {
-	pub fn start_indexing(&mut self, documents_list_file: &str) {
-
-		match File::open(documents_list_file) {
-			Ok(file) => {
-				let file = BufReader::new(&file);
-				for line in file.lines() {
-					let l = line.unwrap();
-					let mut document_already_indexed = false;
-					for document_filename in self.get_images_associations().values().chain(self.get_texts_associations().values()).chain(self.get_sounds_associations().values()) {
-						if document_filename == &l {
-							document_already_indexed = true;
-							break;
-						}
-					}
-					if !document_already_indexed {
-						match l.rsplit('.').next().unwrap().to_string().to_lowercase().as_ref() {
-							"txt" => {
-								// Image file
-								println!("Indexing image: {}", l);
-
-								match File::open(l.clone()) {
-									Ok(file) => {
-										match ImageDescriptor::from_plain_text_file(self, file) {
-											Ok(descriptor) => {
-												self.add_image_association(String::from(descriptor.get_id()), l);
-												self.add_image_descriptor(descriptor);
-
-											},
-											Err(e) => println!("{:?}", e),
-										}
-									},
-									Err(e) => println!("{} {}", e, l),
-								}
-								()
-							},
-							"png" | "jpg" | "jpeg" | "gif" | "bmp" | "ico" | "tiff" | "webp" => {
-								match ImageDescriptor::from_image_file(self, l.as_ref()) {
-									Ok(descriptor) => {
-										self.add_image_association(String::from(descriptor.get_id()), l);
-										self.add_image_descriptor(descriptor);
-
-									},
-									Err(e) => println!("{:?}", e),
-								}
-							},
-							"bin" => {
-								// Sound file
-								println!("Indexing sound: {}", l);
-
-								match File::open(l.clone()) {
-									Ok(file) => {
-										match SoundDescriptor::from_raw_file(self, file) {
-											Ok(descriptor) => {
-												self.add_sound_association(String::from(descriptor.get_id()), l);
-												self.add_sound_descriptor(descriptor);
-
-											},
-											Err(e) => println!("{:?}", e),
-										}
-									},
-									Err(e) => println!("{} {}", e, l),
-								}
-								()
-							},
-							"wav" => {
-								// Sound file
-								println!("Indexing sound: {}", l);
-
-								match File::open(l.clone()) {
-									Ok(file) => {
-										match SoundDescriptor::from_wav_file(self, file) {
-											Ok(descriptor) => {
-												self.add_sound_association(String::from(descriptor.get_id()), l);
-												self.add_sound_descriptor(descriptor);
-
-											},
-											Err(e) => println!("{:?}", e),
-										}
-									},
-									Err(e) => println!("{} {}", e, l),
-								}
-								()
-							},
-							"mp3" | "mpeg3" => {
-								// Sound file
-								println!("Indexing sound: {}", l);
-
-								match File::open(l.clone()) {
-									Ok(file) => {
-										match SoundDescriptor::from_mp3_file(self, file) {
-											Ok(descriptor) => {
-												self.add_sound_association(String::from(descriptor.get_id()), l);
-												self.add_sound_descriptor(descriptor);
-
-											},
-											Err(e) => println!("{:?}", e),
-										}
-									},
-									Err(e) => println!("{} {}", e, l),
-								}
-								()
-							},
-							"xml" => {
-								// Text file
-								println!("Indexing text: {}", l);
-
-								match File::open(l.clone()) {
-									Ok(file) => {
-										match TextDescriptor::from_text_file(self, file) {
-											Ok(descriptor) => {
-												self.add_text_association(String::from(descriptor.get_id()), l);
-												self.add_text_descriptor(descriptor);
-
-											},
-											Err(e) => println!("{:?}", e),
-										}
-									},
-									Err(e) => println!("{} {}", e, l),
-								}
-								()
-							},
-							"srt" => {
-								// Subrip file
-								println!("Indexing subtitle: {}", l);
-
-								match File::open(l.clone()) {
-									Ok(file) => {
-										match TextDescriptor::from_subrip_file(self, file) {
-											Ok(descriptor) => {
-												self.add_text_association(String::from(descriptor.get_id()), l);
-												self.add_text_descriptor(descriptor);
-
-											},
-											Err(e) => println!("{:?}", e),
-										}
-									},
-									Err(e) => println!("{} {}", e, l),
-								}
-								()
-							},
-							_ => (),
-						}
+	/// Tells if a document with this exact filename, once stored relative to the collection root, is already associated to an id
+	pub fn is_document_indexed(&self, filename: &str) -> bool {
+		let filename = self.collection_path(filename);
+		self.get_images_associations().values().chain(self.get_texts_associations().values()).chain(self.get_sounds_associations().values()).any(|document_filename| *document_filename == filename)
+	}
+
+	/// Extracts the descriptor of a single document and adds it to the index
+	///
+	/// A document already indexed is extracted again only when its content changed.
+	pub fn index_document(&mut self, filename: &str) -> Result<IndexingOutcome, SriError> {
+		let job = self.indexing_job(String::from(filename));
+		let extraction = try!(extract_document(self.get_config(), &job));
+		Ok(self.commit_document(job, extraction))
+	}
+
+	/// Indexes several documents, their descriptors being extracted by `indexing_threads` threads
+	///
+	/// The documents are added to the index in the order of `filenames`, which is also the order `report` is called in,
+	/// so the sequential ids and the saved index do not depend on which extraction ends first.
+	/// `report` also gets the time the extraction of the document took.
+	pub fn index_documents<F: FnMut(&str, Result<IndexingOutcome, SriError>, Duration)>(&mut self, filenames: Vec<String>, mut report: F) {
+		let threads = match self.get_config().get_indexing_threads() {
+			0 => num_cpus::get(),
+			threads => threads,
+		};
+		let jobs: Vec<IndexingJob> = filenames.into_iter().map(|filename| self.indexing_job(filename)).collect();
+		if threads <= 1 || jobs.len() <= 1 {
+			for job in jobs {
+				let filename = job.filename.clone();
+				let (extraction, elapsed) = timed_extraction(self.get_config(), &job);
+				let outcome = extraction.map(|extraction| self.commit_document(job, extraction));
+				report(&filename, outcome, elapsed);
+			}
+			return;
+		}
+
+		let filenames: Vec<String> = jobs.iter().map(|job| job.filename.clone()).collect();
+		let config = Arc::new(self.get_config().clone());
+		let (job_sender, job_receiver) = mpsc::channel::<(usize, IndexingJob)>();
+		let job_receiver = Arc::new(Mutex::new(job_receiver));
+		let (extraction_sender, extraction_receiver) = mpsc::channel();
+		let mut workers = Vec::with_capacity(threads);
+		for _ in 0..threads {
+			let (config, job_receiver, extraction_sender) = (config.clone(), job_receiver.clone(), extraction_sender.clone());
+			workers.push(thread::spawn(move || {
+				loop {
+					let next_job = match job_receiver.lock() {
+						Ok(job_receiver) => job_receiver.recv(),
+						Err(_) => break,
+					};
+					let (position, job) = match next_job {
+						Ok(next_job) => next_job,
+						Err(_) => break,
+					};
+					let (extraction, elapsed) = timed_extraction(&config, &job);
+					if extraction_sender.send((position, job, extraction, elapsed)).is_err() {
+						break;
 					}
 				}
-			},
-			Err(e) => panic!("{}", e),
+			}));
+		}
+		drop(extraction_sender);
+		for job in jobs.into_iter().enumerate() {
+			// The workers only stop once every job is sent, the channel can't be closed yet
+			job_sender.send(job).unwrap();
+		}
+		drop(job_sender);
+
+		// Extractions ending early wait for the ones before them in the list
+		let mut pending = HashMap::new();
+		let mut next_position = 0;
+		for (position, job, extraction, elapsed) in extraction_receiver.iter() {
+			pending.insert(position, (job, extraction, elapsed));
+			while let Some((job, extraction, elapsed)) = pending.remove(&next_position) {
+				let outcome = extraction.map(|extraction| self.commit_document(job, extraction));
+				report(&filenames[next_position], outcome, elapsed);
+				next_position += 1;
+			}
+		}
+		for worker in workers {
+			let _ = worker.join();
+		}
+
+		// Only a worker that panicked loses its job, the documents after it are still added
+		for position in next_position..filenames.len() {
+			let (outcome, elapsed) = match pending.remove(&position) {
+				Some((job, extraction, elapsed)) => (extraction.map(|extraction| self.commit_document(job, extraction)), elapsed),
+				None => (Err(SriError::decode("The extraction stopped unexpectedly").in_file(&filenames[position])), Duration::from_secs(0)),
+			};
+			report(&filenames[position], outcome, elapsed);
 		}
 	}
+
+	fn indexing_job(&self, filename: String) -> IndexingJob {
+		let indexed_id = self.find_document(&self.collection_path(&filename));
+		let previous = indexed_id.as_ref().and_then(|id| self.get_fingerprint(id).cloned());
+		IndexingJob { filename: filename, indexed_id: indexed_id, previous: previous }
+	}
+
+	/// Adds the outcome of an extraction to the index, the new documents getting their id here
+	fn commit_document(&mut self, job: IndexingJob, extraction: Extraction) -> IndexingOutcome {
+		let IndexingJob { filename, indexed_id, .. } = job;
+		let (media_type, mut descriptor, metadata, fingerprint) = match extraction {
+			Extraction::Unchanged(fingerprint) => {
+				let id = indexed_id.unwrap_or_default();
+				if let Some(fingerprint) = fingerprint {
+					self.set_fingerprint(&id, fingerprint);
+				}
+				return IndexingOutcome::Unchanged(id);
+			}
+			Extraction::Unsupported => return IndexingOutcome::Unsupported,
+			Extraction::Extracted(media_type, descriptor, metadata, fingerprint) => (media_type, descriptor, metadata, fingerprint),
+		};
+
+		let path = self.collection_path(&filename);
+		let indexed_id = match indexed_id {
+			Some(id) => Some(id),
+			// Listed twice, the document was added by its first line once the job of the second one was planned
+			None => {
+				match self.find_document(&path) {
+					Some(ref id) if self.get_fingerprint(id).map(|indexed| indexed.get_hash()) == Some(fingerprint.get_hash()) => return IndexingOutcome::Unchanged(id.clone()),
+					id => id,
+				}
+			}
+		};
+		println!("Indexing {}: {}", media_type.get_name(), filename);
+		match indexed_id {
+			Some(id) if self.get_document_kind(&id) == Some(descriptor.get_kind()) => {
+				descriptor.set_id(id.clone());
+				self.remove_document(&id);
+				self.add_document(path, descriptor);
+				self.set_fingerprint(&id, fingerprint);
+				self.set_metadata(&id, metadata);
+				IndexingOutcome::Updated(id)
+			}
+			former_id => {
+				// A file whose content became another kind of document needs an id with the prefix of that kind
+				if let Some(ref former_id) = former_id {
+					self.remove_document(former_id);
+				}
+				let id = self.new_document_id(descriptor.get_kind(), &path, Some(&fingerprint));
+				descriptor.set_id(id.clone());
+				self.add_document(path, descriptor);
+				self.set_fingerprint(&id, fingerprint);
+				self.set_metadata(&id, metadata);
+				if let Some(ref former_id) = former_id {
+					self.add_alias(former_id, &id);
+				}
+				IndexingOutcome::Added(id)
+			}
+		}
+	}
+
+	/// Indexes every document listed, one filename per line, in the documents list file
+	///
+	/// Documents that fail to be indexed are reported and skipped, only a documents list that can't be read is an error.
+	/// The documents extracted again because their file changed are reported, followed by a summary of the pass.
+	pub fn start_indexing(&mut self, documents_list_file: &str) -> Result<IndexingReport, SriError> {
+		let file = match File::open(documents_list_file) {
+			Ok(file) => BufReader::new(file),
+			Err(e) => return Err(SriError::io(documents_list_file, e)),
+		};
+		let mut filenames = Vec::new();
+		for line in file.lines() {
+			filenames.push(try!(line.map_err(|e| SriError::io(documents_list_file, e))));
+		}
+		Ok(self.index_files(filenames))
+	}
+
+	/// Indexes every file the crawler finds in a directory tree, like the documents of a documents list
+	///
+	/// Files of an unsupported type are skipped like in a list, a failing crawl being an error. The files of the index
+	/// directory are left out when it is inside the crawled one.
+	pub fn index_directory(&mut self, directory: &str, crawler: &Crawler) -> Result<IndexingReport, SriError> {
+		let mut filenames = try!(crawler.crawl(directory));
+		filenames.retain(|filename| !self.is_index_file(filename));
+		println!("{} files found in {}", filenames.len(), directory);
+		Ok(self.index_files(filenames))
+	}
+
+	/// Tells whether a file is in the index directory, which may be inside a crawled one but is never indexed
+	fn is_index_file(&self, filename: &str) -> bool { collection::replace_prefix(&absolute_path(filename), &absolute_path(self.get_config().get_index()), "").is_some() }
+
+	/// Indexes the documents, reporting the failures and the updated documents, then a summary of the pass
+	fn index_files(&mut self, filenames: Vec<String>) -> IndexingReport {
+		let start = Instant::now();
+		let mut outcomes = Vec::with_capacity(filenames.len());
+		self.index_documents(filenames, |filename, outcome, elapsed| {
+			match outcome {
+				Ok(IndexingOutcome::Updated(ref id)) => println!("Updated {}: {}", id, filename),
+				Err(ref e) => {
+					let _ = writeln!(&mut io::stderr(), "{}", e);
+				}
+				_ => (),
+			}
+			outcomes.push((String::from(filename), outcome, elapsed));
+		});
+		let elapsed = start.elapsed();
+
+		let mut documents = Vec::with_capacity(outcomes.len());
+		for (filename, outcome, elapsed) in outcomes {
+			let (media_type, size) = match outcome {
+				Ok(IndexingOutcome::Added(ref id)) | Ok(IndexingOutcome::Updated(ref id)) | Ok(IndexingOutcome::Unchanged(ref id)) => {
+					(self.get_metadata(id).and_then(|metadata| metadata.get(metadata::MEDIA_TYPE)).map(String::from), self.get_fingerprint(id).map(Fingerprint::get_size))
+				}
+				// The type the document was taken for helps finding why it failed, its size is known even if it was never read
+				Err(_) => {
+					let media_type = registry::detect_media_type(&filename).ok().and_then(|media_type| media_type).or_else(|| registry::find_media_type(&filename));
+					(media_type.map(|media_type| String::from(media_type.get_name())), fs::metadata(&filename).ok().map(|metadata| metadata.len()))
+				}
+				Ok(IndexingOutcome::Unsupported) => (None, None),
+			};
+			documents.push(DocumentReport::new(filename, outcome, media_type, size, elapsed));
+		}
+		let report = IndexingReport::from_documents(documents, elapsed);
+		println!("{} documents added, {} updated, {} unchanged", report.count("added"), report.count("updated"), report.count("unchanged"));
+		report
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+	use std::fs::File;
+	use std::io::Write;
+	use std::time::Duration;
+
+	use super::*;
+	use includes::util::test_directory;
+
+	fn context(index_path: &str, threads: usize) -> ContextObject {
+		let mut params = HashMap::new();
+		params.insert(String::from("index"), String::from(index_path));
+		params.insert(String::from("indexing_threads"), threads.to_string());
+		ContextObject::new(Config::check(&params).into_result().unwrap())
+	}
+
+	/// Plain texts of growing sizes, so that the last ones take longer to extract
+	fn documents(directory: &str, count: usize) -> Vec<String> {
+		(0..count).map(|number| {
+			           let filename = format!("{}/doc{:02}.txt", directory, count - number);
+			           let mut file = File::create(&filename).unwrap();
+			           for line in 0..number * 200 {
+				           writeln!(file, "line {} of document {} with some words", line, number).unwrap();
+			           }
+			           filename
+			          })
+		          .collect()
+	}
+
+	fn index(context: &mut ContextObject, filenames: &[String]) -> Vec<(String, IndexingOutcome)> {
+		let mut reported = Vec::new();
+		context.index_documents(filenames.to_vec(), |filename: &str, outcome: Result<IndexingOutcome, SriError>, _: Duration| reported.push((String::from(filename), outcome.unwrap())));
+		reported
+	}
+
+	#[test]
+	fn documents_are_committed_in_list_order() {
+		let directory = test_directory("indexing-order");
+		let filenames = documents(&directory, 12);
+		let mut sequential = context(&format!("{}/sequential", directory), 1);
+		let mut parallel = context(&format!("{}/parallel", directory), 4);
+
+		let expected = index(&mut sequential, &filenames);
+		let reported = index(&mut parallel, &filenames);
+		assert_eq!(reported.iter().map(|document| document.0.clone()).collect::<Vec<String>>(), filenames);
+		assert_eq!(reported, expected);
+		for (number, document) in reported.iter().enumerate() {
+			assert_eq!(document.1, IndexingOutcome::Added(format!("txt{}", number)));
+		}
+	}
+
+	#[test]
+	fn unchanged_documents_keep_their_id() {
+		let directory = test_directory("indexing-unchanged");
+		let filenames = documents(&directory, 3);
+		let mut context = context(&format!("{}/index", directory), 2);
+		let mut listed_twice = filenames.clone();
+		listed_twice.push(filenames[0].clone());
+		let reported = index(&mut context, &listed_twice);
+		assert_eq!(reported[3].1, IndexingOutcome::Unchanged(String::from("txt0")));
+
+		let reported = index(&mut context, &filenames);
+		assert!(reported.iter().enumerate().all(|(number, document)| document.1 == IndexingOutcome::Unchanged(format!("txt{}", number))));
+		File::create(&filenames[1]).unwrap().write_all(b"other words\n").unwrap();
+		assert_eq!(context.index_document(&filenames[1]).unwrap(), IndexingOutcome::Updated(String::from("txt1")));
+		assert_eq!(context.get_texts_base().len(), 3);
+	}
+
+	#[test]
+	fn documents_changing_kind_get_a_new_id() {
+		let directory = test_directory("indexing-kind");
+		let filename = format!("{}/picture.txt", directory);
+		File::create(&filename).unwrap().write_all(b"some words\n").unwrap();
+		let index_path = format!("{}/index", directory);
+		let mut context = context(&index_path, 1);
+		assert_eq!(context.index_document(&filename).unwrap(), IndexingOutcome::Added(String::from("txt0")));
+		context.save_index().unwrap();
+
+		File::create(&filename).unwrap().write_all(b"2 2 1\n0 255 255 0\n").unwrap();
+		assert_eq!(context.index_document(&filename).unwrap(), IndexingOutcome::Added(String::from("img1")));
+		assert!(context.get_texts_associations().is_empty());
+		context.save_index().unwrap();
+
+		let mut loaded = self::context(&index_path, 1);
+		loaded.load_index().unwrap();
+		assert!(loaded.get_warnings().is_empty());
+		assert_eq!(loaded.resolve_id("txt0"), "img1");
+		assert_eq!(loaded.get_images_base().len(), 1);
+	}
 }
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
+
+use rustc_serialize::json::Json;
+
+use includes::error::SriError;
+
+use includes::indexing::IndexingOutcome;
+
+/// What happened to one document of an indexing run
+pub struct DocumentReport {
+	filename: String,
+	outcome: Result<IndexingOutcome, SriError>,
+	media_type: Option<String>,
+	size: Option<u64>,
+	elapsed: Duration,
+}
+
+impl DocumentReport {
+	pub fn new(filename: String, outcome: Result<IndexingOutcome, SriError>, media_type: Option<String>, size: Option<u64>, elapsed: Duration) -> DocumentReport {
+		DocumentReport { filename: filename, outcome: outcome, media_type: media_type, size: size, elapsed: elapsed }
+	}
+
+	pub fn get_filename(&self) -> &str { &self.filename }
+
+	pub fn get_outcome(&self) -> &Result<IndexingOutcome, SriError> { &self.outcome }
+
+	/// Name of the media type of the document, the one it was detected as or the one of its extension when it failed
+	pub fn get_media_type(&self) -> Option<&str> { self.media_type.as_ref().map(|media_type| media_type.as_ref()) }
+
+	/// Size of the file, known once it is in the index or when it failed
+	pub fn get_size(&self) -> Option<u64> { self.size }
+
+	/// Time the document took to be checked and extracted
+	pub fn get_elapsed(&self) -> Duration { self.elapsed }
+
+	/// Name of the outcome, as written in the JSON report
+	pub fn get_status(&self) -> &'static str {
+		match self.outcome {
+			Ok(IndexingOutcome::Added(_)) => "added",
+			Ok(IndexingOutcome::Updated(_)) => "updated",
+			Ok(IndexingOutcome::Unchanged(_)) => "unchanged",
+			Ok(IndexingOutcome::Unsupported) => "unsupported",
+			Err(_) => "failed",
+		}
+	}
+
+	/// Why the document was not added or updated, `None` when it was
+	pub fn get_skip_reason(&self) -> Option<&'static str> {
+		match self.outcome {
+			Ok(IndexingOutcome::Added(_)) | Ok(IndexingOutcome::Updated(_)) => None,
+			Ok(IndexingOutcome::Unchanged(_)) => Some("already indexed"),
+			Ok(IndexingOutcome::Unsupported) => Some("unsupported type"),
+			Err(SriError::Decode { .. }) => Some("decode error"),
+			Err(SriError::Io(..)) => Some("io error"),
+			Err(SriError::Parse { .. }) => Some("parse error"),
+			Err(SriError::Config(_)) => Some("config error"),
+			Err(SriError::Busy(_)) => Some("busy"),
+		}
+	}
+
+	fn to_json(&self) -> Json {
+		let mut object = BTreeMap::new();
+		object.insert(String::from("path"), Json::String(self.filename.clone()));
+		object.insert(String::from("status"), Json::String(String::from(self.get_status())));
+		match self.outcome {
+			Ok(IndexingOutcome::Added(ref id)) | Ok(IndexingOutcome::Updated(ref id)) | Ok(IndexingOutcome::Unchanged(ref id)) => {
+				object.insert(String::from("id"), Json::String(id.clone()));
+			}
+			Ok(IndexingOutcome::Unsupported) => (),
+			Err(ref e) => {
+				object.insert(String::from("error"), Json::String(e.to_string()));
+			}
+		}
+		if let Some(ref media_type) = self.media_type {
+			object.insert(String::from("type"), Json::String(media_type.clone()));
+		}
+		if let Some(size) = self.size {
+			object.insert(String::from("size"), Json::U64(size));
+		}
+		object.insert(String::from("seconds"), Json::F64(seconds(self.elapsed)));
+		Json::Object(object)
+	}
+}
+
+/// Outcome of every document of an indexing run, in the order they were indexed
+pub struct IndexingReport {
+	documents: Vec<DocumentReport>,
+	elapsed: Duration,
+}
+
+fn seconds(duration: Duration) -> f64 { duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9 }
+
+/// Number per second, 0 for a run too short to be measured
+fn rate(count: f64, elapsed: Duration) -> f64 {
+	match seconds(elapsed) {
+		elapsed if elapsed > 0f64 => count / elapsed,
+		_ => 0f64,
+	}
+}
+
+impl IndexingReport {
+	pub fn new() -> IndexingReport { IndexingReport { documents: Vec::new(), elapsed: Duration::from_secs(0) } }
+
+	pub fn from_documents(documents: Vec<DocumentReport>, elapsed: Duration) -> IndexingReport { IndexingReport { documents: documents, elapsed: elapsed } }
+
+	/// Adds the documents of another run, their times adding up
+	pub fn append(&mut self, mut other: IndexingReport) {
+		self.documents.append(&mut other.documents);
+		self.elapsed += other.elapsed;
+	}
+
+	pub fn get_documents(&self) -> &[DocumentReport] { &self.documents }
+
+	/// Time the whole run took, extractions running in parallel
+	pub fn get_elapsed(&self) -> Duration { self.elapsed }
+
+	/// Number of documents with the status `status`
+	pub fn count(&self, status: &str) -> usize { self.documents.iter().filter(|document| document.get_status() == status).count() }
+
+	/// Number of documents added or updated
+	pub fn count_changed(&self) -> usize { self.count("added") + self.count("updated") }
+
+	/// Documents that failed to be indexed, in the order they were indexed
+	pub fn get_failures(&self) -> Vec<&DocumentReport> { self.documents.iter().filter(|document| document.get_outcome().is_err()).collect() }
+
+	/// Writes the paths of the failed documents as a documents list, so they can be indexed again by a later run
+	pub fn write_failures(&self, writer: &mut Write) -> Result<(), SriError> {
+		for document in self.get_failures() {
+			try!(writeln!(writer, "{}", document.get_filename()));
+		}
+		Ok(())
+	}
+
+	/// Counts of the run by status and by media type, the documents skipped and why, the time of each document and the throughput
+	///
+	/// The throughput counts the documents and the bytes that were added or updated.
+	pub fn to_json(&self) -> Json {
+		let mut counts = BTreeMap::new();
+		for status in &["added", "updated", "unchanged", "unsupported", "failed"] {
+			counts.insert(String::from(*status), Json::U64(self.count(status) as u64));
+		}
+
+		let mut media_types: BTreeMap<String, BTreeMap<String, u64>> = BTreeMap::new();
+		for document in &self.documents {
+			if let Some(media_type) = document.get_media_type() {
+				*media_types.entry(String::from(media_type)).or_insert_with(BTreeMap::new).entry(String::from(document.get_status())).or_insert(0) += 1;
+			}
+		}
+		let media_types = media_types.into_iter().map(|(media_type, counts)| (media_type, Json::Object(counts.into_iter().map(|(status, count)| (status, Json::U64(count))).collect())));
+
+		let mut skipped = Vec::new();
+		for document in &self.documents {
+			if let Some(reason) = document.get_skip_reason() {
+				let mut object = BTreeMap::new();
+				object.insert(String::from("path"), Json::String(String::from(document.get_filename())));
+				object.insert(String::from("reason"), Json::String(String::from(reason)));
+				skipped.push(Json::Object(object));
+			}
+		}
+
+		let changed_bytes: u64 = self.documents.iter().filter(|document| document.get_skip_reason().is_none()).filter_map(DocumentReport::get_size).sum();
+		let mut throughput = BTreeMap::new();
+		throughput.insert(String::from("documents_per_second"), Json::F64(rate(self.count_changed() as f64, self.elapsed)));
+		throughput.insert(String::from("bytes_per_second"), Json::F64(rate(changed_bytes as f64, self.elapsed)));
+
+		let mut report = BTreeMap::new();
+		report.insert(String::from("documents"), Json::U64(self.documents.len() as u64));
+		report.insert(String::from("counts"), Json::Object(counts));
+		report.insert(String::from("media_types"), Json::Object(media_types.collect()));
+		report.insert(String::from("skipped"), Json::Array(skipped));
+		report.insert(String::from("files"), Json::Array(self.documents.iter().map(DocumentReport::to_json).collect()));
+		report.insert(String::from("seconds"), Json::F64(seconds(self.elapsed)));
+		report.insert(String::from("throughput"), Json::Object(throughput));
+		Json::Object(report)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io;
+
+	use super::*;
+
+	fn document(filename: &str, outcome: Result<IndexingOutcome, SriError>, media_type: Option<&str>, size: Option<u64>) -> DocumentReport {
+		DocumentReport::new(String::from(filename), outcome, media_type.map(String::from), size, Duration::from_millis(250))
+	}
+
+	fn report() -> IndexingReport {
+		let mut report = IndexingReport::from_documents(vec![document("a.txt", Ok(IndexingOutcome::Added(String::from("txt0"))), Some("text"), Some(300)),
+		                                                     document("b.txt", Ok(IndexingOutcome::Unchanged(String::from("txt1"))), Some("text"), Some(50)),
+		                                                     document("c.bin", Ok(IndexingOutcome::Unsupported), None, None)],
+		                                                Duration::from_secs(1));
+		report.append(IndexingReport::from_documents(vec![document("d.pgm", Err(SriError::decode("bad header").in_file("d.pgm")), Some("image"), Some(10)),
+		                                                  document("e.txt", Err(SriError::io("e.txt", io::Error::new(io::ErrorKind::NotFound, "gone"))), Some("text"), None),
+		                                                  document("f.txt", Ok(IndexingOutcome::Updated(String::from("txt2"))), Some("text"), Some(100))],
+		                                             Duration::from_secs(1)));
+		report
+	}
+
+	#[test]
+	fn reports_count_the_outcomes_by_status_and_media_type() {
+		let json = report().to_json();
+		assert_eq!(json.find("documents").and_then(Json::as_u64), Some(6));
+		assert_eq!(json.find("seconds").and_then(Json::as_f64), Some(2.0));
+		let counts = json.find("counts").unwrap();
+		for &(status, count) in &[("added", 1), ("updated", 1), ("unchanged", 1), ("unsupported", 1), ("failed", 2)] {
+			assert_eq!(counts.find(status).and_then(Json::as_u64), Some(count));
+		}
+		assert_eq!(json.find_path(&["media_types", "text", "failed"]).and_then(Json::as_u64), Some(1));
+		assert_eq!(json.find_path(&["media_types", "image", "failed"]).and_then(Json::as_u64), Some(1));
+		assert!(json.find_path(&["media_types", "image", "added"]).is_none());
+
+		let skipped: Vec<(&str, &str)> = json.find("skipped")
+		                                     .and_then(Json::as_array)
+		                                     .unwrap()
+		                                     .iter()
+		                                     .map(|skipped| (skipped.find("path").and_then(Json::as_string).unwrap(), skipped.find("reason").and_then(Json::as_string).unwrap()))
+		                                     .collect();
+		assert_eq!(skipped, vec![("b.txt", "already indexed"), ("c.bin", "unsupported type"), ("d.pgm", "decode error"), ("e.txt", "io error")]);
+
+		let files = json.find("files").and_then(Json::as_array).unwrap();
+		assert_eq!(files[0].find("id").and_then(Json::as_string), Some("txt0"));
+		assert_eq!(files[0].find("seconds").and_then(Json::as_f64), Some(0.25));
+		assert!(files[2].find("type").is_none() && files[2].find("id").is_none());
+		assert!(files[3].find("error").and_then(Json::as_string).unwrap().contains("bad header"));
+		assert_eq!(json.find_path(&["throughput", "documents_per_second"]).and_then(Json::as_f64), Some(1.0));
+		assert_eq!(json.find_path(&["throughput", "bytes_per_second"]).and_then(Json::as_f64), Some(200.0));
+	}
+
+	#[test]
+	fn failures_are_listed_to_be_indexed_again() {
+		let report = report();
+		let mut failures = Vec::new();
+		report.write_failures(&mut failures).unwrap();
+		assert_eq!(String::from_utf8(failures).unwrap(), "d.pgm\ne.txt\n");
+		assert_eq!(report.count_changed(), 2);
+		assert_eq!(IndexingReport::new().to_json().find_path(&["throughput", "documents_per_second"]).and_then(Json::as_f64), Some(0.0));
+	}
+}
@@ -8,43 +8,41 @@ use simplemad::Decoder;
 use hound;
 
 use includes::sound_types::*;
-use super::super::context_types::ContextObject;
+use includes::config::Config;
+use includes::error::SriError;
+use includes::metadata;
+use includes::metadata::Metadata;
+
+/// Size and amount of levels of the histograms computed for each window of a sound
+fn window_parameters(config: &Config) -> Result<(usize, usize), SriError> {
+	match (config.get_window_size(), config.get_window_levels()) {
+		(Some(window_size), Some(window_levels)) => Ok((window_size, window_levels)),
+		(None, _) => Err(SriError::config("Can't find parameter 'window_size' in config")),
+		(_, None) => Err(SriError::config("Can't find parameter 'window_levels' in config")),
+	}
+}
 
 impl SoundDescriptor {
-	pub fn from_mp3_file(context: &mut ContextObject, sound_file: File) -> Result<SoundDescriptor, String> {
-		let histograms_size;
-		let histograms_levels;
-
+	pub fn from_mp3_file(config: &Config, sound_file: File, metadata: &mut Metadata) -> Result<SoundDescriptor, SriError> {
 		let mut current_level;
 		let mut position_in_window = 0;
 		let mut window = 0;
 		let mut histograms: Vec<Vec<i32>> = Vec::new();
 
-		match context.get_param("window_size") {
-			Some(value) => {
-				match value.parse::<usize>() {
-					Ok(i) => histograms_size = i,
-					Err(e) => return Err(String::from(format!("Invalid value for parameter window_size ({})", e))),
-				}
-			},
-			None => panic!("Can't find parameter 'window_size' in config"),
-		}
-		match context.get_param("window_levels") {
-			Some(value) => {
-				match value.parse::<usize>() {
-					Ok(i) => histograms_levels = i,
-					Err(e) => return Err(String::from(format!("Invalid value for parameter histograms_levels ({})", e))),
-				}
-			},
-			None => panic!("Can't find parameter 'window_levels' in config"),
-		}
+		let (histograms_size, histograms_levels) = try!(window_parameters(config));
 
-		let decoder = Decoder::decode(sound_file).unwrap();
+		let decoder = match Decoder::decode(sound_file) {
+			Ok(decoder) => decoder,
+			Err(e) => return Err(SriError::decode(format!("{:?}", e))),
+		};
 
+		let mut duration = 0f64;
 		for decoding_result in decoder {
 			match decoding_result {
 				Err(e) => println!("Error: {:?}", e),
 				Ok(frame) => {
+					metadata.set(metadata::SAMPLE_RATE, frame.sample_rate);
+					duration += frame.duration.as_secs() as f64 + frame.duration.subsec_nanos() as f64 / 1e9;
 					for sample in &(&frame.samples)[0] {
 						if position_in_window == 0 {
 							histograms.insert(window, Vec::new());
@@ -67,41 +65,30 @@ impl SoundDescriptor {
 				},
 			}
 		}
+		metadata.set(metadata::DURATION, metadata::format_duration(duration));
 
 
 
-		Ok(SoundDescriptor::from_histogram(context.gen_id(String::from("snd")), histograms_size, histograms_levels, histograms))
+		Ok(SoundDescriptor::from_histogram(String::new(), histograms_size, histograms_levels, histograms))
 	}
 
 
-	pub fn from_wav_file(context: &mut ContextObject, sound_file: File) -> Result<SoundDescriptor, String> {
-		let histograms_size;
-		let histograms_levels;
-
+	pub fn from_wav_file(config: &Config, sound_file: File, metadata: &mut Metadata) -> Result<SoundDescriptor, SriError> {
 		let mut current_level;
 		let mut position_in_window = 0;
 		let mut window = 0;
 		let mut histograms: Vec<Vec<i32>> = Vec::new();
 
-		match context.get_param("window_size") {
-			Some(value) => {
-				match value.parse::<usize>() {
-					Ok(i) => histograms_size = i,
-					Err(e) => return Err(String::from(format!("Invalid value for parameter window_size ({})", e))),
-				}
-			},
-			None => panic!("Can't find parameter 'window_size' in config"),
-		}
-		match context.get_param("window_levels") {
-			Some(value) => {
-				match value.parse::<usize>() {
-					Ok(i) => histograms_levels = i,
-					Err(e) => return Err(String::from(format!("Invalid value for parameter histograms_levels ({})", e))),
-				}
-			},
-			None => panic!("Can't find parameter 'window_levels' in config"),
+		let (histograms_size, histograms_levels) = try!(window_parameters(config));
+		let mut reader = match hound::WavReader::new(sound_file) {
+			Ok(reader) => reader,
+			Err(e) => return Err(SriError::decode(format!("{}", e))),
+		};
+		let sample_rate = reader.spec().sample_rate;
+		metadata.set(metadata::SAMPLE_RATE, sample_rate);
+		if sample_rate > 0 {
+			metadata.set(metadata::DURATION, metadata::format_duration(reader.duration() as f64 / sample_rate as f64));
 		}
-		let mut reader = hound::WavReader::new(sound_file).unwrap();
 		for sample in reader.samples::<i16>() {
 			match sample {
 				Err(e) => println!("Error: {:?}", e),
@@ -130,30 +117,12 @@ impl SoundDescriptor {
 
 
 
-		Ok(SoundDescriptor::from_histogram(context.gen_id(String::from("snd")), histograms_size, histograms_levels, histograms))
+		Ok(SoundDescriptor::from_histogram(String::new(), histograms_size, histograms_levels, histograms))
 	}
 
-	pub fn from_raw_file(context: &mut ContextObject, sound_file: File) -> Result<SoundDescriptor, String> {
-		let histograms_size;
-		let histograms_levels;
-		match context.get_param("window_size") {
-			Some(value) => {
-				match value.parse::<usize>() {
-					Ok(i) => histograms_size = i,
-					Err(e) => return Err(String::from(format!("Invalid value for parameter window_size ({})", e))),
-				}
-			},
-			None => panic!("Can't find parameter 'window_size' in config"),
-		}
-		match context.get_param("window_levels") {
-			Some(value) => {
-				match value.parse::<usize>() {
-					Ok(i) => histograms_levels = i,
-					Err(e) => return Err(String::from(format!("Invalid value for parameter histograms_levels ({})", e))),
-				}
-			},
-			None => panic!("Can't find parameter 'window_levels' in config"),
-		}
+	/// Raw sounds have no header, so no metadata can be found in them
+	pub fn from_raw_file(config: &Config, sound_file: File, _metadata: &mut Metadata) -> Result<SoundDescriptor, SriError> {
+		let (histograms_size, histograms_levels) = try!(window_parameters(config));
 
 		let mut current_level;
 		let mut position_in_window = 0;
@@ -185,7 +154,7 @@ impl SoundDescriptor {
 			}
 		}
 
-		file_reader.seek(SeekFrom::Start(0)).unwrap();
+		try!(file_reader.seek(SeekFrom::Start(0)));
 		while let Ok(mut current_value) = read_f32(&mut file_reader, little_endian) {
 			if position_in_window == 0 {
 				histograms.insert(window, Vec::new());
@@ -193,7 +162,7 @@ impl SoundDescriptor {
 			}
 
 			if current_value < -1f64 || current_value > 1f64 {
-				return Err(String::from(format!("Wrong value in sound file ({})", current_value)));
+				return Err(SriError::decode(format!("Wrong value in sound file ({})", current_value)));
 			}
 
 			current_value = current_value * 0.5 + 0.5;
@@ -211,6 +180,6 @@ impl SoundDescriptor {
 		}
 
 
-		Ok(SoundDescriptor::from_histogram(context.gen_id(String::from("snd")), histograms_size, histograms_levels, histograms))
+		Ok(SoundDescriptor::from_histogram(String::new(), histograms_size, histograms_levels, histograms))
 	}
 }
@@ -2,6 +2,10 @@
 use std::collections::HashMap;
 
 use std::io::BufReader;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::fs::File;
 use subrip;
 
@@ -10,34 +14,19 @@ use xml::reader::{EventReader, XmlEvent};
 use stemmer::Stemmer;
 
 use includes::text_types::*;
-use super::super::context_types::ContextObject;
+use includes::config::Config;
+use includes::error::SriError;
+use includes::metadata;
+use includes::metadata::Metadata;
+use includes::util::warn;
 // Extrait de http://snowball.tartarus.org/algorithms/french/stop.txt
 static STOP_WORDS: &'static [&'static str] = &["au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en", "et", "eux", "il", "je", "la", "le", "leur", "lui", "ma", "mais", "me", "même", "mes", "moi", "mon", "ne", "nos", "notre", "nous", "on", "ou", "par", "pas", "pour", "qu", "que", "qui", "sa", "se", "ses", "son", "sur", "ta", "te", "tes", "toi", "ton", "tu", "un", "une", "vos", "votre", "vous", "c", "d", "j", "l", "à", "m", "n", "s", "t", "y", "été", "étée", "étées", "étés", "étant", "suis", "es", "est", "sommes", "êtes", "sont", "serai", "seras", "sera", "serons", "serez", "seront", "serais", "serait", "serions", "seriez", "seraient", "étais", "était", "étions", "étiez", "étaient", "fus", "fut", "fûmes", "fûtes", "furent", "sois", "soit", "soyons", "soyez", "soient", "fusse", "fusses", "fût", "fussions", "fussiez", "fussent", "ayant", "eu", "eue", "eues", "eus", "ai", "as", "avons", "avez", "ont", "aurai", "auras", "aura", "aurons", "aurez", "auront", "aurais", "aurait", "aurions", "auriez", "auraient", "avais", "avait", "avions", "aviez", "avaient", "eut", "eûmes", "eûtes", "eurent", "aie", "aies", "ait", "ayons", "ayez", "aient", "eusse", "eusses", "eût", "eussions", "eussiez", "eussent", "ceci", "cela", "celà", "cet", "cette", "ici", "ils", "les", "leurs", "quel", "quels", "quelle", "quelles", "sans", "soi"];
 
 impl TextDescriptor {
-	pub fn from_text_file(context: &mut ContextObject, text_file: File) -> Result<TextDescriptor, String> {
+	pub fn from_text_file(config: &Config, text_file: File, metadata: &mut Metadata) -> Result<TextDescriptor, SriError> {
 		let mut stemmer = Stemmer::new("french").unwrap();
-		let min_word_size;
-		let max_word_per_text;
-		match context.get_param("min_word_size") {
-			Some(value) => {
-				match value.parse::<usize>() {
-					Ok(i) => min_word_size = i,
-					Err(e) => return Err(String::from(format!("Invalid value for parameter min_word_size ({})", e))),
-				}
-			},
-			None => panic!("Can't find parameter 'min_word_size' in config"),
-		}
-
-		match context.get_param("max_word_per_text") {
-			Some(value) => {
-				match value.parse::<usize>() {
-					Ok(i) => max_word_per_text = i,
-					Err(e) => return Err(String::from(format!("Invalid value for parameter max_word_per_text ({})", e))),
-				}
-			},
-			None => panic!("Can't find parameter 'max_word_per_text' in config"),
-		}
+		let min_word_size = config.get_min_word_size();
+		let max_word_per_text = config.get_max_word_per_text();
 
 		let mut occurences: HashMap<String, i32> = HashMap::new();
 
@@ -51,26 +40,37 @@ impl TextDescriptor {
 
 		let parser = EventReader::new(file_reader);
 		let mut in_phrase = false;
+		let mut in_title = false;
+		let mut title = String::new();
 		for e in parser {
 			match e {
 				Ok(event) => {
 					match event {
 						XmlEvent::StartDocument {version: _version, encoding: document_encoding, standalone: _standalone} => {
 							if document_encoding.to_lowercase().contains("iso") {
-								println!("Warning : encoding not supported, trying to index anyway ({})", document_encoding);
+								warn(&format!("encoding not supported, trying to index anyway ({})", document_encoding));
 							}
 						},
 						XmlEvent::StartElement { name, .. } => {
 							if name.local_name == "phrase" {
 								in_phrase = true;
 							}
+							if name.local_name == "title" && title.is_empty() {
+								in_title = true;
+							}
 						},
 						XmlEvent::EndElement { name } => {
 							if name.local_name == "phrase" {
 								in_phrase = false;
 							}
+							if name.local_name == "title" {
+								in_title = false;
+							}
 						},
 						XmlEvent::Characters(phrase) => {
+							if in_title {
+								title.push_str(&phrase);
+							}
 							if in_phrase {
 								for word in clean_string(phrase.to_lowercase()).split_whitespace() {
 									if word.len() > min_word_size {
@@ -96,10 +96,14 @@ impl TextDescriptor {
 					}
 				},
 				Err(e) => {
-					return Err(format!("Error in parsing xml text {}", e));
+					return Err(SriError::decode(format!("Error in parsing xml text {}", e)));
 				},
 			}
 		}
+		metadata.set(metadata::WORDS, word_in_file);
+		if !title.trim().is_empty() {
+			metadata.set(metadata::TITLE, title.trim());
+		}
 
 
 		let mut values: Vec<(String, i32)> = occurences.into_iter().collect();
@@ -118,33 +122,14 @@ impl TextDescriptor {
 		}
 
 
-		Ok(TextDescriptor::from_occurences(context.gen_id(String::from("txt")), word_in_file, word_filtered as usize, occurences))
+		Ok(TextDescriptor::from_occurences(String::new(), word_in_file, word_filtered as usize, occurences))
 	}
 
 
-	pub fn from_subrip_file(context: &mut ContextObject, text_file: File) -> Result<TextDescriptor, String> {
+	pub fn from_subrip_file(config: &Config, text_file: File, metadata: &mut Metadata) -> Result<TextDescriptor, SriError> {
 		let mut stemmer = Stemmer::new("french").unwrap();
-		let min_word_size;
-		let max_word_per_text;
-		match context.get_param("min_word_size") {
-			Some(value) => {
-				match value.parse::<usize>() {
-					Ok(i) => min_word_size = i,
-					Err(e) => return Err(String::from(format!("Invalid value for parameter min_word_size ({})", e))),
-				}
-			},
-			None => panic!("Can't find parameter 'min_word_size' in config"),
-		}
-
-		match context.get_param("max_word_per_text") {
-			Some(value) => {
-				match value.parse::<usize>() {
-					Ok(i) => max_word_per_text = i,
-					Err(e) => return Err(String::from(format!("Invalid value for parameter max_word_per_text ({})", e))),
-				}
-			},
-			None => panic!("Can't find parameter 'max_word_per_text' in config"),
-		}
+		let min_word_size = config.get_min_word_size();
+		let max_word_per_text = config.get_max_word_per_text();
 
 		let mut occurences: HashMap<String, i32> = HashMap::new();
 
@@ -178,6 +163,10 @@ impl TextDescriptor {
 				word_in_file += 1;
 			}
 		}
+		metadata.set(metadata::WORDS, word_in_file);
+		if let Some(duration) = try!(subrip_duration(&text_file)) {
+			metadata.set(metadata::DURATION, metadata::format_duration(duration));
+		}
 
 		let mut values: Vec<(String, i32)> = occurences.into_iter().collect();
 		values.sort_by(|&(_, v_a), &(_, v_b)| v_b.cmp(&v_a));
@@ -194,11 +183,77 @@ impl TextDescriptor {
 			}
 		}
 
-		Ok(TextDescriptor::from_occurences(context.gen_id(String::from("txt")), word_in_file, word_filtered as usize, occurences))
+		Ok(TextDescriptor::from_occurences(String::new(), word_in_file, word_filtered as usize, occurences))
+	}
+
+	/// Descriptor of a text without markup, every line being read and the bytes that aren't utf-8 being replaced
+	pub fn from_plain_text_file(config: &Config, mut text_file: File, metadata: &mut Metadata) -> Result<TextDescriptor, SriError> {
+		let mut stemmer = Stemmer::new("french").unwrap();
+		let min_word_size = config.get_min_word_size();
+		let max_word_per_text = config.get_max_word_per_text();
+
+		let mut occurences: HashMap<String, i32> = HashMap::new();
+
+		let mut content = Vec::new();
+		try!(text_file.read_to_end(&mut content));
+
+		let mut word_in_file = 0;
+		let mut word_filtered = 0;
+		let mut word_in_descriptor = 0;
+
+		for word in clean_string(String::from_utf8_lossy(&content).to_lowercase()).split_whitespace() {
+			if word.len() > min_word_size && !STOP_WORDS.contains(&word) {
+				let word_occurence = occurences.entry(stemmer.stem(word)).or_insert(0);
+				*word_occurence += 1;
+			}
+			word_in_file += 1;
+		}
+		metadata.set(metadata::WORDS, word_in_file);
+
+		let mut values: Vec<(String, i32)> = occurences.into_iter().collect();
+		values.sort_by(|&(_, v_a), &(_, v_b)| v_b.cmp(&v_a));
+		occurences = HashMap::new();
+
+		for (word, occurence) in values {
+			if word_in_descriptor < max_word_per_text {
+				occurences.insert(word, occurence);
+				word_filtered += occurence;
+				word_in_descriptor += 1;
+			} else {
+				break;
+			}
+		}
+
+		Ok(TextDescriptor::from_occurences(String::new(), word_in_file, word_filtered as usize, occurences))
 	}
 }
 
 
+/// End of the last sequence of a subtitle, read from its `00:01:02,500 --> 00:01:04,000` timing lines
+fn subrip_duration(text_file: &File) -> Result<Option<f64>, SriError> {
+	let mut file_reader = BufReader::new(text_file);
+	try!(file_reader.seek(SeekFrom::Start(0)));
+	let mut duration = None;
+	for line in file_reader.lines() {
+		let line = match line {
+			Ok(line) => line,
+			Err(_) => break,
+		};
+		if let Some(end) = line.split("-->").nth(1) {
+			let mut fields = end.trim().split(|c| c == ':' || c == ',' || c == '.');
+			let mut seconds = 0f64;
+			for &unit in &[3600f64, 60f64, 1f64, 0.001f64] {
+				match fields.next().and_then(|field| field.trim().parse::<u32>().ok()) {
+					Some(value) => seconds += value as f64 * unit,
+					None => break,
+				}
+			}
+			duration = Some(seconds);
+		}
+	}
+	Ok(duration)
+}
+
 fn clean_string(value: String) -> String {
 	value.replace(".", " ")
 	     .replace(",", " ")
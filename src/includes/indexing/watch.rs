@@ -0,0 +1,386 @@
+use std::cmp;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+use std::time::Instant;
+
+use notify;
+use notify::DebouncedEvent;
+use notify::RecursiveMode;
+use notify::Watcher;
+
+use includes::crawl::Crawler;
+use includes::collection;
+use includes::error::SriError;
+use includes::util::absolute_path;
+use includes::util::warn;
+
+use includes::context_types::ContextObject;
+
+/// Change of a watched directory, its paths being given like the ones the crawl finds
+enum Change {
+	/// File or directory created or written to
+	Written(String),
+	Removed(String),
+	Moved(String, String),
+	/// Events were lost, the whole directory has to be crawled again
+	Rescan,
+}
+
+fn watch_error(directory: &str, error: notify::Error) -> SriError {
+	match error {
+		notify::Error::Io(e) => SriError::io(directory, e),
+		e => SriError::io(directory, io::Error::new(io::ErrorKind::Other, e.to_string())),
+	}
+}
+
+/// Path of an event from the crawled `directory`, `None` when the event happened outside of it
+fn event_path(directory: &str, watched_directory: &Path, path: &Path) -> Option<String> {
+	match path.strip_prefix(watched_directory) {
+		Ok(relative_path) => Some(Path::new(directory).join(relative_path).to_string_lossy().into_owned()),
+		Err(_) => None,
+	}
+}
+
+fn to_change(directory: &str, watched_directory: &Path, event: DebouncedEvent) -> Option<Change> {
+	match event {
+		DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => event_path(directory, watched_directory, &path).map(Change::Written),
+		DebouncedEvent::Remove(path) => event_path(directory, watched_directory, &path).map(Change::Removed),
+		DebouncedEvent::Rename(from, to) => {
+			match (event_path(directory, watched_directory, &from), event_path(directory, watched_directory, &to)) {
+				(Some(from), Some(to)) => Some(Change::Moved(from, to)),
+				(Some(from), None) => Some(Change::Removed(from)),
+				(None, Some(to)) => Some(Change::Written(to)),
+				(None, None) => None,
+			}
+		}
+		DebouncedEvent::Rescan => Some(Change::Rescan),
+		DebouncedEvent::Error(e, path) => {
+			warn(&format!("{} ({})", e, path.map_or(String::from(directory), |path| path.to_string_lossy().into_owned())));
+			None
+		}
+		DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) | DebouncedEvent::Chmod(_) => None,
+	}
+}
+
+/// Events of the next batch: the first event, then the ones following it by less than `delay`, for `max_delay` at most
+///
+/// After a `failed` batch, the batch is empty when no event comes for `max_delay`, so it is tried again even without change.
+/// `None` when the watcher is gone.
+fn next_batch(receiver: &mpsc::Receiver<DebouncedEvent>, delay: Duration, max_delay: Duration, failed: bool) -> Option<Vec<DebouncedEvent>> {
+	let first_event = match failed {
+		false => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+		true => receiver.recv_timeout(max_delay),
+	};
+	let mut events = match first_event {
+		Ok(event) => vec![event],
+		Err(RecvTimeoutError::Timeout) => return Some(Vec::new()),
+		Err(RecvTimeoutError::Disconnected) => return None,
+	};
+	// The watcher already waits for `delay` before sending the events of a path, the batch goes on while other paths change
+	let deadline = Instant::now() + max_delay;
+	loop {
+		let now = Instant::now();
+		if now >= deadline {
+			return Some(events);
+		}
+		match receiver.recv_timeout(cmp::min(delay, deadline - now)) {
+			Ok(event) => events.push(event),
+			Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => return Some(events),
+		}
+	}
+}
+
+impl ContextObject {
+	/// Keeps the index in sync with a directory tree until the process is stopped
+	///
+	/// The tree is crawled and the documents whose file is gone are removed first. Then created and written files are indexed,
+	/// removed ones are removed from the index, and moved ones keep their id under their new path, or are removed when the
+	/// crawler would not find them there. The changes are indexed and saved in batches once the directory stayed quiet for
+	/// `watch_delay` milliseconds, or once the first change of the batch is `watch_max_delay` milliseconds old. The index is
+	/// only locked for writing while a batch is saved. A batch that fails is reported and its changes are saved with the next one.
+	pub fn watch_directory(&mut self, directory: &str, crawler: &Crawler) -> Result<(), SriError> {
+		let delay = Duration::from_millis(self.get_config().get_watch_delay());
+		let max_delay = Duration::from_millis(self.get_config().get_watch_max_delay());
+		let watched_directory: PathBuf = match fs::canonicalize(directory) {
+			Ok(watched_directory) => watched_directory,
+			Err(e) => return Err(SriError::io(directory, e)),
+		};
+		let (sender, receiver) = mpsc::channel();
+		let mut watcher = try!(notify::watcher(sender, delay).map_err(|e| watch_error(directory, e)));
+		try!(watcher.watch(&watched_directory, RecursiveMode::Recursive).map_err(|e| watch_error(directory, e)));
+
+		try!(self.sync_directory(directory, crawler));
+		try!(self.save_index());
+		println!("Watching {}...", directory);
+		let mut failed = false;
+		loop {
+			let events = match next_batch(&receiver, delay, max_delay, failed) {
+				Some(events) => events,
+				None => return Err(SriError::io(directory, io::Error::new(io::ErrorKind::Other, "The watch stopped unexpectedly"))),
+			};
+			let changes: Vec<Change> = events.into_iter().filter_map(|event| to_change(directory, &watched_directory, event)).filter(|change| !self.is_index_change(change)).collect();
+			if changes.is_empty() && !failed {
+				continue;
+			}
+			failed = match self.save_changes(directory, crawler, changes, failed) {
+				Ok(()) => false,
+				Err(e) => {
+					let _ = writeln!(&mut io::stderr(), "Error: {}, the index is synced with {} again with the next batch", e, directory);
+					true
+				}
+			};
+		}
+	}
+
+	/// Applies a batch of changes then saves the index if it changed
+	///
+	/// After a `failed` batch, whose changes may be partly applied and whose index may have been saved by another process since,
+	/// the index is loaded again and synced with the whole directory instead.
+	fn save_changes(&mut self, directory: &str, crawler: &Crawler, changes: Vec<Change>, failed: bool) -> Result<(), SriError> {
+		let changed = match failed {
+			false => try!(self.apply_changes(directory, crawler, changes)),
+			true => {
+				let mut reloaded = ContextObject::new(self.get_config().clone());
+				println!("Loading index...");
+				try!(reloaded.load_index());
+				*self = reloaded;
+				try!(self.sync_directory(directory, crawler))
+			}
+		};
+		if changed > 0 {
+			println!("Saving index to disk...");
+			try!(self.save_index());
+			println!("Index saved!");
+		}
+		Ok(())
+	}
+
+	/// Tells whether a change only concerns the index directory, like the ones each save makes
+	fn is_index_change(&self, change: &Change) -> bool {
+		match *change {
+			Change::Written(ref path) | Change::Removed(ref path) => self.is_index_file(path),
+			Change::Moved(ref from, ref to) => self.is_index_file(from) && self.is_index_file(to),
+			Change::Rescan => false,
+		}
+	}
+
+	/// Indexes the files the crawler finds in the directory and removes the documents whose file is gone, returning how many documents changed
+	fn sync_directory(&mut self, directory: &str, crawler: &Crawler) -> Result<usize, SriError> {
+		let mut changed = try!(self.index_directory(directory, crawler)).count_changed();
+		let missing: Vec<(String, String)> = self.find_documents_under(directory).into_iter().filter(|&(_, ref stored_path)| !Path::new(&self.document_path(stored_path)).is_file()).collect();
+		for (id, stored_path) in missing {
+			println!("Removed {} ({})", id, stored_path);
+			self.remove_document(&id);
+			changed += 1;
+		}
+		Ok(changed)
+	}
+
+	/// Applies a batch of changes in the order they happened, returning how many documents changed
+	fn apply_changes(&mut self, directory: &str, crawler: &Crawler, changes: Vec<Change>) -> Result<usize, SriError> {
+		let mut changed = 0;
+		let mut written: Vec<String> = Vec::new();
+		for change in changes {
+			match change {
+				Change::Written(path) => written.push(path),
+				Change::Removed(path) => {
+					written.retain(|written_path| collection::replace_prefix(written_path, &path, "").is_none());
+					for (id, stored_path) in self.find_documents_under(&path) {
+						println!("Removed {} ({})", id, stored_path);
+						self.remove_document(&id);
+						changed += 1;
+					}
+				}
+				Change::Moved(from, to) => {
+					written.retain(|written_path| collection::replace_prefix(written_path, &from, "").is_none());
+					let (absolute_from, absolute_to) = (absolute_path(&from), absolute_path(&to));
+					for (id, stored_path) in self.find_documents_under(&from) {
+						let moved_path = match collection::replace_prefix(&absolute_path(&self.document_path(&stored_path)), &absolute_from, &absolute_to) {
+							Some(absolute_moved_path) => collection::replace_prefix(&absolute_moved_path, &absolute_to, &to).unwrap_or(absolute_moved_path),
+							None => continue,
+						};
+						if crawler.accepts(directory, &moved_path) {
+							println!("Moved {}: {} to {}", id, stored_path, moved_path);
+							self.move_document(&id, &moved_path);
+						} else {
+							println!("Removed {} ({})", id, stored_path);
+							self.remove_document(&id);
+						}
+						changed += 1;
+					}
+					written.push(to);
+				}
+				Change::Rescan => {
+					println!("Events were lost, crawling {} again", directory);
+					written.clear();
+					changed += try!(self.sync_directory(directory, crawler));
+				}
+			}
+		}
+
+		let mut filenames: Vec<String> = Vec::new();
+		for path in written {
+			if Path::new(&path).is_dir() {
+				filenames.extend(crawler.crawl_under(directory, &path));
+			} else if crawler.accepts(directory, &path) {
+				filenames.push(path);
+			}
+		}
+		let mut seen = HashSet::new();
+		filenames.retain(|filename| !self.is_index_file(filename) && seen.insert(filename.clone()));
+		if !filenames.is_empty() {
+			changed += self.index_files(filenames).count_changed();
+		}
+		Ok(changed)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+	use std::fs;
+	use std::fs::File;
+	use std::io::Write;
+	use std::thread;
+
+	use super::*;
+	use includes::config::Config;
+	use includes::util::test_directory;
+
+	/// Collection with its index inside it, the index storing the paths relative to the collection itself
+	fn collection(name: &str) -> (String, ContextObject) {
+		let directory = test_directory(name);
+		for path in &["a.txt", "sub/b.txt", "sub/c.txt"] {
+			let path = format!("{}/{}", directory, path);
+			fs::create_dir_all(Path::new(&path).parent().unwrap()).unwrap();
+			writeln!(File::create(&path).unwrap(), "words of {}", path).unwrap();
+		}
+		let mut params = HashMap::new();
+		params.insert(String::from("index"), format!("{}/index", directory));
+		params.insert(String::from("collection_root"), String::from(".."));
+		let context = ContextObject::new(Config::check(&params).into_result().unwrap());
+		(directory, context)
+	}
+
+	/// Sends a write event for each number, `interval` apart, then hangs up
+	fn send_events(sender: mpsc::Sender<DebouncedEvent>, numbers: Vec<usize>, interval: Duration) -> thread::JoinHandle<()> {
+		thread::spawn(move || for number in numbers {
+			thread::sleep(interval);
+			sender.send(DebouncedEvent::Write(PathBuf::from(number.to_string()))).unwrap();
+		})
+	}
+
+	fn numbers(events: Vec<DebouncedEvent>) -> Vec<usize> {
+		events.into_iter()
+		      .map(|event| match event {
+			      DebouncedEvent::Write(path) => path.to_string_lossy().parse().unwrap(),
+			      _ => panic!("unexpected event"),
+			     })
+		      .collect()
+	}
+
+	fn stored_paths(context: &ContextObject) -> Vec<String> {
+		let mut paths: Vec<String> = context.get_texts_associations().values().cloned().collect();
+		paths.sort();
+		paths
+	}
+
+	#[test]
+	fn files_deleted_while_not_watching_are_removed_from_the_root() {
+		let (directory, mut context) = collection("watch-root-sync");
+		assert_eq!(context.sync_directory(&directory, &Crawler::new()).unwrap(), 3);
+		assert_eq!(stored_paths(&context), vec!["a.txt", "sub/b.txt", "sub/c.txt"]);
+
+		fs::remove_file(format!("{}/sub/b.txt", directory)).unwrap();
+		assert_eq!(context.sync_directory(&directory, &Crawler::new()).unwrap(), 1);
+		assert_eq!(stored_paths(&context), vec!["a.txt", "sub/c.txt"]);
+	}
+
+	#[test]
+	fn directories_removed_or_moved_under_the_root_are_followed() {
+		let (directory, mut context) = collection("watch-root-changes");
+		let crawler = Crawler::new();
+		context.sync_directory(&directory, &crawler).unwrap();
+		let id = context.find_document("sub/b.txt").unwrap();
+
+		let (from, to) = (format!("{}/sub", directory), format!("{}/moved", directory));
+		fs::rename(&from, &to).unwrap();
+		assert_eq!(context.apply_changes(&directory, &crawler, vec![Change::Moved(from, to.clone())]).unwrap(), 2);
+		assert_eq!(stored_paths(&context), vec!["a.txt", "moved/b.txt", "moved/c.txt"]);
+		assert_eq!(context.find_document("moved/b.txt"), Some(id));
+
+		fs::remove_dir_all(&to).unwrap();
+		assert_eq!(context.apply_changes(&directory, &crawler, vec![Change::Removed(to)]).unwrap(), 2);
+		assert_eq!(stored_paths(&context), vec!["a.txt"]);
+	}
+
+	#[test]
+	fn written_directories_are_crawled_alone() {
+		let (directory, mut context) = collection("watch-written");
+		let mut crawler = Crawler::new();
+		crawler.exclude("sub/c.txt").unwrap();
+		context.sync_directory(&directory, &crawler).unwrap();
+
+		let added = format!("{}/sub/new", directory);
+		fs::create_dir_all(&added).unwrap();
+		writeln!(File::create(format!("{}/d.txt", added)).unwrap(), "new words").unwrap();
+		assert_eq!(context.apply_changes(&directory, &crawler, vec![Change::Written(added)]).unwrap(), 1);
+		assert_eq!(stored_paths(&context), vec!["a.txt", "sub/b.txt", "sub/new/d.txt"]);
+	}
+
+	#[test]
+	fn batches_end_once_the_events_stop() {
+		let (sender, receiver) = mpsc::channel();
+		for number in 0..3 {
+			sender.send(DebouncedEvent::Write(PathBuf::from(number.to_string()))).unwrap();
+		}
+		let late = send_events(sender, vec![3], Duration::from_millis(500));
+		assert_eq!(numbers(next_batch(&receiver, Duration::from_millis(100), Duration::from_secs(10), false).unwrap()), vec![0, 1, 2]);
+		assert_eq!(numbers(next_batch(&receiver, Duration::from_millis(100), Duration::from_secs(10), false).unwrap()), vec![3]);
+		late.join().unwrap();
+		assert!(next_batch(&receiver, Duration::from_millis(100), Duration::from_secs(10), false).is_none());
+	}
+
+	#[test]
+	fn batches_are_bounded_by_their_max_delay() {
+		let (sender, receiver) = mpsc::channel();
+		let events = send_events(sender, (0..40).collect(), Duration::from_millis(10));
+		let mut batches = Vec::new();
+		while let Some(batch) = next_batch(&receiver, Duration::from_secs(1), Duration::from_millis(100), false) {
+			batches.push(numbers(batch));
+		}
+		events.join().unwrap();
+		assert!(batches.len() > 1);
+		assert_eq!(batches.concat(), (0..40).collect::<Vec<usize>>());
+	}
+
+	#[test]
+	fn failed_batches_are_tried_again_without_event() {
+		let (sender, receiver) = mpsc::channel::<DebouncedEvent>();
+		assert_eq!(next_batch(&receiver, Duration::from_millis(10), Duration::from_millis(50), true).map(|batch| batch.len()), Some(0));
+		drop(sender);
+		assert!(next_batch(&receiver, Duration::from_millis(10), Duration::from_millis(50), true).is_none());
+	}
+
+	#[test]
+	fn changes_of_a_batch_are_applied_in_order() {
+		let (directory, mut context) = collection("watch-order");
+		let crawler = Crawler::new();
+		context.sync_directory(&directory, &crawler).unwrap();
+
+		let (created, written) = (format!("{}/sub/d.txt", directory), format!("{}/a.txt", directory));
+		writeln!(File::create(&written).unwrap(), "other words").unwrap();
+		let changes = vec![Change::Written(created.clone()), Change::Written(written.clone()), Change::Removed(created), Change::Written(format!("{}/index/manifest", directory))];
+		let changes: Vec<Change> = changes.into_iter().filter(|change| !context.is_index_change(change)).collect();
+		assert_eq!(changes.len(), 3);
+		assert_eq!(context.apply_changes(&directory, &crawler, changes).unwrap(), 1);
+		assert_eq!(stored_paths(&context), vec!["a.txt", "sub/b.txt", "sub/c.txt"]);
+	}
+}
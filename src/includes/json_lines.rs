@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::io::BufRead;
+use std::io::Write;
+use std::fs::File;
+
+use rustc_serialize::json::Json;
+
+use includes::image_types::ImageDescriptor;
+use includes::sound_types::SoundDescriptor;
+use includes::text_types::TextDescriptor;
+use includes::descriptor::Descriptor;
+use includes::MediaDescriptor;
+use includes::fingerprint::Fingerprint;
+use includes::metadata::Metadata;
+use includes::error::SriError;
+use includes::util::warn;
+
+use includes::context_types::ContextObject;
+
+/// Value of a field of a JSON object, a missing field being an error
+pub fn get_field<'a>(object: &'a Json, key: &str) -> Result<&'a Json, SriError> {
+	match object.find(key) {
+		Some(value) => Ok(value),
+		None => Err(SriError::parse(format!("Missing field '{}'", key))),
+	}
+}
+
+pub fn get_u64_field(object: &Json, key: &str) -> Result<u64, SriError> {
+	match try!(get_field(object, key)).as_u64() {
+		Some(value) => Ok(value),
+		None => Err(SriError::parse(format!("Field '{}' is not a positive integer", key))),
+	}
+}
+
+pub fn get_string_field<'a>(object: &'a Json, key: &str) -> Result<&'a str, SriError> {
+	match try!(get_field(object, key)).as_string() {
+		Some(value) => Ok(value),
+		None => Err(SriError::parse(format!("Field '{}' is not a string", key))),
+	}
+}
+
+pub fn get_array_field<'a>(object: &'a Json, key: &str) -> Result<&'a Vec<Json>, SriError> {
+	match try!(get_field(object, key)).as_array() {
+		Some(value) => Ok(value),
+		None => Err(SriError::parse(format!("Field '{}' is not an array", key))),
+	}
+}
+
+/// Integers of a JSON array, such as the values of a histogram
+pub fn get_i32_array(values: &[Json], key: &str) -> Result<Vec<i32>, SriError> {
+	let mut integers = Vec::with_capacity(values.len());
+	for value in values {
+		match value.as_i64() {
+			Some(integer) if integer >= i32::min_value() as i64 && integer <= i32::max_value() as i64 => integers.push(integer as i32),
+			_ => return Err(SriError::parse(format!("Field '{}' holds a value that is not a 32 bits integer ({})", key, value))),
+		}
+	}
+	Ok(integers)
+}
+
+
+fn fingerprint_to_json(fingerprint: &Fingerprint) -> Json {
+	let mut object = BTreeMap::new();
+	object.insert(String::from("size"), Json::U64(fingerprint.get_size()));
+	object.insert(String::from("mtime"), Json::U64(fingerprint.get_mtime()));
+	object.insert(String::from("hash"), Json::String(format!("{:016x}", fingerprint.get_hash())));
+	Json::Object(object)
+}
+
+fn fingerprint_from_json(object: &Json) -> Result<Fingerprint, SriError> {
+	let hash = try!(get_string_field(object, "hash"));
+	match u64::from_str_radix(hash, 16) {
+		Ok(hash) => Ok(Fingerprint::new(try!(get_u64_field(object, "size")), try!(get_u64_field(object, "mtime")), hash)),
+		Err(e) => Err(SriError::parse(format!("Invalid hash '{}' ({})", hash, e))),
+	}
+}
+
+fn metadata_to_json(metadata: &Metadata) -> Json { Json::Object(metadata.get_fields().iter().map(|(key, value)| (key.clone(), Json::String(value.clone()))).collect()) }
+
+fn metadata_from_json(object: &Json) -> Result<Metadata, SriError> {
+	let fields = match object.as_object() {
+		Some(fields) => fields,
+		None => return Err(SriError::parse("Field 'metadata' is not an object")),
+	};
+	let mut metadata = Metadata::new();
+	for (key, value) in fields {
+		match value.as_string() {
+			Some(value) => metadata.set(key, value),
+			None => return Err(SriError::parse(format!("Metadata '{}' is not a string", key))),
+		}
+	}
+	Ok(metadata)
+}
+
+/// One line of an export: `{"id", "kind", "path", "fingerprint", "metadata", "descriptor"}`, the fingerprint and the metadata being left out when unknown
+fn document_to_json<D: Descriptor>(descriptor: &D, path: &str, fingerprint: Option<&Fingerprint>, metadata: Option<&Metadata>) -> Json {
+	let mut object = BTreeMap::new();
+	object.insert(String::from("id"), Json::String(String::from(descriptor.get_id())));
+	object.insert(String::from("kind"), Json::String(String::from(D::media_kind().get_name())));
+	object.insert(String::from("path"), Json::String(String::from(path)));
+	if let Some(fingerprint) = fingerprint {
+		object.insert(String::from("fingerprint"), fingerprint_to_json(fingerprint));
+	}
+	if let Some(metadata) = metadata {
+		object.insert(String::from("metadata"), metadata_to_json(metadata));
+	}
+	object.insert(String::from("descriptor"), descriptor.to_json());
+	Json::Object(object)
+}
+
+/// A document read from a line of an export
+struct ImportedDocument {
+	path: String,
+	fingerprint: Option<Fingerprint>,
+	metadata: Option<Metadata>,
+	descriptor: MediaDescriptor,
+}
+
+fn document_from_json(line: &str) -> Result<ImportedDocument, SriError> {
+	let object = match Json::from_str(line) {
+		Ok(object) => object,
+		Err(e) => return Err(SriError::parse(format!("Invalid JSON ({})", e))),
+	};
+	let id = String::from(try!(get_string_field(&object, "id")));
+	let payload = try!(get_field(&object, "descriptor"));
+	let descriptor = match try!(get_string_field(&object, "kind")) {
+		"image" => try!(ImageDescriptor::from_json(id, payload)).into_media_descriptor(),
+		"sound" => try!(SoundDescriptor::from_json(id, payload)).into_media_descriptor(),
+		"text" => try!(TextDescriptor::from_json(id, payload)).into_media_descriptor(),
+		kind => return Err(SriError::parse(format!("Unknown kind '{}', expected image, sound or text", kind))),
+	};
+	let fingerprint = match object.find("fingerprint") {
+		Some(fingerprint) => Some(try!(fingerprint_from_json(fingerprint))),
+		None => None,
+	};
+	let metadata = match object.find("metadata") {
+		Some(metadata) => Some(try!(metadata_from_json(metadata))),
+		None => None,
+	};
+	Ok(ImportedDocument {
+		path: String::from(try!(get_string_field(&object, "path"))),
+		fingerprint: fingerprint,
+		metadata: metadata,
+		descriptor: descriptor,
+	})
+}
+
+fn export_base<D: Descriptor>(context: &ContextObject, base: &[D], associations: &HashMap<String, String>, writer: &mut Write) -> Result<usize, SriError> {
+	let mut exported = 0;
+	for descriptor in base {
+		if let Some(path) = associations.get(descriptor.get_id()) {
+			try!(writeln!(writer, "{}", document_to_json(descriptor, path, context.get_fingerprint(descriptor.get_id()), context.get_metadata(descriptor.get_id()))));
+			exported += 1;
+		}
+	}
+	Ok(exported)
+}
+
+impl ContextObject {
+	/// Writes every document of the index as a JSON object per line, returning how many were written
+	///
+	/// The descriptors without association are left out, `verify` reports them.
+	pub fn export_json_lines(&self, writer: &mut Write) -> Result<usize, SriError> {
+		let images = try!(export_base(self, self.get_images_base(), self.get_images_associations(), writer));
+		let sounds = try!(export_base(self, self.get_sounds_base(), self.get_sounds_associations(), writer));
+		let texts = try!(export_base(self, self.get_texts_base(), self.get_texts_associations(), writer));
+		Ok(images + sounds + texts)
+	}
+
+	/// Adds the documents of a JSON Lines export, returning how many were imported
+	///
+	/// A document whose path is already indexed replaces it under the same id. The others keep their id when it is
+	/// free in this index, and get a new one otherwise. Lines that don't match the schema are reported and skipped.
+	pub fn import_json_lines(&mut self, filename: &str) -> Result<usize, SriError> {
+		let file = match File::open(filename) {
+			Ok(file) => BufReader::new(file),
+			Err(e) => return Err(SriError::io(filename, e)),
+		};
+		let mut imported = 0;
+		for (line_number, line) in file.lines().enumerate() {
+			let line = try!(line.map_err(|e| SriError::io(filename, e)));
+			if line.trim().is_empty() {
+				continue;
+			}
+			match document_from_json(&line).and_then(|document| self.import_document(document)) {
+				Ok(_) => imported += 1,
+				Err(e) => warn(&format!("{}, the line is left out", e.in_file(filename).at_line(line_number + 1))),
+			}
+		}
+		Ok(imported)
+	}
+
+	fn import_document(&mut self, document: ImportedDocument) -> Result<String, SriError> {
+		let ImportedDocument { path, fingerprint, metadata, mut descriptor } = document;
+		if let MediaDescriptor::SoundDescriptor(ref sound) = descriptor {
+			let config = self.get_config();
+			if config.get_window_size().map_or(false, |size| size != sound.get_histograms_size()) || config.get_window_levels().map_or(false, |levels| levels != sound.get_histograms_levels()) {
+				return Err(SriError::parse(format!("Sound {} was computed with windows of {} samples and {} levels, unlike the sounds of this index",
+				                                   sound.get_id(),
+				                                   sound.get_histograms_size(),
+				                                   sound.get_histograms_levels())));
+			}
+		}
+
+		let kind = descriptor.get_kind();
+		let id = match self.find_document(&path) {
+			Some(id) => {
+				self.remove_document(&id);
+				id
+			}
+			None => {
+				let imported_id = String::from(descriptor.get_id());
+				match self.reserve_id(kind, &imported_id) {
+					true => imported_id,
+					false => self.new_document_id(kind, &path, fingerprint.as_ref()),
+				}
+			}
+		};
+		descriptor.set_id(id.clone());
+		self.add_document(path, descriptor);
+		if let Some(fingerprint) = fingerprint {
+			self.set_fingerprint(&id, fingerprint);
+		}
+		if let Some(metadata) = metadata {
+			self.set_metadata(&id, metadata);
+		}
+		Ok(id)
+	}
+}
@@ -0,0 +1,172 @@
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::process;
+
+use libc;
+
+use includes::error::SriError;
+use includes::util::absolute_path;
+use includes::util::warn;
+
+/// File of an index directory locked by the processes using the index
+pub static LOCK_FILENAME: &'static str = "lock";
+
+/// How an index directory is locked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockMode {
+	/// Held while reading the index, by any number of processes at once
+	Shared,
+	/// Held by the only process writing the index, from its load to its last save
+	Exclusive,
+}
+
+/// Advisory lock on an index directory, released when dropped or when its process ends
+///
+/// The writer records its process id in the lock file and clears it when releasing the lock, so an id still there once the lock is free
+/// was left by a process that crashed. Locks are only taken on unix systems.
+pub struct IndexLock {
+	directory: String,
+	file: File,
+	mode: LockMode,
+}
+
+impl IndexLock {
+	/// Locks the `index_path` directory, creating it, or fails right away when another process holds a conflicting lock
+	pub fn acquire(index_path: &str, mode: LockMode) -> Result<IndexLock, SriError> {
+		try!(fs::create_dir_all(index_path).map_err(|e| SriError::io(index_path, e)));
+		let path = format!("{}/{}", index_path, LOCK_FILENAME);
+		let file = try!(OpenOptions::new().read(true).write(true).create(true).open(&path).map_err(|e| SriError::io(&path, e)));
+		let mut lock = IndexLock { directory: absolute_path(index_path), file: file, mode: mode };
+		if !try!(try_lock(&lock.file, mode).map_err(|e| SriError::io(&path, e))) {
+			let message = match lock.read_writer() {
+				Some(writer) => format!("Index {} is being written by process {}, retry once it is done", index_path, writer),
+				None => format!("Index {} is being read by other processes, retry once they are done", index_path),
+			};
+			return Err(SriError::busy(message));
+		}
+		if mode == LockMode::Exclusive {
+			if let Some(writer) = lock.read_writer() {
+				warn(&format!("process {} ended without releasing its lock on index {}, the lock is taken over", writer, index_path));
+			}
+			try!(lock.write_writer(&format!("{}\n", process::id())).map_err(|e| SriError::io(&path, e)));
+		}
+		Ok(lock)
+	}
+
+	pub fn get_mode(&self) -> LockMode { self.mode }
+
+	/// Tells if this lock is the one of the `index_path` directory
+	pub fn is_on(&self, index_path: &str) -> bool { absolute_path(index_path) == self.directory }
+
+	/// Process id recorded by the writer holding or having held the lock
+	fn read_writer(&mut self) -> Option<String> {
+		let mut content = String::new();
+		match self.file.seek(SeekFrom::Start(0)).and_then(|_| self.file.read_to_string(&mut content)) {
+			Ok(_) if !content.trim().is_empty() => Some(String::from(content.trim())),
+			_ => None,
+		}
+	}
+
+	fn write_writer(&mut self, writer: &str) -> io::Result<()> {
+		try!(self.file.set_len(0));
+		try!(self.file.seek(SeekFrom::Start(0)));
+		try!(self.file.write_all(writer.as_bytes()));
+		self.file.sync_all()
+	}
+}
+
+impl Drop for IndexLock {
+	fn drop(&mut self) {
+		if self.mode == LockMode::Exclusive {
+			let _ = self.write_writer("");
+		}
+		unlock(&self.file);
+	}
+}
+
+/// Locks a file without waiting, `false` meaning that another process holds a conflicting lock
+#[cfg(unix)]
+fn try_lock(file: &File, mode: LockMode) -> io::Result<bool> {
+	use std::os::unix::io::AsRawFd;
+	let operation = match mode {
+		LockMode::Shared => libc::LOCK_SH,
+		LockMode::Exclusive => libc::LOCK_EX,
+	};
+	match unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } {
+		0 => Ok(true),
+		_ => {
+			let error = io::Error::last_os_error();
+			match error.raw_os_error() {
+				Some(code) if code == libc::EWOULDBLOCK => Ok(false),
+				_ => Err(error),
+			}
+		}
+	}
+}
+
+#[cfg(not(unix))]
+fn try_lock(_file: &File, _mode: LockMode) -> io::Result<bool> { Ok(true) }
+
+#[cfg(unix)]
+fn unlock(file: &File) {
+	use std::os::unix::io::AsRawFd;
+	unsafe {
+		libc::flock(file.as_raw_fd(), libc::LOCK_UN);
+	}
+}
+
+#[cfg(not(unix))]
+fn unlock(_file: &File) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::*;
+	use includes::util::test_directory;
+
+	fn assert_busy(result: Result<IndexLock, SriError>) {
+		match result {
+			Err(SriError::Busy(_)) => (),
+			Err(e) => panic!("unexpected error: {}", e),
+			Ok(_) => panic!("the lock was acquired"),
+		}
+	}
+
+	#[test]
+	fn readers_share_the_index() {
+		let index_path = test_directory("lock-shared");
+		let first = IndexLock::acquire(&index_path, LockMode::Shared).unwrap();
+		let second = IndexLock::acquire(&index_path, LockMode::Shared).unwrap();
+		assert_busy(IndexLock::acquire(&index_path, LockMode::Exclusive));
+		drop(first);
+		drop(second);
+		assert!(IndexLock::acquire(&index_path, LockMode::Exclusive).is_ok());
+	}
+
+	#[test]
+	fn writers_keep_everyone_out() {
+		let index_path = test_directory("lock-exclusive");
+		let writer = IndexLock::acquire(&index_path, LockMode::Exclusive).unwrap();
+		assert_eq!(writer.get_mode(), LockMode::Exclusive);
+		assert!(writer.is_on(&format!("{}/.", index_path)));
+		assert_busy(IndexLock::acquire(&index_path, LockMode::Exclusive));
+		assert_busy(IndexLock::acquire(&index_path, LockMode::Shared));
+		drop(writer);
+		assert!(IndexLock::acquire(&index_path, LockMode::Shared).is_ok());
+	}
+
+	#[test]
+	fn writers_record_their_process_until_released() {
+		let index_path = test_directory("lock-writer");
+		let mut writer = IndexLock::acquire(&index_path, LockMode::Exclusive).unwrap();
+		assert_eq!(writer.read_writer(), Some(process::id().to_string()));
+		drop(writer);
+		let mut reader = IndexLock::acquire(&index_path, LockMode::Shared).unwrap();
+		assert_eq!(reader.read_writer(), None);
+	}
+}
@@ -0,0 +1,460 @@
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+
+use includes::config::IndexFormat;
+use includes::error::SriError;
+use includes::util::fnv1a;
+use includes::util::warn;
+
+/// Manifest of the last complete save of an index directory
+pub static MANIFEST_FILENAME: &'static str = "manifest";
+/// Manifest of a save in progress, renamed to `MANIFEST_FILENAME` once every file is in place
+static NEW_MANIFEST_FILENAME: &'static str = "manifest.new";
+
+static MANIFEST_HEADER: &'static str = "sri-manifest 1";
+static TEMPORARY_EXTENSION: &'static str = ".tmp";
+static SEGMENT_PREFIX: &'static str = "segment-";
+
+
+/// File written by a save, with the length and checksum it had
+pub struct ManifestEntry {
+	filename: String,
+	length: u64,
+	checksum: u64,
+}
+
+impl ManifestEntry {
+	pub fn get_filename(&self) -> &str { &self.filename }
+
+	pub fn get_length(&self) -> u64 { self.length }
+
+	pub fn get_checksum(&self) -> u64 { self.checksum }
+}
+
+/// Directory holding the documents appended to the index by a save, with the same layout as the index itself
+#[derive(Clone)]
+pub struct Segment {
+	name: String,
+	format: IndexFormat,
+}
+
+impl Segment {
+	pub fn get_name(&self) -> &str { &self.name }
+
+	pub fn get_format(&self) -> IndexFormat { self.format }
+}
+
+/// Files of an index that belong to the same save
+pub struct Manifest {
+	generation: u64,
+	format: IndexFormat,
+	files: Vec<ManifestEntry>,
+	segments: Vec<Segment>,
+}
+
+impl Manifest {
+	/// Number of the save, incremented by each one
+	pub fn get_generation(&self) -> u64 { self.generation }
+
+	pub fn get_format(&self) -> IndexFormat { self.format }
+
+	pub fn get_files(&self) -> &Vec<ManifestEntry> { &self.files }
+
+	/// Segments appended since the last compaction, oldest first
+	pub fn get_segments(&self) -> &Vec<Segment> { &self.segments }
+
+	/// Reads a manifest, refusing it when it was not completely written
+	pub fn from_file(path: &str) -> Result<Manifest, SriError> {
+		let file = try!(File::open(path).map_err(|e| SriError::io(path, e)));
+		let mut lines = Vec::new();
+		for line in BufReader::new(file).lines() {
+			lines.push(try!(line.map_err(|e| SriError::io(path, e))));
+		}
+		Manifest::parse(&lines).map_err(|e| e.in_file(path))
+	}
+
+	fn parse(lines: &[String]) -> Result<Manifest, SriError> {
+		if lines.first().map(String::as_ref) != Some(MANIFEST_HEADER) {
+			return Err(SriError::parse("Not an index manifest").at_line(1));
+		}
+		if lines.last().map(String::as_ref) != Some("end") {
+			return Err(SriError::parse("Truncated manifest"));
+		}
+
+		let mut generation = None;
+		let mut format = None;
+		let mut files = Vec::new();
+		let mut segments = Vec::new();
+		for (line_number, line) in lines.iter().enumerate().skip(1).take(lines.len() - 2) {
+			let fields: Vec<&str> = line.splitn(4, ' ').collect();
+			match (fields[0], fields.len()) {
+				("generation", 2) => generation = fields[1].parse::<u64>().ok(),
+				("format", 2) => format = IndexFormat::from_name(fields[1]),
+				("segment", 3) => {
+					match IndexFormat::from_name(fields[1]) {
+						Some(segment_format) => segments.push(Segment { name: String::from(fields[2]), format: segment_format }),
+						None => return Err(SriError::parse(format!("Invalid segment entry '{}'", line)).at_line(line_number + 1)),
+					}
+				},
+				("file", 4) => {
+					match (fields[1].parse::<u64>(), u64::from_str_radix(fields[2], 16)) {
+						(Ok(length), Ok(checksum)) => files.push(ManifestEntry { filename: String::from(fields[3]), length: length, checksum: checksum }),
+						_ => return Err(SriError::parse(format!("Invalid file entry '{}'", line)).at_line(line_number + 1)),
+					}
+				},
+				_ => return Err(SriError::parse(format!("Invalid manifest line '{}'", line)).at_line(line_number + 1)),
+			}
+		}
+
+		match (generation, format) {
+			(Some(generation), Some(format)) => Ok(Manifest { generation: generation, format: format, files: files, segments: segments }),
+			_ => Err(SriError::parse("Manifest without generation or format")),
+		}
+	}
+
+	/// Writes the manifest and waits for it to reach the disk
+	fn write(&self, path: &str) -> Result<(), SriError> {
+		let file = try!(File::create(path).map_err(|e| SriError::io(path, e)));
+		{
+			let mut manifest_writer = BufWriter::new(&file);
+			let mut content = format!("{}\ngeneration {}\nformat {}\n", MANIFEST_HEADER, self.generation, self.format.get_name());
+			for entry in &self.files {
+				content.push_str(&format!("file {} {:016x} {}\n", entry.length, entry.checksum, entry.filename));
+			}
+			for segment in &self.segments {
+				content.push_str(&format!("segment {} {}\n", segment.format.get_name(), segment.name));
+			}
+			content.push_str("end\n");
+			try!(manifest_writer.write_all(content.as_bytes()).map_err(|e| SriError::io(path, e)));
+			try!(manifest_writer.flush().map_err(|e| SriError::io(path, e)));
+		}
+		file.sync_all().map_err(|e| SriError::io(path, e))
+	}
+
+	/// Checks that every file of the manifest is still the one that was saved, by its checksum
+	pub fn check_files(&self, index_path: &str) -> Result<(), SriError> {
+		for entry in &self.files {
+			try!(self.check_file(index_path, entry));
+		}
+		Ok(())
+	}
+
+	/// Checks that a file of the manifest has the content it was saved with, which means reading it whole
+	pub fn check_file(&self, index_path: &str, entry: &ManifestEntry) -> Result<(), SriError> {
+		let path = format!("{}/{}", index_path, entry.filename);
+		let (length, checksum) = try!(file_checksum(&path));
+		if length != entry.length || checksum != entry.checksum {
+			return Err(self.mismatch(&path));
+		}
+		Ok(())
+	}
+
+	/// Checks that every file of the manifest still has the length it was saved with, without reading them
+	///
+	/// Enough to refuse the files torn by an interrupted save or replaced by another one, the checksums being left to `check_files`.
+	pub fn check_lengths(&self, index_path: &str) -> Result<(), SriError> {
+		for entry in &self.files {
+			let path = format!("{}/{}", index_path, entry.filename);
+			let metadata = try!(fs::metadata(&path).map_err(|e| SriError::io(&path, e)));
+			if metadata.len() != entry.length {
+				return Err(self.mismatch(&path));
+			}
+		}
+		Ok(())
+	}
+
+	fn mismatch(&self, path: &str) -> SriError {
+		SriError::parse(format!("File does not match generation {} of the manifest, the index was modified or torn by an interrupted save", self.generation)).in_file(path)
+	}
+}
+
+
+/// Length and checksum of the content of a file
+fn file_checksum(path: &str) -> Result<(u64, u64), SriError> {
+	let mut content = Vec::new();
+	let file = try!(File::open(path).map_err(|e| SriError::io(path, e)));
+	try!(BufReader::new(file).read_to_end(&mut content).map_err(|e| SriError::io(path, e)));
+	Ok((content.len() as u64, fnv1a(&content)))
+}
+
+fn temporary_path(index_path: &str, filename: &str, generation: u64) -> String { format!("{}/{}.{}{}", index_path, filename, generation, TEMPORARY_EXTENSION) }
+
+/// Waits for the renames done in a directory to reach the disk
+fn sync_directory(index_path: &str) -> Result<(), SriError> {
+	let directory = try!(File::open(index_path).map_err(|e| SriError::io(index_path, e)));
+	directory.sync_all().map_err(|e| SriError::io(index_path, e))
+}
+
+/// Removes the segment directories that are not part of the manifest, left by compactions or interrupted saves
+fn remove_unlisted_segments(index_path: &str, manifest: &Manifest) -> Result<(), SriError> {
+	for entry in try!(fs::read_dir(index_path).map_err(|e| SriError::io(index_path, e))) {
+		let path = try!(entry.map_err(|e| SriError::io(index_path, e))).path();
+		let name = match path.file_name() {
+			Some(name) => String::from(name.to_string_lossy()),
+			None => continue,
+		};
+		if path.is_dir() && name.starts_with(SEGMENT_PREFIX) && !manifest.segments.iter().any(|segment| segment.name == name) {
+			try!(fs::remove_dir_all(&path).map_err(|e| SriError::io(&path.to_string_lossy(), e)));
+		}
+	}
+	Ok(())
+}
+
+fn remove_temporary_files(index_path: &str) -> Result<(), SriError> {
+	for entry in try!(fs::read_dir(index_path).map_err(|e| SriError::io(index_path, e))) {
+		let path = try!(entry.map_err(|e| SriError::io(index_path, e))).path();
+		if path.to_string_lossy().ends_with(TEMPORARY_EXTENSION) {
+			try!(fs::remove_file(&path).map_err(|e| SriError::io(&path.to_string_lossy(), e)));
+		}
+	}
+	Ok(())
+}
+
+/// Tells whether a save of the index was interrupted, leaving its new manifest behind
+pub fn is_interrupted(index_path: &str) -> bool { Path::new(&format!("{}/{}", index_path, NEW_MANIFEST_FILENAME)).exists() }
+
+/// Finishes or cancels a save that was interrupted
+///
+/// A complete new manifest means every file of the save reached the disk, so the save is rolled forward by renaming the remaining temporary files.
+/// Otherwise the save never touched the files of the previous one and is rolled back by removing what it wrote.
+pub fn recover(index_path: &str) -> Result<(), SriError> {
+	if !is_interrupted(index_path) {
+		return Ok(());
+	}
+	let new_manifest_path = format!("{}/{}", index_path, NEW_MANIFEST_FILENAME);
+
+	match Manifest::from_file(&new_manifest_path) {
+		Ok(manifest) => {
+			warn(&format!("finishing the interrupted save of generation {} in {}", manifest.generation, index_path));
+			for entry in &manifest.files {
+				let temporary = temporary_path(index_path, &entry.filename, manifest.generation);
+				if Path::new(&temporary).exists() {
+					let path = format!("{}/{}", index_path, entry.filename);
+					try!(fs::rename(&temporary, &path).map_err(|e| SriError::io(&temporary, e)));
+				}
+			}
+			try!(manifest.check_files(index_path));
+			let manifest_path = format!("{}/{}", index_path, MANIFEST_FILENAME);
+			try!(fs::rename(&new_manifest_path, &manifest_path).map_err(|e| SriError::io(&new_manifest_path, e)));
+		},
+		Err(_) => {
+			warn(&format!("rolling back an interrupted save in {}", index_path));
+			try!(fs::remove_file(&new_manifest_path).map_err(|e| SriError::io(&new_manifest_path, e)));
+		},
+	}
+	try!(remove_temporary_files(index_path));
+	sync_directory(index_path)
+}
+
+/// Manifest of an index directory after checking the lengths of its files, `None` for an index saved without one
+pub fn load_manifest(index_path: &str) -> Result<Option<Manifest>, SriError> {
+	let manifest_path = format!("{}/{}", index_path, MANIFEST_FILENAME);
+	if !Path::new(&manifest_path).exists() {
+		return Ok(None);
+	}
+	let manifest = try!(Manifest::from_file(&manifest_path));
+	try!(manifest.check_lengths(index_path));
+	Ok(Some(manifest))
+}
+
+
+/// Save writing every file of an index aside before putting them all in place at once
+pub struct SaveTransaction {
+	index_path: String,
+	generation: u64,
+	format: IndexFormat,
+	kept_files: Vec<ManifestEntry>,
+	segments: Vec<Segment>,
+	filenames: Vec<String>,
+}
+
+impl SaveTransaction {
+	/// Starts a save replacing the whole index in `index_path`, finishing first any save interrupted there and removing the files it left aside
+	pub fn begin(index_path: &str, format: IndexFormat) -> Result<SaveTransaction, SriError> {
+		try!(fs::create_dir_all(index_path).map_err(|e| SriError::io(index_path, e)));
+		try!(recover(index_path));
+		try!(remove_temporary_files(index_path));
+		let manifest_path = format!("{}/{}", index_path, MANIFEST_FILENAME);
+		let generation = match Manifest::from_file(&manifest_path) {
+			Ok(manifest) => manifest.generation + 1,
+			Err(_) => 1,
+		};
+		Ok(SaveTransaction { index_path: String::from(index_path), generation: generation, format: format, kept_files: Vec::new(), segments: Vec::new(), filenames: Vec::new() })
+	}
+
+	/// Starts a save adding files to the index described by `manifest`, which keeps all of its current files
+	pub fn append(index_path: &str, manifest: Manifest) -> SaveTransaction {
+		SaveTransaction {
+			index_path: String::from(index_path),
+			generation: manifest.generation + 1,
+			format: manifest.format,
+			kept_files: manifest.files,
+			segments: manifest.segments,
+			filenames: Vec::new(),
+		}
+	}
+
+	/// Creates the directory of a new segment, returning its name to prefix the files written in it
+	pub fn add_segment(&mut self, format: IndexFormat) -> Result<String, SriError> {
+		let name = format!("{}{:06}", SEGMENT_PREFIX, self.generation);
+		let path = format!("{}/{}", self.index_path, name);
+		try!(fs::create_dir_all(&path).map_err(|e| SriError::io(&path, e)));
+		self.segments.push(Segment { name: name.clone(), format: format });
+		Ok(name)
+	}
+
+	/// Path the file `filename` of the index has to be written to during the save
+	pub fn file_path(&mut self, filename: &str) -> String {
+		self.filenames.push(String::from(filename));
+		temporary_path(&self.index_path, filename, self.generation)
+	}
+
+	/// Puts every file written during the save in place, the save being complete once the manifest is renamed
+	pub fn commit(self) -> Result<Manifest, SriError> {
+		let index_path = &self.index_path;
+		let mut files = self.kept_files;
+		for filename in &self.filenames {
+			let temporary = temporary_path(index_path, filename, self.generation);
+			try!(File::open(&temporary).and_then(|file| file.sync_all()).map_err(|e| SriError::io(&temporary, e)));
+			let (length, checksum) = try!(file_checksum(&temporary));
+			files.push(ManifestEntry { filename: filename.clone(), length: length, checksum: checksum });
+		}
+		let manifest = Manifest { generation: self.generation, format: self.format, files: files, segments: self.segments };
+
+		let new_manifest_path = format!("{}/{}", index_path, NEW_MANIFEST_FILENAME);
+		try!(manifest.write(&new_manifest_path));
+		try!(sync_directory(index_path));
+
+		for filename in &self.filenames {
+			let temporary = temporary_path(index_path, filename, self.generation);
+			let path = format!("{}/{}", index_path, filename);
+			try!(fs::rename(&temporary, &path).map_err(|e| SriError::io(&temporary, e)));
+		}
+		for segment in &manifest.segments {
+			try!(sync_directory(&format!("{}/{}", index_path, segment.name)));
+		}
+		let manifest_path = format!("{}/{}", index_path, MANIFEST_FILENAME);
+		try!(fs::rename(&new_manifest_path, &manifest_path).map_err(|e| SriError::io(&new_manifest_path, e)));
+		try!(sync_directory(index_path));
+		try!(remove_unlisted_segments(index_path, &manifest));
+		Ok(manifest)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::fs::File;
+	use std::io::Read;
+	use std::io::Write;
+
+	use super::*;
+	use includes::config::IndexFormat;
+	use includes::util::test_directory;
+
+	fn write_file(path: &str, content: &str) { File::create(path).unwrap().write_all(content.as_bytes()).unwrap(); }
+
+	fn read_file(path: &str) -> String {
+		let mut content = String::new();
+		File::open(path).unwrap().read_to_string(&mut content).unwrap();
+		content
+	}
+
+	/// Index directory whose first save holds a `base` file
+	fn saved_index(name: &str) -> String {
+		let index_path = test_directory(name);
+		let mut transaction = SaveTransaction::begin(&index_path, IndexFormat::Text).unwrap();
+		let path = transaction.file_path("base");
+		write_file(&path, "old base");
+		transaction.commit().unwrap();
+		index_path
+	}
+
+	/// Leaves a second save of `base` interrupted before its files were put in place, with its new manifest written whole or torn
+	fn interrupt_save(index_path: &str, complete: bool) {
+		write_file(&temporary_path(index_path, "base", 2), "new base");
+		let manifest = Manifest { generation: 2, format: IndexFormat::Text, files: vec![ManifestEntry { filename: String::from("base"), length: 8, checksum: fnv1a(b"new base") }], segments: Vec::new() };
+		let new_manifest_path = format!("{}/{}", index_path, NEW_MANIFEST_FILENAME);
+		manifest.write(&new_manifest_path).unwrap();
+		if !complete {
+			let content = read_file(&new_manifest_path);
+			write_file(&new_manifest_path, &content[..content.len() - 4]);
+		}
+	}
+
+	fn leftovers(index_path: &str) -> Vec<String> {
+		fs::read_dir(index_path).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).filter(|name| name.ends_with(TEMPORARY_EXTENSION) || name == NEW_MANIFEST_FILENAME).collect()
+	}
+
+	#[test]
+	fn committed_saves_are_loaded_back() {
+		let index_path = saved_index("manifest-commit");
+		let manifest = load_manifest(&index_path).unwrap().unwrap();
+		assert_eq!(manifest.get_generation(), 1);
+		assert_eq!(manifest.get_files()[0].get_filename(), "base");
+		assert_eq!(manifest.get_files()[0].get_checksum(), fnv1a(b"old base"));
+		assert_eq!(read_file(&format!("{}/base", index_path)), "old base");
+		assert!(leftovers(&index_path).is_empty());
+
+		let mut transaction = SaveTransaction::begin(&index_path, IndexFormat::Text).unwrap();
+		let path = transaction.file_path("base");
+		write_file(&path, "new base");
+		assert_eq!(read_file(&format!("{}/base", index_path)), "old base");
+		assert_eq!(transaction.commit().unwrap().get_generation(), 2);
+		assert_eq!(read_file(&format!("{}/base", index_path)), "new base");
+	}
+
+	#[test]
+	fn directories_without_manifest_have_none() {
+		let index_path = test_directory("manifest-missing");
+		assert!(load_manifest(&index_path).unwrap().is_none());
+		assert!(!is_interrupted(&index_path));
+	}
+
+	#[test]
+	fn saves_with_a_complete_manifest_are_rolled_forward() {
+		let index_path = saved_index("manifest-forward");
+		interrupt_save(&index_path, true);
+		assert!(is_interrupted(&index_path));
+		recover(&index_path).unwrap();
+		assert_eq!(read_file(&format!("{}/base", index_path)), "new base");
+		assert_eq!(load_manifest(&index_path).unwrap().unwrap().get_generation(), 2);
+		assert!(leftovers(&index_path).is_empty());
+	}
+
+	#[test]
+	fn saves_with_a_torn_manifest_are_rolled_back() {
+		let index_path = saved_index("manifest-back");
+		interrupt_save(&index_path, false);
+		recover(&index_path).unwrap();
+		assert_eq!(read_file(&format!("{}/base", index_path)), "old base");
+		assert_eq!(load_manifest(&index_path).unwrap().unwrap().get_generation(), 1);
+		assert!(leftovers(&index_path).is_empty());
+	}
+
+	#[test]
+	fn modified_files_are_detected() {
+		let index_path = saved_index("manifest-modified");
+		let manifest = load_manifest(&index_path).unwrap().unwrap();
+		write_file(&format!("{}/base", index_path), "OLD BASE");
+		assert!(manifest.check_lengths(&index_path).is_ok());
+		assert!(manifest.check_files(&index_path).is_err());
+		write_file(&format!("{}/base", index_path), "old");
+		assert!(load_manifest(&index_path).is_err());
+	}
+
+	#[test]
+	fn truncated_manifests_are_refused() {
+		let lines: Vec<String> = vec![String::from(MANIFEST_HEADER), String::from("generation 3"), String::from("format text")];
+		assert!(Manifest::parse(&lines).is_err());
+		let mut complete = lines.clone();
+		complete.push(String::from("end"));
+		assert_eq!(Manifest::parse(&complete).unwrap().get_generation(), 3);
+	}
+}
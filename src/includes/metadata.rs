@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+/// Size of the file, in bytes, taken from the fingerprint of the document
+pub const SIZE: &'static str = "size";
+/// Modification time of the file, in milliseconds since the epoch, taken from the fingerprint of the document
+pub const MTIME: &'static str = "mtime";
+/// Name of the media type of the registry the document was extracted with
+pub const MEDIA_TYPE: &'static str = "type";
+/// Width of an image, in pixels
+pub const WIDTH: &'static str = "width";
+/// Height of an image, in pixels
+pub const HEIGHT: &'static str = "height";
+/// Sample rate of a sound, in Hz
+pub const SAMPLE_RATE: &'static str = "sample_rate";
+/// Duration of a sound or of a subtitle, in seconds
+pub const DURATION: &'static str = "duration";
+/// Number of words of a text, before filtering
+pub const WORDS: &'static str = "words";
+/// Title of an xml text
+pub const TITLE: &'static str = "title";
+
+/// Descriptive fields of a document, such as its media type or its dimensions, stored next to its association
+///
+/// The size and the modification time of the file are not part of it, its fingerprint already holds them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+	fields: BTreeMap<String, String>,
+}
+
+impl Metadata {
+	pub fn new() -> Metadata { Metadata { fields: BTreeMap::new() } }
+
+	pub fn set<V: ToString>(&mut self, key: &str, value: V) { self.fields.insert(String::from(key), value.to_string()); }
+
+	pub fn get(&self, key: &str) -> Option<&str> { self.fields.get(key).map(String::as_str) }
+
+	/// Every field, sorted by key
+	pub fn get_fields(&self) -> &BTreeMap<String, String> { &self.fields }
+
+	pub fn is_empty(&self) -> bool { self.fields.is_empty() }
+}
+
+/// Duration in seconds, with the millisecond precision metadata are given with
+pub fn format_duration(seconds: f64) -> String { format!("{:.3}", seconds) }
+
+/// Escapes the backslashes, equal signs, tabs and line breaks of a value so it fits in a field of an associations line
+pub fn escape(value: &str) -> String { value.replace('\\', "\\\\").replace('=', "\\=").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r") }
+
+/// Splits a `key=value` field at its first equal sign that is not escaped
+pub fn split_field(field: &str) -> Option<(&str, &str)> {
+	let mut characters = field.char_indices();
+	while let Some((index, character)) = characters.next() {
+		match character {
+			'\\' => {
+				characters.next();
+			}
+			'=' => return Some((&field[..index], &field[index + 1..])),
+			_ => (),
+		}
+	}
+	None
+}
+
+/// Reverse of `escape`, unknown escape sequences being kept as they are
+pub fn unescape(value: &str) -> String {
+	let mut unescaped = String::with_capacity(value.len());
+	let mut characters = value.chars();
+	while let Some(character) = characters.next() {
+		if character != '\\' {
+			unescaped.push(character);
+			continue;
+		}
+		match characters.next() {
+			Some('t') => unescaped.push('\t'),
+			Some('n') => unescaped.push('\n'),
+			Some('r') => unescaped.push('\r'),
+			Some('\\') => unescaped.push('\\'),
+			Some('=') => unescaped.push('='),
+			Some(other) => {
+				unescaped.push('\\');
+				unescaped.push(other);
+			}
+			None => unescaped.push('\\'),
+		}
+	}
+	unescaped
+}
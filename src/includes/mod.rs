@@ -1,23 +1,92 @@
 pub mod util;
+pub mod error;
+pub mod config;
+pub mod fingerprint;
+pub mod metadata;
+pub mod collection;
 
+pub mod descriptor;
 pub mod image_types;
 pub mod sound_types;
 pub mod text_types;
 
+pub mod registry;
 pub mod indexing;
+pub mod crawl;
 pub mod querying;
 
+pub mod binary_index;
+pub mod manifest;
+pub mod lock;
+pub mod snapshot;
+pub mod verify;
+pub mod json_lines;
 pub mod context_types;
 
 extern crate time;
 
+use includes::descriptor::Descriptor;
+
 pub fn get_time_millis() -> i64 {
 	let timespec = time::get_time();
 	timespec.sec + timespec.nsec as i64 / 1000 / 1000
 }
 
-pub enum Descriptor {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaKind {
+	Image,
+	Sound,
+	Text,
+}
+
+impl MediaKind {
+	/// Prefix of the ids given to the documents of this kind
+	pub fn get_prefix(&self) -> &'static str {
+		match *self {
+			MediaKind::Image => "img",
+			MediaKind::Sound => "snd",
+			MediaKind::Text => "txt",
+		}
+	}
+
+	pub fn get_name(&self) -> &'static str {
+		match *self {
+			MediaKind::Image => "image",
+			MediaKind::Sound => "sound",
+			MediaKind::Text => "text",
+		}
+	}
+}
+
+pub enum MediaDescriptor {
 	ImageDescriptor(image_types::ImageDescriptor),
 	SoundDescriptor(sound_types::SoundDescriptor),
 	TextDescriptor(text_types::TextDescriptor),
 }
+
+impl MediaDescriptor {
+	pub fn get_id(&self) -> &str {
+		match *self {
+			MediaDescriptor::ImageDescriptor(ref descriptor) => descriptor.get_id(),
+			MediaDescriptor::SoundDescriptor(ref descriptor) => descriptor.get_id(),
+			MediaDescriptor::TextDescriptor(ref descriptor) => descriptor.get_id(),
+		}
+	}
+
+	/// Gives the descriptor the id of the document it replaces
+	pub fn set_id(&mut self, id: String) {
+		match *self {
+			MediaDescriptor::ImageDescriptor(ref mut descriptor) => descriptor.set_id(id),
+			MediaDescriptor::SoundDescriptor(ref mut descriptor) => descriptor.set_id(id),
+			MediaDescriptor::TextDescriptor(ref mut descriptor) => descriptor.set_id(id),
+		}
+	}
+
+	pub fn get_kind(&self) -> MediaKind {
+		match *self {
+			MediaDescriptor::ImageDescriptor(_) => MediaKind::Image,
+			MediaDescriptor::SoundDescriptor(_) => MediaKind::Sound,
+			MediaDescriptor::TextDescriptor(_) => MediaKind::Text,
+		}
+	}
+}
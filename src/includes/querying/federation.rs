@@ -0,0 +1,191 @@
+use std::path::Path;
+use std::cmp::Ordering;
+
+use includes::config::Config;
+use includes::error::SriError;
+use includes::querying::SearchResult;
+use includes::querying::ResultFormat;
+use includes::querying::rank_results;
+use includes::querying::run_queries;
+
+use includes::context_types::ContextObject;
+
+/// Separates the name of a shard from the id of one of its documents in the results of a federation
+pub const SHARD_SEPARATOR: char = ':';
+
+/// Size and levels of the windows the sounds of an index were indexed with, `None` when it has no sound
+fn sound_window(context: &ContextObject) -> Option<(usize, usize)> { context.get_sounds_base().first().map(|sound| (sound.get_histograms_size(), sound.get_histograms_levels())) }
+
+/// Several indexes queried as one, each of them being a shard of the collection
+///
+/// The ids of the results are prefixed by the name of the shard they come from, `shard:id`, so they can be traced back to their index.
+pub struct Federation {
+	shards: Vec<(String, ContextObject)>,
+}
+
+impl Federation {
+	pub fn new() -> Federation { Federation { shards: Vec::new() } }
+
+	/// Loads the index of each directory as a shard named after the directory, with the config given for its directory
+	///
+	/// A shard can be given as `NAME=DIR` to choose its name, which is needed when several directories have the same name.
+	/// The index of the config itself is not loaded.
+	pub fn open(config: &Config, shards: &[String]) -> Result<Federation, SriError> {
+		let mut federation = Federation::new();
+		for shard in shards {
+			let mut fields = shard.splitn(2, '=');
+			let (name, index_path) = match (fields.next(), fields.next()) {
+				(Some(name), Some(index_path)) => (String::from(name), index_path),
+				_ => {
+					match Path::new(shard).file_name() {
+						Some(name) => (name.to_string_lossy().into_owned(), shard.as_str()),
+						None => return Err(SriError::config(format!("Can't name the shard '{}', give it as NAME=DIR", shard))),
+					}
+				}
+			};
+			if !Path::new(index_path).exists() {
+				return Err(SriError::config(format!("Shard '{}' has no index in {}", name, index_path)));
+			}
+			let mut context = ContextObject::new(config.with_index(index_path));
+			try!(context.load_index());
+			try!(federation.add_shard(name, context));
+		}
+		Ok(federation)
+	}
+
+	/// Adds a shard, its name having to be unique and free of the shard separator
+	///
+	/// Its sounds must have been indexed with the windows of the config and of the other shards, or their scores could not be compared.
+	pub fn add_shard(&mut self, name: String, context: ContextObject) -> Result<(), SriError> {
+		if name.is_empty() || name.contains(SHARD_SEPARATOR) || name.contains(char::is_whitespace) {
+			return Err(SriError::config(format!("Invalid shard name '{}'", name)));
+		}
+		if self.shards.iter().any(|&(ref shard_name, _)| *shard_name == name) {
+			return Err(SriError::config(format!("Two shards are named '{}', give them as NAME=DIR", name)));
+		}
+		if let Some((size, levels)) = sound_window(&context) {
+			let config = context.get_config();
+			if config.get_window_size().map_or(false, |config_size| config_size != size) || config.get_window_levels().map_or(false, |config_levels| config_levels != levels) {
+				return Err(SriError::config(format!("Shard '{}' holds sounds indexed with windows of {} samples and {} levels, not the ones of the config", name, size, levels)));
+			}
+			if let Some(&(ref other_name, _)) = self.shards.iter().find(|&&(_, ref other)| sound_window(other).map_or(false, |window| window != (size, levels))) {
+				return Err(SriError::config(format!("Shards '{}' and '{}' hold sounds indexed with different windows", other_name, name)));
+			}
+		}
+		self.shards.push((name, context));
+		Ok(())
+	}
+
+	pub fn get_shards(&self) -> &[(String, ContextObject)] { &self.shards }
+
+	/// Runs a single query line against every shard and returns their documents as one list, best first
+	///
+	/// The query descriptor is built once, with the config of the first shard.
+	pub fn query(&mut self, query: &str) -> Result<Vec<SearchResult>, SriError> { self.query_with_limit(query, usize::max_value()) }
+
+	/// Runs a single query line against every shard and returns the `result_limit` best documents of all of them
+	///
+	/// The results are described with the path and the metadata their document has in its shard.
+	pub fn query_with_limit(&mut self, query: &str, result_limit: usize) -> Result<Vec<SearchResult>, SriError> {
+		let (query_id, descriptor) = match self.shards.first_mut() {
+			Some(&mut (_, ref mut context)) => try!(context.query_descriptor(query)),
+			None => return Err(SriError::config("No shard to query")),
+		};
+		let mut results = Vec::new();
+		for (shard, &(_, ref context)) in self.shards.iter().enumerate() {
+			results.extend(context.search(&query_id, &descriptor).into_iter().map(|result| (shard, result)));
+		}
+		results.sort_by(|a, b| b.1.get_score().partial_cmp(&a.1.get_score()).unwrap_or(Ordering::Equal));
+		results.truncate(result_limit);
+
+		let mut federated_results = Vec::with_capacity(results.len());
+		for (shard, mut result) in results {
+			let (ref name, ref context) = self.shards[shard];
+			context.describe_result(&mut result);
+			federated_results.push(result.in_shard(name));
+		}
+		Ok(rank_results(federated_results, result_limit))
+	}
+
+	/// Runs every query of the queries list file against every shard and writes their `result_limit` best results to `result_filename`
+	pub fn start_querying(&mut self, queries_list_filename: &str, result_filename: &str, result_limit: usize, result_format: ResultFormat) -> Result<(), SriError> {
+		run_queries(queries_list_filename, result_filename, result_limit, result_format, |query, limit| self.query_with_limit(query, limit))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+	use std::fs::File;
+	use std::io::Write;
+
+	use super::*;
+	use includes::MediaDescriptor;
+	use includes::sound_types::SoundDescriptor;
+	use includes::util::test_directory;
+
+	fn config(index_path: &str, window: Option<(usize, usize)>) -> Config {
+		let mut params = HashMap::new();
+		params.insert(String::from("index"), String::from(index_path));
+		params.insert(String::from("max_snapshots"), String::from("0"));
+		if let Some((size, levels)) = window {
+			params.insert(String::from("window_size"), size.to_string());
+			params.insert(String::from("window_levels"), levels.to_string());
+		}
+		Config::check(&params).into_result().unwrap()
+	}
+
+	/// Indexes a text file for each of `texts` in the index `directory/name`, returning the path of the index
+	fn shard(directory: &str, name: &str, texts: &[&str]) -> String {
+		let index_path = format!("{}/{}", directory, name);
+		let mut context = ContextObject::new(config(&index_path, None));
+		for (number, text) in texts.iter().enumerate() {
+			let filename = format!("{}/{}-doc{}.txt", directory, name, number);
+			File::create(&filename).unwrap().write_all(text.as_bytes()).unwrap();
+			context.index_document(&filename).unwrap();
+		}
+		context.save_index().unwrap();
+		index_path
+	}
+
+	/// Context holding a single sound indexed with windows of `size` samples and `levels` levels
+	fn sound_context(config_window: Option<(usize, usize)>, size: usize, levels: usize) -> ContextObject {
+		let mut context = ContextObject::new(config("unsaved", config_window));
+		let sound = SoundDescriptor::from_histogram(String::from("snd0"), size, levels, vec![vec![0; 2 * levels]]);
+		context.add_document(String::from("sound.wav"), MediaDescriptor::SoundDescriptor(sound));
+		context
+	}
+
+	#[test]
+	fn results_of_every_shard_are_ranked_together() {
+		let directory = test_directory("federation-query");
+		let first = shard(&directory, "first", &["maison jardin\n", "voiture route\n"]);
+		let second = shard(&directory, "second", &["maison maison maison\n", "route route\n"]);
+		let query_filename = format!("{}/query.txt", directory);
+		File::create(&query_filename).unwrap().write_all(b"maison\n").unwrap();
+
+		let mut federation = Federation::open(&config(&directory, None), &[format!("one={}", first), second]).unwrap();
+		let names: Vec<&str> = federation.get_shards().iter().map(|shard| shard.0.as_str()).collect();
+		assert_eq!(names, vec!["one", "second"]);
+
+		let results = federation.query(&format!("q:{}", query_filename)).unwrap();
+		let mut ids: Vec<&str> = results.iter().map(SearchResult::get_document_id).collect();
+		assert_eq!(&ids[..2], &["second:txt0", "one:txt0"][..]);
+		ids.sort();
+		assert_eq!(ids, vec!["one:txt0", "one:txt1", "second:txt0", "second:txt1"]);
+		assert_eq!(results[0].get_path(), Some(format!("{}/second-doc0.txt", directory).as_str()));
+		assert_eq!(federation.query_with_limit(&format!("q:{}", query_filename), 1).unwrap().len(), 1);
+	}
+
+	#[test]
+	fn shards_of_other_sound_windows_are_refused() {
+		let mut federation = Federation::new();
+		federation.add_shard(String::from("first"), sound_context(Some((4, 1)), 4, 1)).unwrap();
+		assert!(federation.add_shard(String::from("first"), sound_context(Some((4, 1)), 4, 1)).is_err());
+		assert!(federation.add_shard(String::from("bad:name"), sound_context(Some((4, 1)), 4, 1)).is_err());
+		assert!(federation.add_shard(String::from("other_size"), sound_context(None, 8, 1)).is_err());
+		assert!(federation.add_shard(String::from("other_config"), sound_context(Some((8, 2)), 4, 1)).is_err());
+		federation.add_shard(String::from("same"), sound_context(None, 4, 1)).unwrap();
+		assert_eq!(federation.get_shards().len(), 2);
+	}
+}
@@ -1,35 +1,39 @@
 use includes::image_types::*;
 use includes::image_types;
 use includes::context_types::ContextObject;
+use includes::error::SriError;
 
 impl ImageDescriptor {
-	pub fn from_criteria(context: &mut ContextObject, criteria_as_str: &str) -> Result<ImageDescriptor, String> {
+	pub fn from_criteria(context: &mut ContextObject, criteria_as_str: &str) -> Result<ImageDescriptor, SriError> {
 
 		let mut histogram: [i32; IMAGE_QUANT_LVL] = [0; IMAGE_QUANT_LVL];
 
 
 		let mut color_components = criteria_as_str.split_whitespace();
 
-		let r_int = match color_components.next().unwrap().parse::<u32>() {
-			Ok(i) => i,
-			Err(e) => return Err(String::from(format!("Corrupted image file ({})", e))),
+		let r_int = match color_components.next().map(|value| value.parse::<u32>()) {
+			Some(Ok(i)) => i,
+			Some(Err(e)) => return Err(SriError::parse(format!("Invalid color component in criteria ({})", e))),
+			None => return Err(SriError::parse(format!("Missing color component in criteria ({})", criteria_as_str))),
 		};
 
-		let g_int = match color_components.next().unwrap().parse::<u32>() {
-			Ok(i) => i,
-			Err(e) => return Err(String::from(format!("Corrupted image file ({})", e))),
+		let g_int = match color_components.next().map(|value| value.parse::<u32>()) {
+			Some(Ok(i)) => i,
+			Some(Err(e)) => return Err(SriError::parse(format!("Invalid color component in criteria ({})", e))),
+			None => return Err(SriError::parse(format!("Missing color component in criteria ({})", criteria_as_str))),
 		};
 
-		let b_int = match color_components.next().unwrap().parse::<u32>() {
-			Ok(i) => i,
-			Err(e) => return Err(String::from(format!("Corrupted image file ({})", e))),
+		let b_int = match color_components.next().map(|value| value.parse::<u32>()) {
+			Some(Ok(i)) => i,
+			Some(Err(e)) => return Err(SriError::parse(format!("Invalid color component in criteria ({})", e))),
+			None => return Err(SriError::parse(format!("Missing color component in criteria ({})", criteria_as_str))),
 		};
 
 		match image_types::quantification_image(r_int, g_int, b_int) {
 			Ok(quantification) => {
 				histogram[quantification as usize] = 1;
 			},
-			Err(e) => panic!(e),
+			Err(e) => return Err(SriError::parse(e)),
 		}
 
 
@@ -1,235 +1,211 @@
 pub mod image_querying;
 pub mod sound_querying;
 pub mod text_querying;
+pub mod federation;
 
 use std::io::BufReader;
 use std::io::BufRead;
 use std::fs::File;
 use std::io::Write;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use rustc_serialize::json::Json;
 
 use includes::image_types::ImageDescriptor;
-use includes::sound_types::SoundDescriptor;
 use includes::text_types::TextDescriptor;
-use includes::Descriptor;
+use includes::descriptor::Descriptor;
+use includes::MediaDescriptor;
+use includes::registry;
+use includes::metadata::Metadata;
+use includes::error::SriError;
+use includes::util::warn;
 
 use includes::context_types::ContextObject;
 
-impl ContextObject {
-	pub fn start_querying(&mut self, queries_list_filename: &str, result_filename: &str) {
+/// A document of the index matched by a query
+pub struct SearchResult {
+	query_id: String,
+	document_id: String,
+	rank: usize,
+	score: f64,
+	path: Option<String>,
+	metadata: Metadata,
+}
+
+/// Format of a results file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResultFormat {
+	/// `query_id 0 document_id rank score run_name` lines read by trec_eval
+	Trec,
+	/// A JSON object per result, with the path and the metadata of the document
+	JsonLines,
+}
+
+impl SearchResult {
+	pub fn new(query_id: String, document_id: String, rank: usize, score: f64) -> SearchResult { SearchResult { query_id: query_id, document_id: document_id, rank: rank, score: score, path: None, metadata: Metadata::new() } }
+
+	pub fn get_query_id(&self) -> &str { &self.query_id }
+
+	pub fn get_document_id(&self) -> &str { &self.document_id }
+
+	/// Position of the document in the results of its query, starting at 1
+	pub fn get_rank(&self) -> usize { self.rank }
+
+	pub fn get_score(&self) -> f64 { self.score }
+
+	/// File of the document, `None` until the result is described
+	pub fn get_path(&self) -> Option<&str> { self.path.as_ref().map(String::as_str) }
 
+	/// Metadata of the document with the size and the modification time of its file, empty until the result is described
+	pub fn get_metadata(&self) -> &Metadata { &self.metadata }
 
-		let queries_reader = match File::open(&queries_list_filename) {
-			Ok(query_file) => BufReader::new(query_file),
-			Err(_) => panic!("Error, could not open query file '{}'", queries_list_filename),
+	/// Same result with the id of its document prefixed by the name of the shard it comes from
+	fn in_shard(mut self, shard: &str) -> SearchResult {
+		self.document_id = format!("{}{}{}", shard, federation::SHARD_SEPARATOR, self.document_id);
+		self
+	}
+
+	fn to_json(&self) -> Json {
+		let mut object = BTreeMap::new();
+		object.insert(String::from("query"), Json::String(self.query_id.clone()));
+		object.insert(String::from("rank"), Json::U64(self.rank as u64));
+		object.insert(String::from("score"), Json::F64(self.score));
+		object.insert(String::from("id"), Json::String(self.document_id.clone()));
+		if let Some(ref path) = self.path {
+			object.insert(String::from("path"), Json::String(path.clone()));
+		}
+		let metadata = self.metadata.get_fields().iter().map(|(key, value)| (key.clone(), Json::String(value.clone()))).collect();
+		object.insert(String::from("metadata"), Json::Object(metadata));
+		Json::Object(object)
+	}
+}
+
+/// Sorts the results of a single query by decreasing score, keeps the `result_limit` best ones and numbers them
+pub fn rank_results(mut results: Vec<SearchResult>, result_limit: usize) -> Vec<SearchResult> {
+	results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+	results.truncate(result_limit);
+	for (index, result) in results.iter_mut().enumerate() {
+		result.rank = index + 1;
+	}
+	results
+}
+
+/// Writes results in the trec_eval format: `query_id 0 document_id rank score run_name`, or as JSON Lines
+pub fn write_results(result_writer: &mut Write, results: &[SearchResult], result_format: ResultFormat) -> Result<(), SriError> {
+	for result in results {
+		try!(match result_format {
+			ResultFormat::Trec => writeln!(result_writer, "{} 0 {} {} {} sri_rs", result.get_query_id(), result.get_document_id(), result.get_rank(), result.get_score()),
+			ResultFormat::JsonLines => writeln!(result_writer, "{}", result.to_json()),
+		});
+	}
+	Ok(())
+}
+
+fn compare_to_base<D: Descriptor>(query_id: &str, descriptor: &D, base: &[D]) -> Vec<SearchResult> { base.iter().map(|descriptor_from_index| SearchResult::new(String::from(query_id), String::from(descriptor_from_index.get_id()), 0, descriptor.compare_to(descriptor_from_index))).collect() }
+
+impl ContextObject {
+	/// Builds the descriptor of a query line (`id:example_file[:example]` or `id:criteria:criterion:media_type`)
+	pub fn query_descriptor(&mut self, query: &str) -> Result<(String, MediaDescriptor), SriError> {
+		let mut fields = query.split(":");
+		let query_id = fields.next().unwrap();
+		let query_text = match fields.next() {
+			Some(query_text) => query_text,
+			None => return Err(SriError::parse(format!("Missing query text for query {}", query_id))),
+		};
+		let query_type = match fields.next() {
+			Some(query_type) => query_type.to_lowercase(),
+			None => String::from("example"),
 		};
 
-		let mut result_writer = match File::create(&result_filename) {
-			Ok(result_file) => result_file,
-			Err(_) => panic!("Error, could not create result_file file '{}'", result_filename),
+		let descriptor = match query_type.as_ref() {
+			"example" => {
+				match try!(registry::detect_media_type(query_text)) {
+					Some(media_type) => try!(media_type.extract(self.get_config(), query_text)).0,
+					None => return Err(SriError::parse(format!("Unsupported document type for query {}", query_id))),
+				}
+			},
+			"criterion" => {
+				match fields.next() {
+					Some("img") => try!(ImageDescriptor::from_criteria(self, query_text)).into_media_descriptor(),
+					Some("txt") => try!(TextDescriptor::from_criteria(self, query_text)).into_media_descriptor(),
+					Some(media_type) => return Err(SriError::parse(format!("Invalid media type : {} for query {}", media_type, query_id))),
+					None => return Err(SriError::parse(format!("Missing media type for query {}", query_id))),
+				}
+			},
+			_ => return Err(SriError::parse(format!("Invalid query type : {} for query {}", query_type, query_id))),
 		};
+		Ok((String::from(query_id), descriptor))
+	}
+
+	/// Compares a query descriptor to every descriptor of the same media type in the index
+	///
+	/// The results are not sorted, see `rank_results`.
+	pub fn search(&self, query_id: &str, descriptor: &MediaDescriptor) -> Vec<SearchResult> {
+		match *descriptor {
+			MediaDescriptor::ImageDescriptor(ref descriptor) => compare_to_base(query_id, descriptor, self.get_images_base()),
+			MediaDescriptor::SoundDescriptor(ref descriptor) => compare_to_base(query_id, descriptor, self.get_sounds_base()),
+			MediaDescriptor::TextDescriptor(ref descriptor) => compare_to_base(query_id, descriptor, self.get_texts_base()),
+		}
+	}
+
+	/// Gives the results of this index the path and the metadata of their document
+	pub fn describe_result(&self, result: &mut SearchResult) {
+		result.path = self.get_association(&result.document_id).map(|filename| self.document_path(filename));
+		result.metadata = self.describe_document(&result.document_id);
+	}
+
+	/// Runs a single query line and returns every document of the matching media type, best first
+	pub fn query(&mut self, query: &str) -> Result<Vec<SearchResult>, SriError> { self.query_with_limit(query, usize::max_value()) }
+
+	/// Runs a single query line and returns its `result_limit` best documents, described with their path and metadata
+	pub fn query_with_limit(&mut self, query: &str, result_limit: usize) -> Result<Vec<SearchResult>, SriError> {
+		let (query_id, descriptor) = try!(self.query_descriptor(query));
+		let mut results = rank_results(self.search(&query_id, &descriptor), result_limit);
+		for result in &mut results {
+			self.describe_result(result);
+		}
+		Ok(results)
+	}
+
+	/// Runs every query of the queries list file and writes their `result_limit` best results to `result_filename`
+	///
+	/// Queries that fail are reported and skipped.
+	pub fn start_querying(&mut self, queries_list_filename: &str, result_filename: &str, result_limit: usize, result_format: ResultFormat) -> Result<(), SriError> {
+		run_queries(queries_list_filename, result_filename, result_limit, result_format, |query, limit| self.query_with_limit(query, limit))
+	}
+}
+
+/// Runs every query of the queries list file through `run_query`, given the result limit, and writes their results to `result_filename`
+///
+/// Queries that fail are reported and skipped.
+pub fn run_queries<F>(queries_list_filename: &str, result_filename: &str, result_limit: usize, result_format: ResultFormat, mut run_query: F) -> Result<(), SriError>
+	where F: FnMut(&str, usize) -> Result<Vec<SearchResult>, SriError>
+{
+	let queries_reader = match File::open(&queries_list_filename) {
+		Ok(query_file) => BufReader::new(query_file),
+		Err(e) => return Err(SriError::io(queries_list_filename, e)),
+	};
+
+	let mut result_writer = match File::create(&result_filename) {
+		Ok(result_file) => result_file,
+		Err(e) => return Err(SriError::io(result_filename, e)),
+	};
 
-		for line in queries_reader.lines() {
-			match line {
-				Ok(query) => {
-					match query.trim() {
-						"" => (),
-						_ => {
-
-							let mut fields = query.split(":");
-							let query_id = fields.next().unwrap();
-							let query_text = fields.next().unwrap();
-							let query_type = match fields.next() {
-								Some(query_type) => query_type.to_lowercase(),
-								None => String::from("example"),
-							};
-
-							let descriptor = match query_type.as_ref() {
-								"example" => {
-									match query_text.rsplit('.').next().unwrap().to_string().to_lowercase().as_ref() {
-										"txt" => {
-											match File::open(query_text.clone()) {
-												Ok(image_file) => {
-													match ImageDescriptor::from_plain_text_file(self, image_file) {
-														Ok(descriptor) => Some(Descriptor::ImageDescriptor(descriptor)),
-														Err(e) => {
-															println!("{:?}", e);
-															None
-														},
-													}
-												},
-												Err(e) => {
-													println!("{} {}", e, query);
-													None
-												},
-											}
-										},
-										"png" | "jpg" | "jpeg" | "gif" | "bmp" | "ico" | "tiff" | "webp" => {
-											match ImageDescriptor::from_image_file(self, query_text.as_ref()) {
-												Ok(descriptor) => Some(Descriptor::ImageDescriptor(descriptor)),
-												Err(e) => {
-													println!("{:?}", e);
-													None
-												},
-											}
-										},
-										"bin" => {
-											match File::open(query_text.clone()) {
-												Ok(image_file) => {
-													match SoundDescriptor::from_raw_file(self, image_file) {
-														Ok(descriptor) => Some(Descriptor::SoundDescriptor(descriptor)),
-														Err(e) => {
-															println!("{:?}", e);
-															None
-														},
-													}
-												},
-												Err(e) => {
-													println!("{} {}", e, query);
-													None
-												},
-											}
-										},
-										"wav" => {
-											match File::open(query_text.clone()) {
-												Ok(image_file) => {
-													match SoundDescriptor::from_wav_file(self, image_file) {
-														Ok(descriptor) => Some(Descriptor::SoundDescriptor(descriptor)),
-														Err(e) => {
-															println!("{:?}", e);
-															None
-														},
-													}
-												},
-												Err(e) => {
-													println!("{} {}", e, query);
-													None
-												},
-											}
-										},
-										"mp3" | "MPEG3" => {
-											match File::open(query_text.clone()) {
-												Ok(image_file) => {
-													match SoundDescriptor::from_raw_file(self, image_file) {
-														Ok(descriptor) => Some(Descriptor::SoundDescriptor(descriptor)),
-														Err(e) => {
-															println!("{:?}", e);
-															None
-														},
-													}
-												},
-												Err(e) => {
-													println!("{} {}", e, query);
-													None
-												},
-											}
-										},
-										"xml" => {
-											match File::open(query_text.clone()) {
-												Ok(image_file) => {
-													match TextDescriptor::from_text_file(self, image_file) {
-														Ok(descriptor) => Some(Descriptor::TextDescriptor(descriptor)),
-														Err(e) => {
-															println!("{:?}", e);
-															None
-														},
-													}
-												},
-												Err(e) => {
-													println!("{} {}", e, query);
-													None
-												},
-											}
-										},
-										"srt" => {
-											match File::open(query_text.clone()) {
-												Ok(image_file) => {
-													match TextDescriptor::from_subrip_file(self, image_file) {
-														Ok(descriptor) => Some(Descriptor::TextDescriptor(descriptor)),
-														Err(e) => {
-															println!("{:?}", e);
-															None
-														},
-													}
-												},
-												Err(e) => {
-													println!("{} {}", e, query);
-													None
-												},
-											}
-										},
-										_ => {
-											println!("Error: invalid file extension for query {}", query_id);
-											None
-										},
-
-									}
-								},
-								"criterion" => {
-									let media_type = fields.next().unwrap();
-									match media_type {
-										"img" => {
-											match ImageDescriptor::from_criteria(self, query_text) {
-												Ok(descriptor) => Some(Descriptor::ImageDescriptor(descriptor)),
-												Err(e) => {
-													println!("{:?}", e);
-													None
-												},
-											}
-										},
-										"txt" => {
-											match TextDescriptor::from_criteria(self, query_text) {
-												Ok(descriptor) => Some(Descriptor::TextDescriptor(descriptor)),
-												Err(e) => {
-													println!("{:?}", e);
-													None
-												},
-											}
-										},
-										_ => {
-											println!("Error, invalid media type : {} for query {}", media_type, query_id);
-											None
-										},
-									}
-								},
-								_ => {
-									println!("Error, invalid query type : {} for query {}", query_type, query_id);
-									None
-								},
-							};
-
-
-							match descriptor {
-								Some(descriptor) => {
-									match descriptor {
-										Descriptor::ImageDescriptor(descriptor) => {
-											println!("Searching a picture similar to {}", query_text);
-											for descriptor_from_index in self.get_images_base() {
-												writeln!(result_writer, "{} 0 {} 0 {} sri_rs", query_id, descriptor_from_index.get_id(), descriptor.compare_to(descriptor_from_index)).unwrap();
-
-											}
-										},
-										Descriptor::SoundDescriptor(descriptor) => {
-											println!("Searching a sound similar to {}", query_text);
-											for descriptor_from_index in self.get_sounds_base() {
-												writeln!(result_writer, "{} 0 {} 0 {} sri_rs", query_id, descriptor_from_index.get_id(), descriptor.compare_to(descriptor_from_index)).unwrap();
-											}
-										},
-										Descriptor::TextDescriptor(descriptor) => {
-											println!("Searching a text similar to {}", query_text);
-											for descriptor_from_index in self.get_texts_base() {
-												writeln!(result_writer, "{} 0 {} 0 {} sri_rs", query_id, descriptor_from_index.get_id(), descriptor.compare_to(descriptor_from_index)).unwrap();
-
-											}
-										},
-									}
-								},
-								None => println!("Error: could not create descriptor for query {}", query_id),
-							}
-						},
-					}
-				},
-				Err(e) => panic!(e),
-			}
+	for (line_number, line) in queries_reader.lines().enumerate() {
+		let query = try!(line.map_err(|e| SriError::io(queries_list_filename, e)));
+		match query.trim() {
+			"" => (),
+			_ => {
+				match run_query(&query, result_limit) {
+					Ok(results) => {
+						println!("Searching documents similar to {}", query);
+						try!(write_results(&mut result_writer, &results, result_format).map_err(|e| e.in_file(result_filename)));
+					},
+					Err(e) => warn(&format!("{}, the query is left out", e.in_file(queries_list_filename).at_line(line_number + 1))),
+				}
+			},
 		}
 	}
+	Ok(())
 }
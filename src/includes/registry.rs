@@ -0,0 +1,348 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str;
+
+use includes::image_types::ImageDescriptor;
+use includes::sound_types::SoundDescriptor;
+use includes::text_types::TextDescriptor;
+use includes::descriptor::Descriptor;
+use includes::MediaDescriptor;
+use includes::MediaKind;
+use includes::error::SriError;
+use includes::metadata;
+use includes::metadata::Metadata;
+use includes::config::Config;
+
+/// Extracts the descriptor of a document, given its filename, filling the metadata it finds on the way
+///
+/// Only the config is needed, so documents can be extracted on several threads at once.
+pub type Extractor = fn(&Config, &str, &str, &mut Metadata) -> Result<MediaDescriptor, SriError>;
+
+/// How surely the first bytes of a document tell its format
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Signature {
+	Absent,
+	/// Found in other files too, such as a short magic number or a text pattern, it only decides when the extension doesn't
+	Weak,
+	/// Magic number long enough to override the extension
+	Strong,
+}
+
+/// Finds the signature of a format in the first bytes of a file, at most `SNIFFED_BYTES` of them, the flag telling whether they are the whole file
+pub type Sniffer = fn(&[u8], bool) -> Signature;
+
+/// Number of bytes read at the start of a document to detect its format
+pub const SNIFFED_BYTES: usize = 4096;
+
+/// A supported document format, with the signature and the extensions it is recognized by and the extractor computing its descriptor
+pub struct MediaType {
+	name: &'static str,
+	kind: MediaKind,
+	sniffer: Sniffer,
+	extensions: &'static [&'static str],
+	extractor: Extractor,
+}
+
+impl MediaType {
+	pub fn get_name(&self) -> &'static str { self.name }
+
+	pub fn get_kind(&self) -> MediaKind { self.kind }
+
+	pub fn get_extensions(&self) -> &'static [&'static str] { self.extensions }
+
+	/// Signature of this format in the first bytes of a document, `complete` telling whether they are the whole document
+	pub fn sniff(&self, header: &[u8], complete: bool) -> Signature { (self.sniffer)(header, complete) }
+
+	/// Extracts the descriptor and the metadata of a document, errors being located in that document
+	///
+	/// The descriptor has no id yet, the index gives it one when the document is added.
+	pub fn extract(&self, config: &Config, filename: &str) -> Result<(MediaDescriptor, Metadata), SriError> {
+		let mut metadata = Metadata::new();
+		metadata.set(metadata::MEDIA_TYPE, self.name);
+		let descriptor = try!((self.extractor)(config, self.name, filename, &mut metadata).map_err(|e| e.in_file(filename)));
+		Ok((descriptor, metadata))
+	}
+}
+
+pub const IMAGE: &'static str = "image";
+pub const WAV_SOUND: &'static str = "wav sound";
+pub const MP3_SOUND: &'static str = "mp3 sound";
+pub const XML_TEXT: &'static str = "xml text";
+pub const SUBTITLE: &'static str = "subtitle";
+pub const PLAIN_TEXT_IMAGE: &'static str = "plain text image";
+pub const RAW_SOUND: &'static str = "raw sound";
+/// Name of the format of the texts without markup, any readable text having its signature
+pub const PLAIN_TEXT: &'static str = "plain text";
+
+/// Every format the indexer and the queries know about
+///
+/// Among the formats sharing an extension, the first one whose signature is found is chosen, the last one otherwise.
+/// Adding a new format only requires a new entry here.
+pub static MEDIA_TYPES: &'static [MediaType] = &[MediaType { name: IMAGE, kind: MediaKind::Image, sniffer: sniff_image, extensions: &["png", "jpg", "jpeg", "gif", "bmp", "ico", "tiff", "webp"], extractor: extract::<ImageDescriptor> },
+                                                 MediaType { name: WAV_SOUND, kind: MediaKind::Sound, sniffer: sniff_wav, extensions: &["wav"], extractor: extract::<SoundDescriptor> },
+                                                 MediaType { name: MP3_SOUND, kind: MediaKind::Sound, sniffer: sniff_mp3, extensions: &["mp3", "mpeg3"], extractor: extract::<SoundDescriptor> },
+                                                 MediaType { name: XML_TEXT, kind: MediaKind::Text, sniffer: sniff_xml, extensions: &["xml"], extractor: extract::<TextDescriptor> },
+                                                 MediaType { name: SUBTITLE, kind: MediaKind::Text, sniffer: sniff_subrip, extensions: &["srt"], extractor: extract::<TextDescriptor> },
+                                                 MediaType { name: PLAIN_TEXT_IMAGE, kind: MediaKind::Image, sniffer: sniff_plain_text_image, extensions: &["txt"], extractor: extract::<ImageDescriptor> },
+                                                 MediaType { name: RAW_SOUND, kind: MediaKind::Sound, sniffer: sniff_nothing, extensions: &["bin"], extractor: extract::<SoundDescriptor> },
+                                                 MediaType { name: PLAIN_TEXT, kind: MediaKind::Text, sniffer: sniff_plain_text, extensions: &["txt"], extractor: extract::<TextDescriptor> }];
+
+/// Extension of a filename in lower case, `None` when its name has none
+fn extension(filename: &str) -> Option<String> { Path::new(filename).extension().map(|extension| extension.to_string_lossy().to_lowercase()) }
+
+/// Finds the media type of a document from its extension, case insensitively, the last format of a shared extension being chosen
+pub fn find_media_type(filename: &str) -> Option<&'static MediaType> {
+	let extension = match extension(filename) {
+		Some(extension) => extension,
+		None => return None,
+	};
+	MEDIA_TYPES.iter().filter(|media_type| media_type.extensions.contains(&extension.as_ref())).last()
+}
+
+/// Finds the media type of a document from its first bytes alone, a strong signature first and a weak one otherwise
+///
+/// Readable text is not enough for a plain text, whose files are only told apart by their extension.
+pub fn sniff_media_type(header: &[u8], complete: bool) -> Option<&'static MediaType> {
+	MEDIA_TYPES.iter()
+	           .find(|media_type| media_type.sniff(header, complete) == Signature::Strong)
+	           .or_else(|| MEDIA_TYPES.iter().find(|media_type| media_type.name != PLAIN_TEXT && media_type.sniff(header, complete) == Signature::Weak))
+}
+
+/// Finds the media type of a document from its content and its extension
+///
+/// A strong signature decides first. A known extension comes next, a weak signature only choosing among the formats
+/// that share it, so a raw sound starting like an icon stays a sound. A file with an unknown extension then goes by its weak
+/// signature, and only a file without extension is taken as plain text for being readable, leaving out the json, csv or
+/// markdown files of a crawl. An empty file only has its extension.
+pub fn detect_media_type(filename: &str) -> Result<Option<&'static MediaType>, SriError> {
+	let file = try!(open_document(filename));
+	let mut header = Vec::with_capacity(SNIFFED_BYTES);
+	if let Err(e) = file.take(SNIFFED_BYTES as u64).read_to_end(&mut header) {
+		return Err(SriError::io(filename, e));
+	}
+	// Fewer bytes than asked for are only read at the end of the file
+	let complete = header.len() < SNIFFED_BYTES;
+
+	if let Some(media_type) = MEDIA_TYPES.iter().find(|media_type| media_type.sniff(&header, complete) == Signature::Strong) {
+		return Ok(Some(media_type));
+	}
+	let extension = extension(filename);
+	if let Some(ref extension) = extension {
+		let candidates: Vec<&'static MediaType> = MEDIA_TYPES.iter().filter(|media_type| media_type.extensions.contains(&extension.as_ref())).collect();
+		if let Some(&media_type) = candidates.iter().find(|media_type| media_type.sniff(&header, complete) != Signature::Absent).or(candidates.last()) {
+			return Ok(Some(media_type));
+		}
+	}
+	if header.is_empty() {
+		return Ok(None);
+	}
+	if let Some(media_type) = sniff_media_type(&header, complete) {
+		return Ok(Some(media_type));
+	}
+	Ok(match extension.is_none() && sniff_plain_text(&header, complete) != Signature::Absent {
+		true => MEDIA_TYPES.iter().find(|media_type| media_type.name == PLAIN_TEXT),
+		false => None,
+	})
+}
+
+fn sniff_nothing(_header: &[u8], _complete: bool) -> Signature { Signature::Absent }
+
+/// PNG, JPEG, GIF and WEBP magic numbers are strong, the short BMP, ICO and TIFF ones are weak
+fn sniff_image(header: &[u8], _complete: bool) -> Signature {
+	if header.starts_with(b"\x89PNG\r\n\x1a\n") || header.starts_with(b"\xff\xd8\xff") || header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") ||
+	   (header.starts_with(b"RIFF") && header.len() >= 12 && &header[8..12] == b"WEBP") {
+		return Signature::Strong;
+	}
+	match header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") || header.starts_with(b"\0\0\x01\0") || (header.starts_with(b"BM") && header.len() >= 14) {
+		true => Signature::Weak,
+		false => Signature::Absent,
+	}
+}
+
+fn sniff_wav(header: &[u8], _complete: bool) -> Signature {
+	match header.starts_with(b"RIFF") && header.len() >= 12 && &header[8..12] == b"WAVE" {
+		true => Signature::Strong,
+		false => Signature::Absent,
+	}
+}
+
+/// ID3 tag, or two MPEG layer III frames in a row, a single frame header being too likely in other binary data
+fn sniff_mp3(header: &[u8], complete: bool) -> Signature {
+	let found = header.starts_with(b"ID3") ||
+	            match mpeg_frame_length(header) {
+		Some(length) if length == header.len() => complete,
+		Some(length) if length < header.len() => mpeg_frame_length(&header[length..]).is_some(),
+		_ => false,
+	};
+	match found {
+		true => Signature::Weak,
+		false => Signature::Absent,
+	}
+}
+
+/// Length in bytes of the MPEG layer III frame starting the data, if its header is valid
+fn mpeg_frame_length(data: &[u8]) -> Option<usize> {
+	static MPEG1_BITRATES: [usize; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+	static MPEG2_BITRATES: [usize; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+	static SAMPLE_RATES: [usize; 3] = [44100, 48000, 32000];
+
+	if data.len() < 4 || data[0] != 0xff || data[1] & 0xe0 != 0xe0 || (data[1] >> 1) & 0x03 != 0x01 {
+		return None;
+	}
+	let version = (data[1] >> 3) & 0x03;
+	let (bitrate_index, sample_rate_index) = ((data[2] >> 4) as usize, ((data[2] >> 2) & 0x03) as usize);
+	if version == 0x01 || bitrate_index == 0 || bitrate_index == 0x0f || sample_rate_index == 0x03 {
+		return None;
+	}
+	let padding = ((data[2] >> 1) & 0x01) as usize;
+	Some(match version {
+		0x03 => 144000 * MPEG1_BITRATES[bitrate_index] / SAMPLE_RATES[sample_rate_index] + padding,
+		0x02 => 72000 * MPEG2_BITRATES[bitrate_index] / (SAMPLE_RATES[sample_rate_index] / 2) + padding,
+		_ => 72000 * MPEG2_BITRATES[bitrate_index] / (SAMPLE_RATES[sample_rate_index] / 4) + padding,
+	})
+}
+
+/// Text without its byte order mark and leading white spaces, when the header is utf-8
+///
+/// Only a header cut by the sniff limit may end in the middle of a character.
+fn header_text(header: &[u8], complete: bool) -> Option<&str> {
+	let header = match header.starts_with(b"\xef\xbb\xbf") {
+		true => &header[3..],
+		false => header,
+	};
+	let text = match str::from_utf8(header) {
+		Ok(text) => text,
+		Err(ref e) if !complete && e.error_len().is_none() => str::from_utf8(&header[..e.valid_up_to()]).unwrap(),
+		Err(_) => return None,
+	};
+	Some(text.trim_left())
+}
+
+fn weak_signature(found: bool) -> Signature {
+	match found {
+		true => Signature::Weak,
+		false => Signature::Absent,
+	}
+}
+
+fn sniff_xml(header: &[u8], complete: bool) -> Signature { weak_signature(header_text(header, complete).map_or(false, |text| text.starts_with("<?xml"))) }
+
+/// A sequence number line followed by a `00:00:01,000 --> 00:00:02,000` timing line
+fn sniff_subrip(header: &[u8], complete: bool) -> Signature {
+	let mut lines = match header_text(header, complete) {
+		Some(text) => text.lines(),
+		None => return Signature::Absent,
+	};
+	let number = lines.next().map_or(false, |line| !line.trim().is_empty() && line.trim().chars().all(|c| c.is_digit(10)));
+	let timing = lines.next().map_or(false, |line| {
+		let mut times = line.split("-->");
+		match (times.next(), times.next()) {
+			(Some(start), Some(end)) => is_subrip_time(start.trim()) && is_subrip_time(end.trim().split_whitespace().next().unwrap_or("")),
+			_ => false,
+		}
+	});
+	weak_signature(number && timing)
+}
+
+fn is_subrip_time(time: &str) -> bool {
+	let fields: Vec<&str> = time.split(|c| c == ':' || c == ',' || c == '.').collect();
+	fields.len() == 4 && fields.iter().all(|field| !field.is_empty() && field.chars().all(|c| c.is_digit(10)))
+}
+
+/// A `l h nbcomp` header line of three positive integers, the components being 1 or 3, followed by pixel values
+fn sniff_plain_text_image(header: &[u8], complete: bool) -> Signature {
+	let mut lines = match header_text(header, complete) {
+		Some(text) => text.lines(),
+		None => return Signature::Absent,
+	};
+	let fields: Vec<usize> = match lines.next() {
+		Some(line) => line.split_whitespace().map(|field| field.parse::<usize>().unwrap_or(0)).collect(),
+		None => return Signature::Absent,
+	};
+	if fields.len() != 3 || fields.iter().any(|&field| field == 0) || (fields[2] != 1 && fields[2] != 3) {
+		return Signature::Absent;
+	}
+	weak_signature(lines.next().map_or(false, |line| !line.trim().is_empty() && line.split_whitespace().all(|value| value.parse::<u32>().is_ok())))
+}
+
+/// Utf-8 text without control characters other than white spaces
+fn sniff_plain_text(header: &[u8], complete: bool) -> Signature { weak_signature(header_text(header, complete).map_or(false, |text| text.chars().all(|c| !c.is_control() || c.is_whitespace()))) }
+
+/// Opens a document to extract, its errors naming the file
+pub fn open_document(filename: &str) -> Result<File, SriError> {
+	match File::open(filename) {
+		Ok(file) => Ok(file),
+		Err(e) => Err(SriError::io(filename, e)),
+	}
+}
+
+fn extract<D: Descriptor>(config: &Config, format: &str, filename: &str, metadata: &mut Metadata) -> Result<MediaDescriptor, SriError> { D::extract(config, format, filename, metadata).map(Descriptor::into_media_descriptor) }
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::io::Write;
+
+	use super::*;
+	use includes::util::test_directory;
+
+	fn write_document(directory: &str, name: &str, content: &[u8]) -> String {
+		let filename = format!("{}/{}", directory, name);
+		File::create(&filename).unwrap().write_all(content).unwrap();
+		filename
+	}
+
+	fn detected_name(filename: &str) -> Option<&'static str> { detect_media_type(filename).unwrap().map(MediaType::get_name) }
+
+	#[test]
+	fn strong_signatures_are_sniffed() {
+		assert_eq!(sniff_media_type(b"RIFF\x24\0\0\0WAVEfmt ", false).map(MediaType::get_name), Some(WAV_SOUND));
+		assert_eq!(sniff_media_type(b"\x89PNG\r\n\x1a\n\0\0", false).map(MediaType::get_name), Some(IMAGE));
+		assert_eq!(sniff_media_type(b"<?xml version=\"1.0\"?><a/>", true).map(MediaType::get_name), Some(XML_TEXT));
+	}
+
+	#[test]
+	fn readable_text_is_not_sniffed_as_plain_text() {
+		assert!(sniff_media_type(b"just some words\n", true).is_none());
+		assert_eq!(sniff_plain_text(b"just some words\n", true), Signature::Weak);
+		assert_eq!(sniff_plain_text(b"\x00\x01binary", true), Signature::Absent);
+	}
+
+	#[test]
+	fn cut_characters_are_only_accepted_at_the_sniff_limit() {
+		let header = "caf\u{e9}".as_bytes();
+		let cut = &header[..header.len() - 1];
+		assert_eq!(header_text(cut, false), Some("caf"));
+		assert_eq!(header_text(cut, true), None);
+		assert_eq!(header_text(b"\xef\xbb\xbf  text", true), Some("text"));
+	}
+
+	#[test]
+	fn subrip_needs_a_number_and_a_timing_line() {
+		assert_eq!(sniff_subrip(b"1\n00:00:01,000 --> 00:00:02,500\nHello\n", true), Signature::Weak);
+		assert_eq!(sniff_subrip(b"1\nHello\n", true), Signature::Absent);
+	}
+
+	#[test]
+	fn extensions_are_matched_case_insensitively() {
+		assert_eq!(find_media_type("a/B.WAV").map(MediaType::get_name), Some(WAV_SOUND));
+		assert_eq!(find_media_type("notes.txt").map(MediaType::get_name), Some(PLAIN_TEXT));
+		assert!(find_media_type("archive.tar.gz").is_none());
+		assert!(find_media_type("README").is_none());
+	}
+
+	#[test]
+	fn media_types_are_detected_from_content_and_extension() {
+		let directory = test_directory("registry-detect");
+		assert_eq!(detected_name(&write_document(&directory, "bitmap.txt", b"BM is how this text starts")), Some(PLAIN_TEXT));
+		assert_eq!(detected_name(&write_document(&directory, "picture.txt", b"2 2 1\n0 255 255 0\n")), Some(PLAIN_TEXT_IMAGE));
+		assert_eq!(detected_name(&write_document(&directory, "samples.bin", b"\0\0\x01\0\x10\x20")), Some(RAW_SOUND));
+		assert_eq!(detected_name(&write_document(&directory, "sound", b"RIFF\x24\0\0\0WAVEfmt ")), Some(WAV_SOUND));
+		assert_eq!(detected_name(&write_document(&directory, "wrong.txt", b"RIFF\x24\0\0\0WAVEfmt ")), Some(WAV_SOUND));
+		assert_eq!(detected_name(&write_document(&directory, "README", b"Some readable words\n")), Some(PLAIN_TEXT));
+		assert_eq!(detected_name(&write_document(&directory, "data.json", b"{\"key\": 1}\n")), None);
+		assert_eq!(detected_name(&write_document(&directory, "empty", b"")), None);
+		assert_eq!(detected_name(&write_document(&directory, "empty.srt", b"")), Some(SUBTITLE));
+	}
+}
@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use rustc_serialize::json::Json;
+
+use includes::descriptor::Descriptor;
+use includes::error::SriError;
+use includes::fingerprint::Fingerprint;
+use includes::manifest;
+use includes::manifest::Manifest;
+use includes::util::warn;
+
+use includes::context_types::ContextObject;
+
+/// Directory of an index holding its snapshots, one index directory per kept generation
+pub static SNAPSHOTS_DIRECTORY: &'static str = "snapshots";
+
+static SNAPSHOT_PREFIX: &'static str = "generation-";
+static TEMPORARY_EXTENSION: &'static str = ".tmp";
+
+/// Set once the missing hard links were reported, so a process saving many times only reports them once
+static MISSING_LINKS_REPORTED: AtomicBool = AtomicBool::new(false);
+
+
+/// Generation of an index kept by a save, with the layout of the index itself
+pub struct Snapshot {
+	name: String,
+	manifest: Manifest,
+}
+
+impl Snapshot {
+	/// Name of the snapshot, `generation-` followed by its generation
+	pub fn get_name(&self) -> &str { &self.name }
+
+	pub fn get_manifest(&self) -> &Manifest { &self.manifest }
+}
+
+/// Name of the snapshot of a generation
+pub fn snapshot_name(generation: u64) -> String { format!("{}{:06}", SNAPSHOT_PREFIX, generation) }
+
+/// Directory of a snapshot of the index stored in `index_path`
+pub fn snapshot_path(index_path: &str, name: &str) -> String { format!("{}/{}/{}", index_path, SNAPSHOTS_DIRECTORY, name) }
+
+/// Name of the snapshot designated by its name or by its generation number
+pub fn find_snapshot(index_path: &str, name: &str) -> Result<String, SriError> {
+	let name = match name.parse::<u64>() {
+		Ok(generation) => snapshot_name(generation),
+		Err(_) => String::from(name),
+	};
+	match Path::new(&snapshot_path(index_path, &name)).is_dir() && name.starts_with(SNAPSHOT_PREFIX) {
+		true => Ok(name),
+		false => Err(SriError::config(format!("No snapshot named '{}' in {}", name, index_path))),
+	}
+}
+
+/// Snapshots of the index stored in `index_path`, oldest first
+pub fn list_snapshots(index_path: &str) -> Result<Vec<Snapshot>, SriError> {
+	let directory = format!("{}/{}", index_path, SNAPSHOTS_DIRECTORY);
+	let mut snapshots = Vec::new();
+	if !Path::new(&directory).is_dir() {
+		return Ok(snapshots);
+	}
+	for entry in try!(fs::read_dir(&directory).map_err(|e| SriError::io(&directory, e))) {
+		let name = String::from(try!(entry.map_err(|e| SriError::io(&directory, e))).file_name().to_string_lossy());
+		if !name.starts_with(SNAPSHOT_PREFIX) || name.ends_with(TEMPORARY_EXTENSION) {
+			continue;
+		}
+		let manifest_path = format!("{}/{}/{}", directory, name, manifest::MANIFEST_FILENAME);
+		snapshots.push(Snapshot { name: name, manifest: try!(Manifest::from_file(&manifest_path)) });
+	}
+	snapshots.sort_by_key(|snapshot| snapshot.manifest.get_generation());
+	Ok(snapshots)
+}
+
+/// Keeps the generation of `manifest` as a snapshot, then removes the oldest snapshots beyond `max_snapshots`
+///
+/// The files of a save are never modified once in place, so the snapshot hard links them and only costs directory entries.
+/// On a filesystem without hard links, the files are only copied when `copies` allows it, no snapshot being taken otherwise.
+/// It is written aside and renamed once complete, an interrupted snapshot being removed by the next one.
+pub fn take_snapshot(index_path: &str, manifest: &Manifest, max_snapshots: usize, copies: bool) -> Result<(), SriError> {
+	let name = snapshot_name(manifest.get_generation());
+	let path = snapshot_path(index_path, &name);
+	let temporary_path = format!("{}{}", path, TEMPORARY_EXTENSION);
+	try!(remove_temporary_snapshots(index_path));
+	if Path::new(&path).exists() {
+		try!(fs::remove_dir_all(&path).map_err(|e| SriError::io(&path, e)));
+	}
+
+	let mut filenames: Vec<&str> = manifest.get_files().iter().map(|entry| entry.get_filename()).collect();
+	filenames.push(manifest::MANIFEST_FILENAME);
+	for filename in filenames {
+		let source = format!("{}/{}", index_path, filename);
+		let destination = format!("{}/{}", temporary_path, filename);
+		if let Some(parent) = Path::new(&destination).parent() {
+			try!(fs::create_dir_all(parent).map_err(|e| SriError::io(&destination, e)));
+		}
+		if fs::hard_link(&source, &destination).is_err() {
+			let reported = MISSING_LINKS_REPORTED.swap(true, Ordering::SeqCst);
+			if !copies {
+				if !reported {
+					warn(&format!("{} has no hard links, no snapshot is taken unless snapshot_copies is set to copy the whole index for each one", index_path));
+				}
+				return fs::remove_dir_all(&temporary_path).map_err(|e| SriError::io(&temporary_path, e));
+			}
+			if !reported {
+				warn(&format!("{} has no hard links, each snapshot copies the whole index", index_path));
+			}
+			try!(fs::copy(&source, &destination).map_err(|e| SriError::io(&source, e)));
+		}
+	}
+	try!(fs::rename(&temporary_path, &path).map_err(|e| SriError::io(&temporary_path, e)));
+
+	let snapshots = try!(list_snapshots(index_path));
+	if snapshots.len() > max_snapshots {
+		for snapshot in &snapshots[..snapshots.len() - max_snapshots] {
+			let path = snapshot_path(index_path, &snapshot.name);
+			try!(fs::remove_dir_all(&path).map_err(|e| SriError::io(&path, e)));
+		}
+	}
+	Ok(())
+}
+
+fn remove_temporary_snapshots(index_path: &str) -> Result<(), SriError> {
+	let directory = format!("{}/{}", index_path, SNAPSHOTS_DIRECTORY);
+	if !Path::new(&directory).is_dir() {
+		return Ok(());
+	}
+	for entry in try!(fs::read_dir(&directory).map_err(|e| SriError::io(&directory, e))) {
+		let path = try!(entry.map_err(|e| SriError::io(&directory, e))).path();
+		if path.to_string_lossy().ends_with(TEMPORARY_EXTENSION) {
+			try!(fs::remove_dir_all(&path).map_err(|e| SriError::io(&path.to_string_lossy(), e)));
+		}
+	}
+	Ok(())
+}
+
+
+/// Documents that differ between two states of an index, sorted by id
+pub struct IndexDiff {
+	added: Vec<(String, String)>,
+	removed: Vec<(String, String)>,
+	changed: Vec<(String, String, Vec<String>)>,
+}
+
+impl IndexDiff {
+	/// Id and path of the documents only found in the newer state
+	pub fn get_added(&self) -> &[(String, String)] { &self.added }
+
+	/// Id and path of the documents only found in the older state
+	pub fn get_removed(&self) -> &[(String, String)] { &self.removed }
+
+	/// Id and path of the documents found in both states, with what changed: `path`, `file` or `descriptor`
+	pub fn get_changed(&self) -> &[(String, String, Vec<String>)] { &self.changed }
+
+	pub fn is_empty(&self) -> bool { self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() }
+}
+
+/// Path, fingerprint and descriptor of every document, by id
+fn documents(context: &ContextObject) -> BTreeMap<String, (String, Option<Fingerprint>, Json)> {
+	fn add_base<D: Descriptor>(context: &ContextObject, base: &[D], documents: &mut BTreeMap<String, (String, Option<Fingerprint>, Json)>) {
+		for descriptor in base {
+			if let Some(path) = context.get_association(descriptor.get_id()) {
+				documents.insert(String::from(descriptor.get_id()), (path.clone(), context.get_fingerprint(descriptor.get_id()).cloned(), descriptor.to_json()));
+			}
+		}
+	}
+	let mut documents = BTreeMap::new();
+	add_base(context, context.get_images_base(), &mut documents);
+	add_base(context, context.get_sounds_base(), &mut documents);
+	add_base(context, context.get_texts_base(), &mut documents);
+	documents
+}
+
+impl ContextObject {
+	/// Documents added, removed or changed in `newer` compared to this state of the index
+	pub fn diff(&self, newer: &ContextObject) -> IndexDiff {
+		let (older_documents, newer_documents) = (documents(self), documents(newer));
+		let mut diff = IndexDiff { added: Vec::new(), removed: Vec::new(), changed: Vec::new() };
+		for (id, &(ref path, ref fingerprint, ref descriptor)) in &newer_documents {
+			match older_documents.get(id) {
+				None => diff.added.push((id.clone(), path.clone())),
+				Some(&(ref older_path, ref older_fingerprint, ref older_descriptor)) => {
+					let mut changes = Vec::new();
+					if older_path != path {
+						changes.push(format!("path {} => {}", older_path, path));
+					}
+					if older_fingerprint.map(|fingerprint| fingerprint.get_hash()) != fingerprint.map(|fingerprint| fingerprint.get_hash()) {
+						changes.push(String::from("file"));
+					}
+					if older_descriptor != descriptor {
+						changes.push(String::from("descriptor"));
+					}
+					if !changes.is_empty() {
+						diff.changed.push((id.clone(), path.clone(), changes));
+					}
+				}
+			}
+		}
+		for (id, &(ref path, _, _)) in &older_documents {
+			if !newer_documents.contains_key(id) {
+				diff.removed.push((id.clone(), path.clone()));
+			}
+		}
+		diff
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use super::*;
+	use includes::MediaDescriptor;
+	use includes::MediaKind;
+	use includes::config::Config;
+	use includes::text_types::TextDescriptor;
+	use includes::util::test_directory;
+
+	fn context(index_path: &str) -> ContextObject {
+		let mut params = HashMap::new();
+		params.insert(String::from("index"), String::from(index_path));
+		params.insert(String::from("max_snapshots"), String::from("2"));
+		ContextObject::new(Config::check(&params).into_result().unwrap())
+	}
+
+	fn add_text(context: &mut ContextObject, filename: &str, word: &str) -> String {
+		let id = context.new_document_id(MediaKind::Text, filename, None);
+		let mut occurences = HashMap::new();
+		occurences.insert(String::from(word), 1);
+		context.add_document(String::from(filename), MediaDescriptor::TextDescriptor(TextDescriptor::from_occurences(id.clone(), 1, 1, occurences)));
+		context.set_fingerprint(&id, Fingerprint::new(1, 2, 3));
+		id
+	}
+
+	fn generations(index_path: &str) -> Vec<u64> { list_snapshots(index_path).unwrap().iter().map(|snapshot| snapshot.get_manifest().get_generation()).collect() }
+
+	#[test]
+	fn snapshots_are_compared_and_rolled_back_to() {
+		let index_path = test_directory("snapshot");
+		let mut context = context(&index_path);
+		let first = add_text(&mut context, "docs/a.txt", "alpha");
+		context.save_index().unwrap();
+		let second = add_text(&mut context, "docs/b.txt", "beta");
+		context.save_index().unwrap();
+		let kept = generations(&index_path);
+		assert_eq!(kept.len(), 2);
+
+		assert!(context.remove_document(&first));
+		let third = add_text(&mut context, "docs/c.txt", "gamma");
+		context.set_fingerprint(&second, Fingerprint::new(1, 2, 4));
+		context.save_index().unwrap();
+		assert_eq!(&generations(&index_path)[..1], &kept[1..]);
+
+		let name = find_snapshot(&index_path, &kept[1].to_string()).unwrap();
+		assert_eq!(name, snapshot_name(kept[1]));
+		assert!(find_snapshot(&index_path, "generation-999999").is_err());
+		let mut older = self::context(&index_path);
+		older.load_snapshot(&name).unwrap();
+		let diff = older.diff(&context);
+		assert_eq!(diff.get_added(), &[(third.clone(), String::from("docs/c.txt"))][..]);
+		assert_eq!(diff.get_removed(), &[(first.clone(), String::from("docs/a.txt"))][..]);
+		assert_eq!(diff.get_changed(), &[(second.clone(), String::from("docs/b.txt"), vec![String::from("file")])][..]);
+		assert!(context.diff(&context).is_empty());
+
+		let latest = *generations(&index_path).last().unwrap();
+		context.rollback(&name).unwrap();
+		assert!(context.get_association(&first).is_some() && context.get_association(&third).is_none());
+		let mut reader = self::context(&index_path);
+		reader.load_index().unwrap();
+		assert!(older.diff(&reader).is_empty());
+		let rolled_back = generations(&index_path);
+		assert_eq!(rolled_back[0], latest);
+		assert!(rolled_back[1] > latest);
+	}
+}
@@ -1,7 +1,21 @@
 
 use std::io::BufRead;
 use std::io::Write;
-use std::fs::File;
+use std::collections::BTreeMap;
+use rustc_serialize::json::Json;
+use includes::descriptor::Descriptor;
+use includes::MediaDescriptor;
+use includes::MediaKind;
+use includes::binary_index::RecordReader;
+use includes::binary_index::RecordWriter;
+use includes::json_lines::get_array_field;
+use includes::json_lines::get_i32_array;
+use includes::json_lines::get_u64_field;
+use includes::error::SriError;
+use includes::metadata::Metadata;
+use includes::config::Config;
+use includes::registry;
+use includes::util::split_fields;
 
 pub struct SoundDescriptor {
 	id: String,
@@ -16,7 +30,31 @@ impl SoundDescriptor {
 
 	pub fn from_histogram(id: String, histograms_size: usize, histograms_levels: usize, histograms: Vec<Vec<i32>>) -> SoundDescriptor { SoundDescriptor { id: id, histograms_size: histograms_size, histograms_levels: histograms_levels, histograms: histograms } }
 
-	pub fn from_file(file_reader: &mut BufRead) -> Result<Option<SoundDescriptor>, String> {
+	/// Gives the descriptor the id of the document it replaces
+	pub fn set_id(&mut self, id: String) { self.id = id; }
+
+	pub fn get_histograms_size(&self) -> usize { self.histograms_size }
+
+	pub fn get_histograms_levels(&self) -> usize { self.histograms_levels }
+
+	pub fn get_histograms(&self) -> &Vec<Vec<i32>> { &self.histograms }
+}
+
+impl Descriptor for SoundDescriptor {
+	fn media_kind() -> MediaKind { MediaKind::Sound }
+
+	fn get_id(&self) -> &str { &self.id }
+
+	fn extract(config: &Config, format: &str, filename: &str, metadata: &mut Metadata) -> Result<SoundDescriptor, SriError> {
+		match format {
+			registry::WAV_SOUND => SoundDescriptor::from_wav_file(config, try!(registry::open_document(filename)), metadata),
+			registry::MP3_SOUND => SoundDescriptor::from_mp3_file(config, try!(registry::open_document(filename)), metadata),
+			registry::RAW_SOUND => SoundDescriptor::from_raw_file(config, try!(registry::open_document(filename)), metadata),
+			_ => Err(SriError::decode(format!("Unknown sound format {}", format)).in_file(filename)),
+		}
+	}
+
+	fn from_file(file_reader: &mut BufRead) -> Result<Option<SoundDescriptor>, SriError> {
 		let id: String;
 		let histograms_size: usize;
 		let histograms_count: usize;
@@ -29,44 +67,45 @@ impl SoundDescriptor {
 				match header.trim() {
 					"" => return Ok(None),
 					_ => {
-						let mut header_fields = header.split_whitespace();
+						let mut header_fields = split_fields(&header).into_iter();
+						let header_end = header.trim_right().chars().count() + 1;
 						match header_fields.next() {
-							Some(value) => id = String::from(value),
-							None => return Err(format!("Invalid header ({})", header)),
+							Some((_, value)) => id = String::from(value),
+							None => return Err(SriError::parse(format!("Invalid header ({})", header.trim())).at_column(header_end)),
 						}
 						match header_fields.next() {
-							Some(value) => {
+							Some((column, value)) => {
 								match value.parse::<usize>() {
 									Ok(i) => histograms_size = i,
-									Err(e) => return Err(String::from(format!("Invalid value for header field 2 ({})", e))),
+									Err(e) => return Err(SriError::parse(format!("Invalid value for header field 2 ({})", e)).at_column(column)),
 								}
 							},
-							None => return Err(format!("Invalid header ({})", header)),
+							None => return Err(SriError::parse(format!("Invalid header ({})", header.trim())).at_column(header_end)),
 						}
 
 						match header_fields.next() {
-							Some(value) => {
+							Some((column, value)) => {
 								match value.parse::<usize>() {
 									Ok(i) => histograms_levels = i,
-									Err(e) => return Err(String::from(format!("Invalid value for header field 2 ({})", e))),
+									Err(e) => return Err(SriError::parse(format!("Invalid value for header field 3 ({})", e)).at_column(column)),
 								}
 							},
-							None => return Err(format!("Invalid header ({})", header)),
+							None => return Err(SriError::parse(format!("Invalid header ({})", header.trim())).at_column(header_end)),
 						}
 
 						match header_fields.next() {
-							Some(value) => {
+							Some((column, value)) => {
 								match value.parse::<usize>() {
 									Ok(i) => histograms_count = i,
-									Err(e) => return Err(String::from(format!("Invalid value for header field 2 ({})", e))),
+									Err(e) => return Err(SriError::parse(format!("Invalid value for header field 4 ({})", e)).at_column(column)),
 								}
 							},
-							None => return Err(format!("Invalid header ({})", header)),
+							None => return Err(SriError::parse(format!("Invalid header ({})", header.trim())).at_column(header_end)),
 						}
 					},
 				}
 			},
-			Err(e) => return Err(format!("{}", e)),
+			Err(e) => return Err(SriError::from(e)),
 		}
 
 		for (line_number, line) in file_reader.lines().enumerate() {
@@ -80,44 +119,97 @@ impl SoundDescriptor {
 						_ => (),
 					};
 
+					let values = split_fields(&histogram_line);
+					if values.len() != histograms_levels && values.len() != histograms_levels * 2 {
+						return Err(SriError::parse(format!("Window {} of {} has {} values instead of {}", line_number + 1, id, values.len(), histograms_levels)).at_line(line_number + 2));
+					}
 					let mut histogram = Vec::new();
 					histogram.resize(histograms_levels * 2, 0);
-					for (index, value) in histogram_line.split_whitespace().enumerate() {
+					for (index, (column, value)) in values.into_iter().enumerate() {
 						match value.parse::<i32>() {
 							Ok(i) => histogram[index] = i,
-							Err(e) => return Err(String::from(format!("Invalid value in histogram of {} ({})", id, e))),
+							Err(e) => return Err(SriError::parse(format!("Invalid value in histogram of {} ({})", id, e)).at_line(line_number + 2).at_column(column)),
 						}
 					}
 					histograms.push(histogram);
 				},
-				Err(e) => return Err(format!("{}", e)),
+				Err(e) => return Err(SriError::from(e)),
 			}
 
 		}
 
 		Ok(Some(SoundDescriptor::from_histogram(id, histograms_size, histograms_levels, histograms)))
 	}
-	pub fn get_id(&self) -> &str { &self.id }
-
-	pub fn get_histograms_size(&self) -> usize { self.histograms_size }
 
-	pub fn get_histograms_levels(&self) -> usize { self.histograms_levels }
+	fn to_file(&self, file_writer: &mut Write) -> Result<(), SriError> {
+		try!(write!(file_writer, "{} {} {} {}\n", self.get_id(), self.get_histograms_size(), self.get_histograms_levels(), self.get_histograms().len()));
+		for histogram in self.get_histograms() {
+			for value in histogram {
+				try!(write!(file_writer, "{} ", value));
+			}
+			try!(write!(file_writer, "\n"));
+		}
+		try!(write!(file_writer, "\n"));
+		Ok(())
+	}
 
-	pub fn get_histograms(&self) -> &Vec<Vec<i32>> { &self.histograms }
+	fn from_record(record: &mut RecordReader) -> Result<SoundDescriptor, SriError> {
+		let id = try!(record.read_string());
+		let histograms_size = try!(record.read_u64()) as usize;
+		let histograms_levels = try!(record.read_u32()) as usize;
+		let histograms_count = try!(record.read_count(4));
+		let mut histograms = Vec::with_capacity(histograms_count);
+		for _ in 0..histograms_count {
+			let histogram_length = try!(record.read_count(4));
+			let mut histogram = Vec::with_capacity(histogram_length);
+			for _ in 0..histogram_length {
+				histogram.push(try!(record.read_i32()));
+			}
+			histograms.push(histogram);
+		}
+		Ok(SoundDescriptor::from_histogram(id, histograms_size, histograms_levels, histograms))
+	}
 
-	pub fn to_file(&self, file_writer: &mut File) {
-		write!(file_writer, "{} {} {} {}\n", self.get_id(), self.get_histograms_size(), self.get_histograms_levels(), self.get_histograms().len()).unwrap();
+	fn to_record(&self, record: &mut RecordWriter) {
+		record.write_str(self.get_id());
+		record.write_u64(self.get_histograms_size() as u64);
+		record.write_u32(self.get_histograms_levels() as u32);
+		record.write_u32(self.get_histograms().len() as u32);
 		for histogram in self.get_histograms() {
+			record.write_u32(histogram.len() as u32);
 			for value in histogram {
-				write!(file_writer, "{} ", value).unwrap();
+				record.write_i32(*value);
+			}
+		}
+	}
+
+	fn from_json(id: String, payload: &Json) -> Result<SoundDescriptor, SriError> {
+		let histograms_size = try!(get_u64_field(payload, "window_size")) as usize;
+		let histograms_levels = try!(get_u64_field(payload, "window_levels")) as usize;
+		let mut histograms = Vec::new();
+		for window in try!(get_array_field(payload, "windows")) {
+			let histogram = match window.as_array() {
+				Some(values) => try!(get_i32_array(values, "windows")),
+				None => return Err(SriError::parse("Field 'windows' holds a value that is not an array")),
+			};
+			if histogram.len() < histograms_levels {
+				return Err(SriError::parse(format!("Window {} of {} has {} levels, fewer than {}", histograms.len(), id, histogram.len(), histograms_levels)));
 			}
-			write!(file_writer, "\n").unwrap();
+			histograms.push(histogram);
 		}
-		write!(file_writer, "\n").unwrap();
+		Ok(SoundDescriptor::from_histogram(id, histograms_size, histograms_levels, histograms))
 	}
 
+	fn to_json(&self) -> Json {
+		let mut payload = BTreeMap::new();
+		payload.insert(String::from("window_size"), Json::U64(self.get_histograms_size() as u64));
+		payload.insert(String::from("window_levels"), Json::U64(self.get_histograms_levels() as u64));
+		let windows = self.get_histograms().iter().map(|histogram| Json::Array(histogram.iter().map(|value| Json::I64(*value as i64)).collect()));
+		payload.insert(String::from("windows"), Json::Array(windows.collect()));
+		Json::Object(payload)
+	}
 
-	pub fn compare_to(&self, other: &SoundDescriptor) -> f64 {
+	fn compare_to(&self, other: &SoundDescriptor) -> f64 {
 		if self.get_histograms().len() < other.get_histograms().len() {
 			return other.compare_to(self);
 		}
@@ -153,4 +245,33 @@ impl SoundDescriptor {
 		}
 		return score;
 	}
+
+	fn into_media_descriptor(self) -> MediaDescriptor { MediaDescriptor::SoundDescriptor(self) }
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::BufReader;
+
+	use super::*;
+
+	#[test]
+	fn base_files_are_read_back() {
+		let mut bytes = Vec::new();
+		SoundDescriptor::from_histogram(String::from("snd1"), 4, 1, vec![vec![1, 3], vec![0, 4]]).to_file(&mut bytes).unwrap();
+
+		let mut reader = BufReader::new(&bytes[..]);
+		let copy = SoundDescriptor::from_file(&mut reader).unwrap().unwrap();
+		assert_eq!((copy.get_id(), copy.get_histograms_size(), copy.get_histograms_levels()), ("snd1", 4, 1));
+		assert_eq!(copy.get_histograms(), &vec![vec![1, 3], vec![0, 4]]);
+		assert!(SoundDescriptor::from_file(&mut reader).unwrap().is_none());
+	}
+
+	#[test]
+	fn json_payloads_are_read_back() {
+		let original = SoundDescriptor::from_histogram(String::from("snd1"), 4, 1, vec![vec![1, 3], vec![0, 4]]);
+		let copy = SoundDescriptor::from_json(String::from("snd1"), &original.to_json()).unwrap();
+		assert_eq!(copy.get_histograms(), original.get_histograms());
+		assert_eq!((copy.get_histograms_size(), copy.get_histograms_levels()), (4, 1));
+	}
 }
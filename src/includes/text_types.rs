@@ -1,9 +1,22 @@
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 
 use std::io::BufRead;
 use std::io::Write;
-use std::fs::File;
+use rustc_serialize::json::Json;
+use includes::descriptor::Descriptor;
+use includes::MediaDescriptor;
+use includes::MediaKind;
+use includes::binary_index::RecordReader;
+use includes::binary_index::RecordWriter;
+use includes::json_lines::get_field;
+use includes::json_lines::get_u64_field;
+use includes::error::SriError;
+use includes::metadata::Metadata;
+use includes::config::Config;
+use includes::registry;
+use includes::util::split_fields;
 use std::collections::HashSet;
 
 // pub const NB_TERM: usize = 64;
@@ -26,7 +39,37 @@ impl TextDescriptor {
 	pub fn from_occurences(id: String, word_file: usize, word_numb: usize, occurences: HashMap<String, i32>) -> TextDescriptor { TextDescriptor { id: id, word_file: word_file, word_numb: word_numb, occurences: occurences } }
 
 
-	pub fn from_file(file_reader: &mut BufRead) -> Result<Option<TextDescriptor>, String> {
+	/// Gives the descriptor the id of the document it replaces
+	pub fn set_id(&mut self, id: String) { self.id = id; }
+
+	pub fn get_word_file(&self) -> usize { self.word_file }
+
+	pub fn get_word_numb(&self) -> usize { self.word_numb }
+
+	pub fn get_occurences(&self) -> &HashMap<String, i32> { &self.occurences }
+
+	pub fn get_sorted_occurences(&self) -> Vec<(String, i32)> {
+		let mut values: Vec<(String, i32)> = self.occurences.clone().into_iter().collect();
+		values.sort_by(|&(_, v_a), &(_, v_b)| v_b.cmp(&v_a));
+		values
+	}
+}
+
+impl Descriptor for TextDescriptor {
+	fn media_kind() -> MediaKind { MediaKind::Text }
+
+	fn get_id(&self) -> &str { &self.id }
+
+	fn extract(config: &Config, format: &str, filename: &str, metadata: &mut Metadata) -> Result<TextDescriptor, SriError> {
+		match format {
+			registry::XML_TEXT => TextDescriptor::from_text_file(config, try!(registry::open_document(filename)), metadata),
+			registry::SUBTITLE => TextDescriptor::from_subrip_file(config, try!(registry::open_document(filename)), metadata),
+			registry::PLAIN_TEXT => TextDescriptor::from_plain_text_file(config, try!(registry::open_document(filename)), metadata),
+			_ => Err(SriError::decode(format!("Unknown text format {}", format)).in_file(filename)),
+		}
+	}
+
+	fn from_file(file_reader: &mut BufRead) -> Result<Option<TextDescriptor>, SriError> {
 		let id: String;
 		let word_file: usize;
 		let word_numb: usize;
@@ -38,53 +81,54 @@ impl TextDescriptor {
 				match header.trim() {
 					"" => return Ok(None),
 					_ => {
-						let mut header_fields = header.split_whitespace();
+						let mut header_fields = split_fields(&header).into_iter();
+						let header_end = header.trim_right().chars().count() + 1;
 						match header_fields.next() {
-							Some(value) => id = String::from(value),
-							None => return Err(format!("Invalid header ({})", header)),
+							Some((_, value)) => id = String::from(value),
+							None => return Err(SriError::parse(format!("Invalid header ({})", header.trim())).at_column(header_end)),
 						}
 						match header_fields.next() {
-							Some(value) => {
+							Some((column, value)) => {
 								match value.parse::<usize>() {
 									Ok(i) => word_file = i,
-									Err(e) => return Err(String::from(format!("Invalid value for header field 2 ({})", e))),
+									Err(e) => return Err(SriError::parse(format!("Invalid value for header field 2 ({})", e)).at_column(column)),
 								}
 							},
-							None => return Err(format!("Invalid header ({})", header)),
+							None => return Err(SriError::parse(format!("Invalid header ({})", header.trim())).at_column(header_end)),
 						}
 						match header_fields.next() {
-							Some(value) => {
+							Some((column, value)) => {
 								match value.parse::<usize>() {
 									Ok(i) => word_numb = i,
-									Err(e) => return Err(String::from(format!("Invalid value for header field 3 ({})", e))),
+									Err(e) => return Err(SriError::parse(format!("Invalid value for header field 3 ({})", e)).at_column(column)),
 								}
 							},
-							None => return Err(format!("Invalid header ({})", header)),
+							None => return Err(SriError::parse(format!("Invalid header ({})", header.trim())).at_column(header_end)),
 						}
 					},
 				}
 			},
-			Err(e) => return Err(format!("{}", e)),
+			Err(e) => return Err(SriError::from(e)),
 		}
 
 		let mut occurences_line = String::new();
 		match file_reader.read_line(&mut occurences_line) {
 			Ok(_) => {
-				let mut line_iterator = occurences_line.split_whitespace();
+				let mut line_iterator = split_fields(&occurences_line).into_iter();
 				loop {
-					let word = match line_iterator.next() {
-						Some(value) => value,
+					let (word_column, word) = match line_iterator.next() {
+						Some(field) => field,
 						None => break,
 					};
 
 					let result = match line_iterator.next() {
-						Some(value) => {
+						Some((column, value)) => {
 							match value.parse::<i32>() {
 								Ok(i) => Ok(occurences.insert(String::from(word), i)),
-								Err(e) => Err(String::from(format!("Can't parse occurence for '{}' ({})", word, e))),
+								Err(e) => Err(SriError::parse(format!("Can't parse occurence for '{}' ({})", word, e)).at_line(2).at_column(column)),
 							}
 						},
-						None => Err(String::from(format!("Can't find occurence for '{}'", word))),
+						None => Err(SriError::parse(format!("Can't find occurence for '{}'", word)).at_line(2).at_column(word_column)),
 					};
 
 					match result {
@@ -92,37 +136,79 @@ impl TextDescriptor {
 						_ => (),
 					}
 				}
-				()
+				// The kept words add up to the filtered count, fewer of them on a last line without end means it was cut
+				let kept_words: i64 = occurences.values().map(|&occurence| occurence as i64).sum();
+				if kept_words < word_numb as i64 && !occurences_line.ends_with('\n') {
+					return Err(SriError::parse(format!("Truncated occurences of {}, {} words instead of {}", id, kept_words, word_numb)).at_line(2));
+				}
 			},
-			Err(e) => return Err(format!("{}", e)),
+			Err(e) => return Err(SriError::from(e)),
 		}
 
 		Ok(Some(TextDescriptor::from_occurences(id, word_file, word_numb, occurences)))
 	}
 
-	pub fn get_id(&self) -> &str { &self.id }
-
-	pub fn get_word_file(&self) -> usize { self.word_file }
-
-	pub fn get_word_numb(&self) -> usize { self.word_numb }
-
-	pub fn get_occurences(&self) -> &HashMap<String, i32> { &self.occurences }
+	fn to_file(&self, file_writer: &mut Write) -> Result<(), SriError> {
+		try!(write!(file_writer, "{} {} {}\n", self.get_id(), self.get_word_file(), self.get_word_numb()));
+		for (word, occurence) in self.get_sorted_occurences() {
+			try!(write!(file_writer, "{} {} ", word, occurence));
+		}
+		try!(write!(file_writer, "\n"));
+		Ok(())
+	}
 
-	pub fn get_sorted_occurences(&self) -> Vec<(String, i32)> {
-		let mut values: Vec<(String, i32)> = self.occurences.clone().into_iter().collect();
-		values.sort_by(|&(_, v_a), &(_, v_b)| v_b.cmp(&v_a));
-		values
+	fn from_record(record: &mut RecordReader) -> Result<TextDescriptor, SriError> {
+		let id = try!(record.read_string());
+		let word_file = try!(record.read_u64()) as usize;
+		let word_numb = try!(record.read_u64()) as usize;
+		// Each occurence holds the length of its word and its count at least
+		let occurences_count = try!(record.read_count(8));
+		let mut occurences = HashMap::with_capacity(occurences_count);
+		for _ in 0..occurences_count {
+			let word = try!(record.read_string());
+			let occurence = try!(record.read_i32());
+			occurences.insert(word, occurence);
+		}
+		Ok(TextDescriptor::from_occurences(id, word_file, word_numb, occurences))
 	}
 
-	pub fn to_file(&self, file_writer: &mut File) {
-		write!(file_writer, "{} {} {}\n", self.get_id(), self.get_word_file(), self.get_word_numb()).unwrap();
+	fn to_record(&self, record: &mut RecordWriter) {
+		record.write_str(self.get_id());
+		record.write_u64(self.get_word_file() as u64);
+		record.write_u64(self.get_word_numb() as u64);
+		record.write_u32(self.get_occurences().len() as u32);
 		for (word, occurence) in self.get_sorted_occurences() {
-			write!(file_writer, "{} {} ", word, occurence).unwrap();
+			record.write_str(&word);
+			record.write_i32(occurence);
+		}
+	}
+
+	fn from_json(id: String, payload: &Json) -> Result<TextDescriptor, SriError> {
+		let word_file = try!(get_u64_field(payload, "words")) as usize;
+		let word_numb = try!(get_u64_field(payload, "kept_words")) as usize;
+		let terms = match try!(get_field(payload, "occurrences")).as_object() {
+			Some(terms) => terms,
+			None => return Err(SriError::parse("Field 'occurrences' is not an object")),
+		};
+		let mut occurences = HashMap::with_capacity(terms.len());
+		for (word, occurence) in terms {
+			match occurence.as_i64() {
+				Some(occurence) if occurence >= 0 && occurence <= i32::max_value() as i64 => occurences.insert(word.clone(), occurence as i32),
+				_ => return Err(SriError::parse(format!("Invalid occurrence count for '{}' in {}", word, id))),
+			};
 		}
-		write!(file_writer, "\n").unwrap();
+		Ok(TextDescriptor::from_occurences(id, word_file, word_numb, occurences))
 	}
 
-	pub fn compare_to(&self, other: &TextDescriptor) -> f64 {
+	fn to_json(&self) -> Json {
+		let mut payload = BTreeMap::new();
+		payload.insert(String::from("words"), Json::U64(self.get_word_file() as u64));
+		payload.insert(String::from("kept_words"), Json::U64(self.get_word_numb() as u64));
+		payload.insert(String::from("occurrences"), Json::Object(self.get_occurences().iter().map(|(word, occurence)| (word.clone(), Json::I64(*occurence as i64))).collect()));
+		Json::Object(payload)
+	}
+
+	fn compare_to(&self, other: &TextDescriptor) -> f64 {
 		let self_keys: HashSet<&String> = self.get_occurences().keys().collect();
 		let other_keys: HashSet<&String> = other.get_occurences().keys().collect();
 		let common_keys = self_keys.intersection(&other_keys);
@@ -140,4 +226,54 @@ impl TextDescriptor {
 		}
 		return score;
 	}
+
+	fn into_media_descriptor(self) -> MediaDescriptor { MediaDescriptor::TextDescriptor(self) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::*;
+
+    fn descriptor(id: &str, words: &[(&str, i32)]) -> TextDescriptor {
+        let occurences: HashMap<String, i32> = words.iter().map(|&(word, count)| (String::from(word), count)).collect();
+        TextDescriptor::from_occurences(String::from(id), 10, words.len(), occurences)
+    }
+
+    #[test]
+    fn base_files_are_read_back() {
+        let mut bytes = Vec::new();
+        descriptor("txt1", &[("alpha", 2), ("beta", 1)]).to_file(&mut bytes).unwrap();
+        descriptor("txt2", &[("gamma", 5)]).to_file(&mut bytes).unwrap();
+
+        let mut reader = BufReader::new(&bytes[..]);
+        let first = TextDescriptor::from_file(&mut reader).unwrap().unwrap();
+        assert_eq!((first.get_id(), first.get_word_file(), first.get_word_numb()), ("txt1", 10, 2));
+        assert_eq!(first.get_sorted_occurences(), vec![(String::from("alpha"), 2), (String::from("beta"), 1)]);
+        assert_eq!(TextDescriptor::from_file(&mut reader).unwrap().unwrap().get_id(), "txt2");
+        assert!(TextDescriptor::from_file(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn malformed_base_files_are_refused() {
+        let mut reader = BufReader::new(&b"txt1 ten 2\nalpha 2 \n"[..]);
+        assert!(TextDescriptor::from_file(&mut reader).is_err());
+    }
+
+    #[test]
+    fn json_payloads_are_read_back() {
+        let original = descriptor("txt1", &[("alpha", 2), ("beta", 1)]);
+        let copy = TextDescriptor::from_json(String::from("txt1"), &original.to_json()).unwrap();
+        assert_eq!(copy.get_occurences(), original.get_occurences());
+        assert_eq!((copy.get_word_file(), copy.get_word_numb()), (10, 2));
+    }
+
+    #[test]
+    fn shared_words_bring_texts_closer() {
+        let query = descriptor("query", &[("alpha", 2), ("beta", 1)]);
+        let close = descriptor("close", &[("alpha", 2), ("beta", 1)]);
+        let far = descriptor("far", &[("gamma", 3)]);
+        assert!(query.compare_to(&close) > query.compare_to(&far));
+    }
 }
@@ -1 +1,166 @@
+use std::env;
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
 
+/// Reader keeping track of how many lines were consumed, to locate errors in the files being parsed
+pub struct LineCountingReader<R> {
+	inner: R,
+	line: usize,
+}
+
+impl<R: BufRead> LineCountingReader<R> {
+	pub fn new(inner: R) -> LineCountingReader<R> { LineCountingReader { inner: inner, line: 0 } }
+
+	/// Number of complete lines read so far
+	pub fn get_line(&self) -> usize { self.line }
+}
+
+fn count_lines(buffer: &[u8]) -> usize { buffer.iter().filter(|&&byte| byte == b'\n').count() }
+
+impl<R: BufRead> Read for LineCountingReader<R> {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		let read = try!(self.inner.read(buffer));
+		self.line += count_lines(&buffer[..read]);
+		Ok(read)
+	}
+}
+
+impl<R: BufRead> BufRead for LineCountingReader<R> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> { self.inner.fill_buf() }
+
+	fn consume(&mut self, amount: usize) {
+		let lines = match self.inner.fill_buf() {
+			Ok(buffer) => count_lines(&buffer[..amount]),
+			Err(_) => 0,
+		};
+		self.line += lines;
+		self.inner.consume(amount);
+	}
+}
+
+/// Whitespace separated fields of a line, each one with its column, counted in characters from 1
+pub fn split_fields(line: &str) -> Vec<(usize, &str)> {
+	let mut fields = Vec::new();
+	let mut start = None;
+	for (column, (index, character)) in line.char_indices().enumerate() {
+		match (character.is_whitespace(), start) {
+			(false, None) => start = Some((column + 1, index)),
+			(true, Some((field_column, field_index))) => {
+				fields.push((field_column, &line[field_index..index]));
+				start = None;
+			}
+			_ => (),
+		}
+	}
+	if let Some((field_column, field_index)) = start {
+		fields.push((field_column, &line[field_index..]));
+	}
+	fields
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64 bits FNV-1a hash, stable across builds and platforms, fed with successive slices of bytes
+pub struct Fnv1a {
+	hash: u64,
+}
+
+impl Fnv1a {
+	pub fn new() -> Fnv1a { Fnv1a { hash: FNV_OFFSET_BASIS } }
+
+	pub fn write(&mut self, bytes: &[u8]) {
+		for byte in bytes {
+			self.hash ^= *byte as u64;
+			self.hash = self.hash.wrapping_mul(FNV_PRIME);
+		}
+	}
+
+	pub fn finish(&self) -> u64 { self.hash }
+}
+
+/// FNV-1a hash of a slice of bytes
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+	let mut hasher = Fnv1a::new();
+	hasher.write(bytes);
+	hasher.finish()
+}
+
+/// Path with `/` separators and without `.` components, empty components or `..` components that can be resolved
+///
+/// Only the text of the path is looked at, so that the same path normalises the same way whether the file exists or not.
+pub fn normalize_path(path: &str) -> String {
+	let mut components: Vec<&str> = Vec::new();
+	for component in path.split(|c| c == '/' || c == '\\') {
+		match component {
+			"" | "." => (),
+			".." if components.last().map_or(false, |last| *last != "..") => {
+				components.pop();
+			}
+			component => components.push(component),
+		}
+	}
+	let normalized = components.join("/");
+	if path.starts_with('/') { format!("/{}", normalized) } else { normalized }
+}
+
+/// Normalised absolute path of a path given from the working directory
+pub fn absolute_path(path: &str) -> String {
+	if path.starts_with('/') {
+		return normalize_path(path);
+	}
+	match env::current_dir() {
+		Ok(directory) => normalize_path(&format!("{}/{}", directory.to_string_lossy(), path)),
+		Err(_) => normalize_path(path),
+	}
+}
+
+/// Part of `path` below the `directory` one, `None` when it is not inside it
+///
+/// Both paths are compared component by component once normalised, so `/data/a` is not inside `/data/ab`.
+pub fn relative_path(path: &str, directory: &str) -> Option<String> {
+	let (path, directory) = (normalize_path(path), normalize_path(directory));
+	if directory.is_empty() && !path.starts_with('/') {
+		return Some(path);
+	}
+	let prefix = if directory.ends_with('/') { directory } else { format!("{}/", directory) };
+	match path.starts_with(&prefix) && path.len() > prefix.len() {
+		true => Some(String::from(&path[prefix.len()..])),
+		false => None,
+	}
+}
+
+/// Reports a problem that does not stop the current operation, on the standard error so the output of a command stays clean
+pub fn warn(message: &str) {
+	let _ = writeln!(&mut io::stderr(), "Warning: {}", message);
+}
+
+/// Empty directory for the files of a test, unique to the test and to the process running it
+#[cfg(test)]
+pub fn test_directory(name: &str) -> String {
+	use std::fs;
+	use std::process;
+	let directory = env::temp_dir().join(format!("sri-test-{}-{}", process::id(), name));
+	let _ = fs::remove_dir_all(&directory);
+	fs::create_dir_all(&directory).unwrap();
+	directory.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fields_are_split_with_their_columns() {
+		assert_eq!(split_fields("  a bc\td"), vec![(3, "a"), (5, "bc"), (8, "d")]);
+	}
+
+	#[test]
+	fn paths_are_normalized() {
+		assert_eq!(normalize_path("a/./b/../c/"), "a/c");
+		assert_eq!(relative_path("/data/docs/a.txt", "/data"), Some(String::from("docs/a.txt")));
+		assert_eq!(relative_path("/data/ab/a.txt", "/data/a"), None);
+	}
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use includes::descriptor::Descriptor;
+use includes::error::SriError;
+use includes::manifest;
+
+use includes::context_types::ContextObject;
+
+/// Inconsistency of an index found by `verify`
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+	/// Id associated to a filename but without descriptor
+	OrphanAssociation(String),
+	/// Descriptor whose id has no association
+	OrphanDescriptor(String),
+	/// Id of several descriptors, with their number
+	DuplicateId(String, usize),
+	/// Record of a text file of the index left out by a lenient loading, with its file, line and column
+	UnreadableRecord(String),
+	/// Id and path of a document whose file can't be found anymore
+	MissingFile(String, String),
+	/// Id of a descriptor whose histograms don't have the length the index expects, with what is wrong
+	WrongHistogram(String, String),
+	/// File of the index whose content doesn't match its checksum in the manifest, with what is wrong
+	CorruptFile(String),
+}
+
+impl fmt::Display for Problem {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Problem::OrphanAssociation(ref id) => write!(f, "Association without descriptor: {}", id),
+			Problem::OrphanDescriptor(ref id) => write!(f, "Descriptor without association: {}", id),
+			Problem::DuplicateId(ref id, count) => write!(f, "Duplicate id: {} ({} descriptors)", id, count),
+			Problem::UnreadableRecord(ref message) => write!(f, "Unreadable record: {}", message),
+			Problem::MissingFile(ref id, ref path) => write!(f, "Missing file: {} {}", id, path),
+			Problem::WrongHistogram(ref id, ref message) => write!(f, "Wrong histogram: {} {}", id, message),
+			Problem::CorruptFile(ref message) => write!(f, "Corrupt file: {}", message),
+		}
+	}
+}
+
+fn count_ids<D: Descriptor>(base: &[D], counts: &mut BTreeMap<String, usize>) {
+	for descriptor in base {
+		*counts.entry(String::from(descriptor.get_id())).or_insert(0) += 1;
+	}
+}
+
+impl ContextObject {
+	/// Problems of the loaded index, orphans first and missing files last
+	///
+	/// Image histograms always have 64 levels, the records of other lengths being already left out at loading.
+	/// Sound windows must have the levels of their descriptor, twice as many once reloaded from a text base,
+	/// and every sound the window size and levels of the config when it sets them.
+	pub fn verify(&self) -> Vec<Problem> {
+		let mut problems = Vec::new();
+		let (orphan_associations, orphan_descriptors) = self.find_orphans();
+		problems.extend(orphan_associations.into_iter().map(Problem::OrphanAssociation));
+		problems.extend(orphan_descriptors.into_iter().map(Problem::OrphanDescriptor));
+
+		let mut counts = BTreeMap::new();
+		count_ids(self.get_images_base(), &mut counts);
+		count_ids(self.get_sounds_base(), &mut counts);
+		count_ids(self.get_texts_base(), &mut counts);
+		problems.extend(counts.into_iter().filter(|&(_, count)| count > 1).map(|(id, count)| Problem::DuplicateId(id, count)));
+
+		problems.extend(self.get_skipped_records().iter().map(|e| Problem::UnreadableRecord(e.to_string())));
+
+		let (window_size, window_levels) = (self.get_config().get_window_size(), self.get_config().get_window_levels());
+		for sound in self.get_sounds_base() {
+			let levels = sound.get_histograms_levels();
+			let message = if window_size.map_or(false, |size| size != sound.get_histograms_size()) || window_levels.map_or(false, |config_levels| config_levels != levels) {
+				Some(format!("computed with windows of {} samples and {} levels, unlike the config", sound.get_histograms_size(), levels))
+			} else if sound.get_histograms().is_empty() {
+				Some(String::from("has no window"))
+			} else {
+				sound.get_histograms()
+				     .iter()
+				     .position(|histogram| histogram.len() != levels && histogram.len() != levels * 2)
+				     .map(|window| format!("window {} has {} values instead of {}", window + 1, sound.get_histograms()[window].len(), levels))
+			};
+			if let Some(message) = message {
+				problems.push(Problem::WrongHistogram(String::from(sound.get_id()), message));
+			}
+		}
+
+		// Only checked here, loading the index only compares the lengths of its files with the manifest
+		let index_path = self.get_config().get_index();
+		if let Ok(Some(manifest)) = manifest::load_manifest(index_path) {
+			for entry in manifest.get_files() {
+				if let Err(e) = manifest.check_file(index_path, entry) {
+					problems.push(Problem::CorruptFile(e.to_string()));
+				}
+			}
+		}
+
+		problems.extend(self.find_missing_documents().into_iter().map(|(id, path)| Problem::MissingFile(id, path)));
+		problems
+	}
+
+	/// Fixes the problems `verify` finds then compacts the index, returning the problems left
+	///
+	/// Orphans and documents with wrong histograms are removed, so the next indexing extracts their file again.
+	/// Only the last loaded descriptor of a duplicate id is kept, and the compaction drops the unreadable records
+	/// and rewrites the files that did not match the manifest with what could be loaded from them.
+	/// An id shared by descriptors of different media kinds and the missing files are left for the user.
+	pub fn repair(&mut self) -> Result<Vec<Problem>, SriError> {
+		for problem in self.verify() {
+			match problem {
+				Problem::OrphanAssociation(id) | Problem::OrphanDescriptor(id) | Problem::WrongHistogram(id, _) => {
+					self.remove_document(&id);
+				}
+				_ => (),
+			}
+		}
+		self.remove_duplicate_descriptors();
+		try!(self.compact());
+		Ok(self.verify())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+	use std::fs::File;
+	use std::io::Read;
+	use std::io::Write;
+
+	use super::*;
+	use includes::MediaDescriptor;
+	use includes::config::Config;
+	use includes::sound_types::SoundDescriptor;
+	use includes::text_types::TextDescriptor;
+	use includes::util::test_directory;
+
+	fn context(index_path: &str) -> ContextObject {
+		let mut params = HashMap::new();
+		params.insert(String::from("index"), String::from(index_path));
+		params.insert(String::from("window_size"), String::from("4"));
+		params.insert(String::from("window_levels"), String::from("1"));
+		params.insert(String::from("max_snapshots"), String::from("0"));
+		ContextObject::new(Config::check(&params).into_result().unwrap())
+	}
+
+	fn text(id: &str, word: &str) -> TextDescriptor {
+		let mut occurences = HashMap::new();
+		occurences.insert(String::from(word), 1);
+		TextDescriptor::from_occurences(String::from(id), 1, 1, occurences)
+	}
+
+	#[test]
+	fn problems_are_found_and_repaired() {
+		let directory = test_directory("verify");
+		let (indexed, other, missing) = (format!("{}/a.txt", directory), format!("{}/b.txt", directory), format!("{}/missing.txt", directory));
+		File::create(&indexed).unwrap();
+		File::create(&other).unwrap();
+		let index_path = format!("{}/index", directory);
+		let mut writer = context(&index_path);
+		writer.add_document(indexed.clone(), MediaDescriptor::TextDescriptor(text("txt0", "alpha")));
+		writer.save_index().unwrap();
+		assert!(writer.verify().is_empty());
+
+		let base_path = format!("{}/{}", index_path, writer.get_config().get_texts_base_filename());
+		let mut base = String::new();
+		File::open(&base_path).unwrap().read_to_string(&mut base).unwrap();
+		File::create(&base_path).unwrap().write_all(base.replace("alpha", "omega").as_bytes()).unwrap();
+
+		let mut context = context(&index_path);
+		context.load_index().unwrap();
+		context.add_text_association(String::from("txt5"), other.clone());
+		context.add_text_descriptor(text("txt6", "beta"));
+		context.add_text_descriptor(text("txt0", "gamma"));
+		context.add_document(other.clone(), MediaDescriptor::SoundDescriptor(SoundDescriptor::from_histogram(String::from("snd7"), 8, 1, vec![vec![0, 1]])));
+		context.add_document(missing.clone(), MediaDescriptor::TextDescriptor(text("txt8", "delta")));
+
+		let problems = context.verify();
+		assert_eq!(&problems[..4], &[Problem::OrphanAssociation(String::from("txt5")), Problem::OrphanDescriptor(String::from("txt6")), Problem::DuplicateId(String::from("txt0"), 2), Problem::WrongHistogram(String::from("snd7"), String::from("computed with windows of 8 samples and 1 levels, unlike the config"))][..]);
+		match problems[4] {
+			Problem::CorruptFile(ref message) => assert!(message.contains(context.get_config().get_texts_base_filename())),
+			ref problem => panic!("unexpected problem: {}", problem),
+		}
+		assert_eq!(&problems[5..], &[Problem::MissingFile(String::from("txt8"), missing.clone())][..]);
+
+		let remaining = context.repair().unwrap();
+		assert_eq!(remaining, vec![Problem::MissingFile(String::from("txt8"), missing)]);
+		assert_eq!(context.get_texts_base().iter().filter(|descriptor| descriptor.get_id() == "txt0").count(), 1);
+		assert!(context.get_texts_base()[0].get_occurences().contains_key("gamma"));
+		let mut reader = self::context(&index_path);
+		reader.load_index().unwrap();
+		assert_eq!(reader.verify(), remaining);
+	}
+}
@@ -0,0 +1,75 @@
+//! Multimedia search engine indexing images, sounds and texts.
+//!
+//! Everything goes through a `ContextObject`: it holds the configuration, the
+//! associations between document ids and filenames, and the descriptors of
+//! every indexed document.
+//!
+//! ```no_run
+//! extern crate sri_rs;
+//!
+//! let mut context = sri_rs::open_index("sri.properties").unwrap();
+//! context.start_indexing("documents.list").unwrap();
+//! context.save_index().unwrap();
+//!
+//! for result in context.query("1:query_image.png").unwrap() {
+//! 	println!("{} {} {}", result.get_rank(), result.get_document_id(), result.get_score());
+//! }
+//! ```
+
+extern crate java_properties;
+extern crate byteorder;
+extern crate xml;
+extern crate stemmer;
+extern crate regex;
+extern crate time;
+extern crate image;
+extern crate hound;
+extern crate simplemad;
+extern crate subrip;
+extern crate rustc_serialize;
+extern crate libc;
+extern crate num_cpus;
+extern crate walkdir;
+extern crate glob;
+extern crate notify;
+
+pub mod includes;
+
+pub use includes::context_types::ContextObject;
+pub use includes::context_types::MergeReport;
+pub use includes::config::Config;
+pub use includes::config::IndexFormat;
+pub use includes::config::IdScheme;
+pub use includes::config::LoadMode;
+pub use includes::image_types::ImageDescriptor;
+pub use includes::sound_types::SoundDescriptor;
+pub use includes::text_types::TextDescriptor;
+pub use includes::querying::SearchResult;
+pub use includes::querying::ResultFormat;
+pub use includes::querying::federation::Federation;
+pub use includes::descriptor::Descriptor;
+pub use includes::MediaDescriptor;
+pub use includes::MediaKind;
+pub use includes::metadata::Metadata;
+pub use includes::collection::CollectionRoot;
+pub use includes::lock::LockMode;
+pub use includes::snapshot::IndexDiff;
+pub use includes::verify::Problem;
+pub use includes::registry::MediaType;
+pub use includes::indexing::IndexingOutcome;
+pub use includes::indexing::report::IndexingReport;
+pub use includes::indexing::report::DocumentReport;
+pub use includes::crawl::Crawler;
+pub use includes::crawl::SymlinkPolicy;
+pub use includes::error::SriError;
+pub use includes::error::SriResult;
+
+
+/// Loads the configuration from `config_filename`, then the index it points to.
+///
+/// A missing index directory is not an error: the returned context is simply empty.
+pub fn open_index(config_filename: &str) -> Result<ContextObject, SriError> {
+	let mut context = try!(ContextObject::from_config_file(config_filename));
+	try!(context.load_index());
+	Ok(context)
+}
@@ -0,0 +1,658 @@
+extern crate getopts;
+extern crate sri_rs;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+
+use getopts::Matches;
+use getopts::Options;
+
+use sri_rs::Config;
+use sri_rs::IndexFormat;
+use sri_rs::IdScheme;
+use sri_rs::IndexingReport;
+use sri_rs::ContextObject;
+use sri_rs::Crawler;
+use sri_rs::Federation;
+use sri_rs::ResultFormat;
+use sri_rs::SriError;
+use sri_rs::SymlinkPolicy;
+use sri_rs::includes::snapshot;
+use sri_rs::includes::util::warn;
+
+
+static COMMANDS: &'static str = "Commands:
+    index     Index the documents of documents lists or of directory trees, or keep a directory tree indexed
+    query     Run the queries of a queries list
+    stats     Print the content of the index
+    remove    Remove documents from the index by id or by path
+    export    Write a copy of the index to another directory, or its documents as JSON Lines
+    import    Add the documents of JSON Lines files to the index
+    merge     Add the documents of other index directories to the index
+    reid      Give every document the stable id of the id scheme of the config
+    relocate  Change the collection root of the index or rewrite the prefixes of its paths
+    convert   Rewrite the index in the text or binary format
+    compact   Merge the segments appended by the last saves into the index
+    snapshot  List the generations kept by the saves, compare them or roll back to one
+    verify    Check the index for orphans, duplicate ids, unreadable records, wrong histograms and missing files
+    config    Check a config file and print the effective configuration";
+
+const DEFAULT_RESULT_LIMIT: usize = 10;
+
+
+fn fail(error: SriError) -> ! {
+	writeln!(&mut io::stderr(), "Error: {}", error).unwrap();
+	process::exit(1);
+}
+
+fn usage(command: &str, arguments: &str, options: &Options) -> ! {
+	let brief = format!("Usage: sri {} [options] {}", command, arguments);
+	writeln!(&mut io::stderr(), "{}", options.usage(&brief)).unwrap();
+	process::exit(2);
+}
+
+/// Options shared by every command, to locate the index and tune its config
+fn common_options() -> Options {
+	let mut options = Options::new();
+	options.optopt("c", "config", "java properties config file", "FILE");
+	options.optopt("i", "index", "index directory, overriding the 'index' parameter of the config", "DIR");
+	options.optmulti("s", "set", "overrides a parameter of the config", "KEY=VALUE");
+	options.optflag("h", "help", "print this help");
+	options
+}
+
+fn parse_options(command: &str, arguments: &str, options: &Options, args: &[String]) -> Matches {
+	let matches = match options.parse(args) {
+		Ok(matches) => matches,
+		Err(e) => {
+			writeln!(&mut io::stderr(), "Error: {}", e).unwrap();
+			usage(command, arguments, options);
+		},
+	};
+	if matches.opt_present("help") {
+		usage(command, arguments, options);
+	}
+	matches
+}
+
+/// Raw config parameters from the config file, then the command line overrides
+fn load_params(matches: &Matches) -> Result<HashMap<String, String>, SriError> {
+	let mut params = match matches.opt_str("config") {
+		Some(config_filename) => try!(Config::load_params(&config_filename)),
+		None => HashMap::new(),
+	};
+	if let Some(index) = matches.opt_str("index") {
+		params.insert(String::from("index"), index);
+	}
+	for assignment in matches.opt_strs("set") {
+		let mut fields = assignment.splitn(2, '=');
+		match (fields.next(), fields.next()) {
+			(Some(key), Some(value)) => params.insert(String::from(key.trim()), String::from(value.trim())),
+			_ => return Err(SriError::config(format!("Invalid override '{}', expected KEY=VALUE", assignment))),
+		};
+	}
+	Ok(params)
+}
+
+fn load_config(matches: &Matches) -> Config { check_params(&load_params(matches).unwrap_or_else(|e| fail(e))) }
+
+fn check_params(params: &HashMap<String, String>) -> Config {
+	let check = Config::check(params);
+	print_warnings(check.get_warnings());
+	check.into_result().unwrap_or_else(|e| fail(e))
+}
+
+/// Context of the index of the config, which its saves lock for writing only while they last
+fn open_context(matches: &Matches) -> ContextObject {
+	let context = load_context(matches);
+	print_skipped_records(context.get_skipped_records());
+	context
+}
+
+/// Same as `open_context`, the records left out by the loading being left for the command to report
+fn load_context(matches: &Matches) -> ContextObject {
+	let mut context = ContextObject::new(load_config(matches));
+	println!("Loading index...");
+	context.load_index().unwrap_or_else(|e| fail(e));
+	print_warnings(context.get_warnings());
+	context
+}
+
+fn print_warnings(warnings: &[String]) {
+	for warning in warnings {
+		warn(warning);
+	}
+}
+
+fn print_skipped_records(skipped_records: &[SriError]) {
+	for e in skipped_records {
+		warn(&format!("{}, the record is left out", e));
+	}
+}
+
+
+fn index_command(args: &[String]) {
+	let arguments = "DOCUMENTS_LIST|DIRECTORY...";
+	let mut options = common_options();
+	options.optmulti("", "include", "only index the files of the directories matching the glob pattern, '*' matching '/' as well", "GLOB");
+	options.optmulti("", "exclude", "leave out the files and the directories matching the glob pattern", "GLOB");
+	options.optopt("", "symlinks", "'skip' the symbolic links (default), index the links to 'files', or 'follow' them all", "POLICY");
+	options.optopt("", "max-depth", "deepest level of the directories crawled, 1 only indexing the files directly in them", "N");
+	options.optflag("", "hidden", "also crawl the files and directories whose name starts with a dot");
+	options.optflag("w", "watch", "keep indexing the changes of the directory, saving them every 'watch_delay' milliseconds without change, until stopped");
+	options.optopt("r", "report", "file the JSON report of the run is written to, with the outcome and the time of every document", "FILE");
+	options.optopt("", "failures", "file the documents that failed to be indexed are listed in, to be indexed again by a later run", "FILE");
+	let matches = parse_options("index", arguments, &options, args);
+	if matches.free.is_empty() {
+		usage("index", arguments, &options);
+	}
+	let crawler = crawler_from(&matches);
+
+	if matches.opt_present("watch") {
+		if matches.free.len() != 1 || !Path::new(&matches.free[0]).is_dir() {
+			fail(SriError::config("--watch needs a single directory"));
+		}
+		if matches.opt_present("report") || matches.opt_present("failures") {
+			fail(SriError::config("--report and --failures can't be used with --watch"));
+		}
+		let mut context = open_context(&matches);
+		context.watch_directory(&matches.free[0], &crawler).unwrap_or_else(|e| fail(e));
+		return;
+	}
+
+	let mut context = open_context(&matches);
+	println!("Indexing documents...");
+	let mut report = IndexingReport::new();
+	let mut error = None;
+	for path in &matches.free {
+		let path_report = match Path::new(path).is_dir() {
+			true => context.index_directory(path, &crawler),
+			false => context.start_indexing(path),
+		};
+		match path_report {
+			Ok(path_report) => report.append(path_report),
+			Err(e) => {
+				error = Some(e);
+				break;
+			}
+		}
+	}
+	println!("Indexing finished!");
+
+	// The documents indexed before an error are saved too, the report telling what the run did either way
+	println!("Saving index to disk...");
+	match context.save_index() {
+		Ok(()) => println!("Index saved!"),
+		Err(e) => error = error.or(Some(e)),
+	}
+	write_reports(&matches, &report);
+	if let Some(e) = error {
+		fail(e);
+	}
+}
+
+/// Writes the JSON report and the list of the failed documents of an indexing run to the files given to `index`
+fn write_reports(matches: &Matches, report: &IndexingReport) {
+	if let Some(report_filename) = matches.opt_str("report") {
+		let mut file = File::create(&report_filename).unwrap_or_else(|e| fail(SriError::io(&report_filename, e)));
+		writeln!(file, "{}", report.to_json().pretty()).unwrap_or_else(|e| fail(SriError::io(&report_filename, e)));
+		println!("Report written to {}", report_filename);
+	}
+	if let Some(failures_filename) = matches.opt_str("failures") {
+		let mut writer = BufWriter::new(File::create(&failures_filename).unwrap_or_else(|e| fail(SriError::io(&failures_filename, e))));
+		report.write_failures(&mut writer).and_then(|_| writer.flush().map_err(SriError::from)).unwrap_or_else(|e| fail(e.in_file(&failures_filename)));
+		println!("{} failed documents listed in {}", report.get_failures().len(), failures_filename);
+	}
+}
+
+/// Crawler of the directories given to `index`, configured by its options
+fn crawler_from(matches: &Matches) -> Crawler {
+	let mut crawler = Crawler::new();
+	for pattern in matches.opt_strs("include") {
+		crawler.include(&pattern).unwrap_or_else(|e| fail(e));
+	}
+	for pattern in matches.opt_strs("exclude") {
+		crawler.exclude(&pattern).unwrap_or_else(|e| fail(e));
+	}
+	if let Some(symlinks) = matches.opt_str("symlinks") {
+		match SymlinkPolicy::from_name(&symlinks) {
+			Some(symlinks) => crawler.set_symlinks(symlinks),
+			None => fail(SriError::config(format!("Invalid symlink policy '{}', expected 'skip', 'files' or 'follow'", symlinks))),
+		}
+	}
+	if let Some(max_depth) = matches.opt_str("max-depth") {
+		match max_depth.parse::<usize>() {
+			Ok(depth) if depth > 0 => crawler.set_max_depth(Some(depth)),
+			_ => fail(SriError::config(format!("Invalid maximum depth '{}', expected a positive integer", max_depth))),
+		}
+	}
+	crawler.set_hidden(matches.opt_present("hidden"));
+	crawler
+}
+
+fn query_command(args: &[String]) {
+	let mut options = common_options();
+	options.optopt("n", "limit", "maximum amount of results per query (default 10)", "N");
+	options.optopt("o", "output", "results file (default results/<timestamp>.res)", "FILE");
+	options.optflag("j", "jsonl", "write the results as JSON Lines, with the path and the metadata of their documents");
+	options.optmulti("", "shard", "index directory queried instead of the index of the config, as one of several shards prefixing the ids of their results", "[NAME=]DIR");
+	let matches = parse_options("query", "QUERIES_LIST", &options, args);
+	let queries_list_filename = match matches.free.first() {
+		Some(filename) => filename.clone(),
+		None => usage("query", "QUERIES_LIST", &options),
+	};
+	let result_limit = match matches.opt_str("limit") {
+		Some(limit) => {
+			match limit.parse::<usize>() {
+				Ok(limit) => limit,
+				Err(e) => fail(SriError::config(format!("Invalid result limit '{}' ({})", limit, e))),
+			}
+		},
+		None => DEFAULT_RESULT_LIMIT,
+	};
+	let result_filename = match matches.opt_str("output") {
+		Some(result_filename) => result_filename,
+		None => format!("results/{}.res", sri_rs::includes::get_time_millis()),
+	};
+	if let Some(result_folder) = Path::new(&result_filename).parent() {
+		fs::create_dir_all(result_folder).unwrap_or_else(|e| fail(SriError::io(&result_filename, e)));
+	}
+
+	let result_format = match matches.opt_present("jsonl") {
+		true => ResultFormat::JsonLines,
+		false => ResultFormat::Trec,
+	};
+
+	let shards = matches.opt_strs("shard");
+	if shards.is_empty() {
+		let mut context = open_context(&matches);
+		println!("Starting querying...");
+		context.start_querying(&queries_list_filename, &result_filename, result_limit, result_format).unwrap_or_else(|e| fail(e));
+	} else {
+		// Each shard is loaded with its own directory as index, the index of the config is neither loaded nor needed
+		let check = Config::check_template(&load_params(&matches).unwrap_or_else(|e| fail(e)));
+		print_warnings(check.get_warnings());
+		let config = check.into_result().unwrap_or_else(|e| fail(e));
+		println!("Loading {} shards...", shards.len());
+		let mut federation = Federation::open(&config, &shards).unwrap_or_else(|e| fail(e));
+		for &(_, ref context) in federation.get_shards() {
+			print_skipped_records(context.get_skipped_records());
+		}
+		println!("Starting querying...");
+		federation.start_querying(&queries_list_filename, &result_filename, result_limit, result_format).unwrap_or_else(|e| fail(e));
+	}
+	println!("{}", result_filename);
+}
+
+fn stats_command(args: &[String]) {
+	let mut options = common_options();
+	options.optflag("v", "verbose", "also print the descriptors");
+	let matches = parse_options("stats", "", &options, args);
+
+	let context = open_context(&matches);
+	if let Some(format) = context.find_index_format(context.get_config().get_index()) {
+		println!("Format: {}", format.get_name());
+	}
+	if let Some(root) = context.get_collection_root() {
+		println!("Collection root: {} ({})", root.get_path(), root.get_directory());
+	}
+	println!("Images: {} associations, {} descriptors", context.get_images_associations().len(), context.get_images_base().len());
+	println!("Sounds: {} associations, {} descriptors", context.get_sounds_associations().len(), context.get_sounds_base().len());
+	println!("Texts: {} associations, {} descriptors", context.get_texts_associations().len(), context.get_texts_base().len());
+	println!("");
+	context.index_details(matches.opt_present("verbose"));
+}
+
+fn remove_command(args: &[String]) {
+	let mut options = common_options();
+	options.optflag("p", "path", "the arguments are paths of indexed documents instead of ids");
+	let matches = parse_options("remove", "ID... | --path PATH...", &options, args);
+	if matches.free.is_empty() {
+		usage("remove", "ID... | --path PATH...", &options);
+	}
+
+	let mut context = open_context(&matches);
+	for argument in &matches.free {
+		if matches.opt_present("path") {
+			let ids = context.remove_path(argument);
+			if ids.is_empty() {
+				warn(&format!("no document indexed from {}", argument));
+			}
+			for id in ids {
+				println!("Removed {} ({})", id, argument);
+			}
+		} else if context.resolve_id(argument) != argument {
+			let id = String::from(context.resolve_id(argument));
+			if context.remove_document(&id) {
+				println!("Removed {} (formerly {})", id, argument);
+			} else {
+				warn(&format!("no document with id {}, which {} is an alias of", id, argument));
+			}
+		} else if context.remove_document(argument) {
+			println!("Removed {}", argument);
+		} else {
+			warn(&format!("no document with id {}", argument));
+		}
+	}
+	context.save_index().unwrap_or_else(|e| fail(e));
+}
+
+fn export_command(args: &[String]) {
+	let mut options = common_options();
+	options.optopt("o", "output", "directory the index is written to, or file with --jsonl ('-' for the standard output)", "PATH");
+	options.optflag("j", "jsonl", "write the documents as JSON Lines, one object per document");
+	let matches = parse_options("export", "--output PATH", &options, args);
+	let output = match matches.opt_str("output") {
+		Some(output) => output,
+		None => usage("export", "--output PATH", &options),
+	};
+
+	if output == "-" && matches.opt_present("jsonl") {
+		// Nothing but the documents may be written to the standard output
+		let mut context = ContextObject::new(load_config(&matches));
+		context.load_index().unwrap_or_else(|e| fail(e));
+		print_warnings(context.get_warnings());
+		print_skipped_records(context.get_skipped_records());
+		let stdout = io::stdout();
+		let mut writer = stdout.lock();
+		context.export_json_lines(&mut writer).unwrap_or_else(|e| fail(e));
+		return;
+	}
+
+	let context = open_context(&matches);
+	if !matches.opt_present("jsonl") {
+		context.save_index_to(&output).unwrap_or_else(|e| fail(e));
+		println!("Index exported to {}", output);
+	} else {
+		let file = File::create(&output).unwrap_or_else(|e| fail(SriError::io(&output, e)));
+		let mut writer = BufWriter::new(file);
+		let exported = context.export_json_lines(&mut writer).unwrap_or_else(|e| fail(e.in_file(&output)));
+		writer.flush().unwrap_or_else(|e| fail(SriError::io(&output, e)));
+		println!("{} documents exported to {}", exported, output);
+	}
+}
+
+fn import_command(args: &[String]) {
+	let options = common_options();
+	let matches = parse_options("import", "JSONL_FILE...", &options, args);
+	if matches.free.is_empty() {
+		usage("import", "JSONL_FILE...", &options);
+	}
+
+	let mut context = open_context(&matches);
+	for filename in &matches.free {
+		let imported = context.import_json_lines(filename).unwrap_or_else(|e| fail(e));
+		println!("{} documents imported from {}", imported, filename);
+	}
+	context.save_index().unwrap_or_else(|e| fail(e));
+	println!("Index saved!");
+}
+
+fn merge_command(args: &[String]) {
+	let mut options = common_options();
+	options.optflag("v", "verbose", "print the new id of every merged document");
+	let matches = parse_options("merge", "INDEX_DIR...", &options, args);
+	if matches.free.is_empty() {
+		usage("merge", "INDEX_DIR...", &options);
+	}
+
+	let mut context = open_context(&matches);
+	for index_path in &matches.free {
+		let report = context.merge_index(index_path).unwrap_or_else(|e| fail(e));
+		print_skipped_records(report.get_skipped_records());
+		for merged_id in report.get_orphans() {
+			warn(&format!("{} {} has no association, the document is left out", index_path, merged_id));
+		}
+		if matches.opt_present("verbose") {
+			for &(ref merged_id, ref id) in report.get_added() {
+				println!("{} {} => {}", index_path, merged_id, id);
+			}
+			for &(ref merged_id, ref id) in report.get_duplicates() {
+				println!("{} {} duplicates {}", index_path, merged_id, id);
+			}
+		}
+		println!("Merged {}: {} documents added, {} duplicates skipped, {} without association skipped", index_path, report.get_added().len(), report.get_duplicates().len(), report.get_orphans().len());
+	}
+	context.compact().unwrap_or_else(|e| fail(e));
+	println!("Index saved!");
+}
+
+fn reid_command(args: &[String]) {
+	let mut options = common_options();
+	options.optflag("v", "verbose", "print the former and the new id of every renamed document");
+	let matches = parse_options("reid", "", &options, args);
+
+	let mut context = open_context(&matches);
+	if context.get_config().get_id_scheme() == IdScheme::Sequential {
+		fail(SriError::config("Parameter 'id_scheme' has to be 'content' or 'path' to give documents stable ids"));
+	}
+	let mut renamed = 0;
+	for rename in context.assign_stable_ids() {
+		match rename {
+			Ok((id, new_id)) => {
+				if matches.opt_present("verbose") {
+					println!("{} => {}", id, new_id);
+				}
+				renamed += 1;
+			}
+			Err(e) => warn(&format!("{}, the document keeps its id", e)),
+		}
+	}
+	context.save_index().unwrap_or_else(|e| fail(e));
+	println!("{} documents renamed, their former ids are kept as aliases", renamed);
+}
+
+fn relocate_command(args: &[String]) {
+	let mut options = common_options();
+	options.optopt("r", "root", "new collection root, relative to the index directory unless absolute", "DIR");
+	options.optmulti("p", "prefix", "replaces the leading OLD components of the stored paths by NEW", "OLD=NEW");
+	options.optflag("v", "verbose", "print every rewritten path");
+	let matches = parse_options("relocate", "[--root DIR] [--prefix OLD=NEW]...", &options, args);
+	let mut prefixes = Vec::new();
+	for prefix in matches.opt_strs("prefix") {
+		let mut fields = prefix.splitn(2, '=');
+		match (fields.next(), fields.next()) {
+			(Some(old_prefix), Some(new_prefix)) => prefixes.push((String::from(old_prefix), String::from(new_prefix))),
+			_ => fail(SriError::config(format!("Invalid prefix '{}', expected OLD=NEW", prefix))),
+		}
+	}
+	let root = matches.opt_str("root");
+	if root.is_none() && prefixes.is_empty() {
+		usage("relocate", "[--root DIR] [--prefix OLD=NEW]...", &options);
+	}
+
+	let mut context = open_context(&matches);
+	let rewritten = context.relocate(root.as_ref().map(String::as_ref), &prefixes);
+	if matches.opt_present("verbose") {
+		for &(ref id, ref former_path, ref path) in &rewritten {
+			println!("{} {} => {}", id, former_path, path);
+		}
+	}
+	context.save_index().unwrap_or_else(|e| fail(e));
+	if let Some(root) = context.get_collection_root() {
+		println!("Collection root: {} ({})", root.get_path(), root.get_directory());
+	}
+	println!("{} paths rewritten", rewritten.len());
+
+	let missing = context.find_missing_documents();
+	for &(ref id, ref path) in &missing {
+		println!("Missing file: {} {}", id, path);
+	}
+	if !missing.is_empty() {
+		warn(&format!("the files of {} documents can't be found under the collection root", missing.len()));
+	}
+}
+
+fn convert_command(args: &[String]) {
+	let mut options = common_options();
+	options.optopt("t", "to", "format to convert the index to, 'text' or 'binary'", "FORMAT");
+	options.optopt("o", "output", "directory the converted index is written to (default: in place)", "DIR");
+	let matches = parse_options("convert", "--to FORMAT", &options, args);
+	let format = match matches.opt_str("to").as_ref().map(String::as_ref).and_then(IndexFormat::from_name) {
+		Some(format) => format,
+		None => usage("convert", "--to FORMAT", &options),
+	};
+
+	let context = open_context(&matches);
+	let output = matches.opt_str("output").unwrap_or_else(|| String::from(context.get_config().get_index()));
+	context.save_index_as(&output, format).unwrap_or_else(|e| fail(e));
+	println!("Index converted to the {} format in {}", format.get_name(), output);
+}
+
+fn compact_command(args: &[String]) {
+	let options = common_options();
+	let matches = parse_options("compact", "", &options, args);
+
+	let mut context = open_context(&matches);
+	context.compact().unwrap_or_else(|e| fail(e));
+	println!("Index compacted");
+}
+
+fn verify_command(args: &[String]) {
+	let mut options = common_options();
+	options.optflag("", "repair", "remove the orphans, the duplicates and the documents with wrong histograms, then compact the index");
+	let matches = parse_options("verify", "", &options, args);
+
+	let repair = matches.opt_present("repair");
+	let mut context = load_context(&matches);
+	let problems = context.verify();
+	for problem in &problems {
+		println!("{}", problem);
+	}
+	if problems.is_empty() {
+		println!("Index is consistent");
+		return;
+	}
+	if !repair {
+		process::exit(1);
+	}
+	let remaining = context.repair().unwrap_or_else(|e| fail(e));
+	println!("Index repaired, {} of {} problems fixed", problems.len() - remaining.len(), problems.len());
+	for problem in &remaining {
+		println!("Left: {}", problem);
+	}
+	if !remaining.is_empty() {
+		process::exit(1);
+	}
+}
+
+fn snapshot_command(args: &[String]) {
+	let arguments = "list | diff FROM [TO] | rollback NAME";
+	let options = common_options();
+	let matches = parse_options("snapshot", arguments, &options, args);
+	let free = &matches.free;
+	match (free.first().map(String::as_ref), free.len()) {
+		(Some("list"), 1) => {
+			let config = load_config(&matches);
+			let snapshots = snapshot::list_snapshots(config.get_index()).unwrap_or_else(|e| fail(e));
+			for snapshot in &snapshots {
+				let manifest = snapshot.get_manifest();
+				println!("{} {} format, {} segments", snapshot.get_name(), manifest.get_format().get_name(), manifest.get_segments().len());
+			}
+			println!("{} snapshots", snapshots.len());
+		}
+		(Some("diff"), 2) | (Some("diff"), 3) => {
+			let mut older = ContextObject::new(load_config(&matches));
+			older.load_snapshot(&free[1]).unwrap_or_else(|e| fail(e));
+			let mut newer = ContextObject::new(load_config(&matches));
+			match free.get(2) {
+				Some(to) => newer.load_snapshot(to),
+				None => newer.load_index(),
+			}.unwrap_or_else(|e| fail(e));
+			print_warnings(older.get_warnings());
+			print_warnings(newer.get_warnings());
+			print_skipped_records(older.get_skipped_records());
+			print_skipped_records(newer.get_skipped_records());
+
+			let diff = older.diff(&newer);
+			for &(ref id, ref path) in diff.get_added() {
+				println!("+ {} {}", id, path);
+			}
+			for &(ref id, ref path) in diff.get_removed() {
+				println!("- {} {}", id, path);
+			}
+			for &(ref id, ref path, ref changes) in diff.get_changed() {
+				println!("~ {} {} ({})", id, path, changes.join(", "));
+			}
+			println!("{} documents added, {} removed, {} changed", diff.get_added().len(), diff.get_removed().len(), diff.get_changed().len());
+		}
+		(Some("rollback"), 2) => {
+			let mut context = ContextObject::new(load_config(&matches));
+			context.rollback(&free[1]).unwrap_or_else(|e| fail(e));
+			println!("Index rolled back to {}", free[1]);
+		}
+		_ => usage("snapshot", arguments, &options),
+	}
+}
+
+fn config_command(args: &[String]) {
+	let options = common_options();
+	let matches = parse_options("config", "check", &options, args);
+	match matches.free.first().map(String::as_ref) {
+		Some("check") => (),
+		_ => usage("config", "check", &options),
+	}
+
+	let params = load_params(&matches).unwrap_or_else(|e| fail(e));
+	let check = Config::check(&params);
+	println!("Effective config: ");
+	for (property, value) in check.get_effective_config().to_params() {
+		println!("{}=>{}", property, value);
+	}
+	println!("");
+
+	for warning in check.get_warnings() {
+		println!("Warning: {}", warning);
+	}
+	for error in check.get_errors() {
+		println!("Error: {}", error);
+	}
+
+	if check.get_errors().is_empty() {
+		println!("Config is valid");
+	} else {
+		process::exit(1);
+	}
+}
+
+
+fn main() {
+	let args: Vec<String> = env::args().collect();
+	let command = match args.get(1) {
+		Some(command) => command.clone(),
+		None => {
+			writeln!(&mut io::stderr(), "Usage: sri COMMAND [options]\n\n{}", COMMANDS).unwrap();
+			process::exit(2);
+		},
+	};
+	let command_args = &args[2..];
+
+	match command.as_ref() {
+		"index" => index_command(command_args),
+		"query" => query_command(command_args),
+		"stats" => stats_command(command_args),
+		"remove" => remove_command(command_args),
+		"export" => export_command(command_args),
+		"import" => import_command(command_args),
+		"merge" => merge_command(command_args),
+		"reid" => reid_command(command_args),
+		"relocate" => relocate_command(command_args),
+		"convert" => convert_command(command_args),
+		"compact" => compact_command(command_args),
+		"snapshot" => snapshot_command(command_args),
+		"verify" => verify_command(command_args),
+		"config" => config_command(command_args),
+		"help" | "-h" | "--help" => println!("Usage: sri COMMAND [options]\n\n{}", COMMANDS),
+		_ => {
+			writeln!(&mut io::stderr(), "Unknown command '{}'\n\n{}", command, COMMANDS).unwrap();
+			process::exit(2);
+		},
+	}
+}